@@ -0,0 +1,366 @@
+use crate::usage::UsageLedger;
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Spending limits for one provider, configured under `[budgets.<provider>]`.
+/// "Session" limits apply from server start; daily limits reset at 00:00 UTC.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BudgetConfig {
+    pub daily_cost_usd: Option<f64>,
+    pub daily_tokens: Option<u64>,
+    pub session_cost_usd: Option<f64>,
+    pub session_tokens: Option<u64>,
+    /// Cheaper model to route to instead of failing when a limit would be exceeded.
+    pub fallback_model: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    pub provider: String,
+    /// "daily" or "session"
+    pub scope: &'static str,
+    /// "cost_usd" or "tokens"
+    pub limit_kind: &'static str,
+    pub limit: f64,
+    pub used: f64,
+    /// `None` when the model has no known price, so its cost cannot be
+    /// held against a cost limit
+    pub estimated: Option<f64>,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.estimated {
+            Some(estimated) => write!(
+                f,
+                "{} {} {} budget exceeded: {:.4} used + ~{:.4} estimated > {:.4} limit",
+                self.provider, self.scope, self.limit_kind, self.used, estimated, self.limit
+            ),
+            None => write!(
+                f,
+                "{} {} {} budget cannot be enforced: the model has no known price; \
+                 set input_price_per_mtok and output_price_per_mtok in models.toml",
+                self.provider, self.scope, self.limit_kind
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+impl QuotaExceeded {
+    /// JSON-RPC error code, next to the provider error codes (-32001..-32008).
+    pub fn code(&self) -> i32 {
+        -32009
+    }
+
+    pub fn data(&self) -> serde_json::Value {
+        serde_json::json!({
+            "kind": "quota_exceeded",
+            "provider": self.provider,
+            "scope": self.scope,
+            "limit_kind": self.limit_kind,
+            "limit": self.limit,
+            "used": self.used,
+            "estimated": self.estimated,
+            "retryable": false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct Spend {
+    tokens: u64,
+    cost_usd: f64,
+}
+
+impl Spend {
+    fn add(&mut self, tokens: u64, cost_usd: f64) {
+        self.tokens += tokens;
+        self.cost_usd += cost_usd;
+    }
+
+    fn sub(&mut self, tokens: u64, cost_usd: f64) {
+        self.tokens = self.tokens.saturating_sub(tokens);
+        self.cost_usd = (self.cost_usd - cost_usd).max(0.0);
+    }
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    day: NaiveDate,
+    daily: BTreeMap<String, Spend>,
+    session: BTreeMap<String, Spend>,
+}
+
+impl BudgetState {
+    fn add(&mut self, provider: &str, spend: Spend) {
+        self.daily
+            .entry(provider.to_string())
+            .or_default()
+            .add(spend.tokens, spend.cost_usd);
+        self.session
+            .entry(provider.to_string())
+            .or_default()
+            .add(spend.tokens, spend.cost_usd);
+    }
+}
+
+/// Budget held for one request by [`BudgetTracker::reserve`]. Recording it
+/// replaces the estimate with the actual usage; dropping it unrecorded
+/// gives the estimate back.
+#[must_use = "dropping a reservation releases it"]
+#[derive(Debug)]
+pub struct Reservation {
+    tracker: Arc<BudgetTracker>,
+    provider: String,
+    day: NaiveDate,
+    held: Spend,
+    settled: bool,
+}
+
+impl Reservation {
+    /// Replace the estimate with the actual usage of the request.
+    pub fn record(mut self, tokens: u64, cost_usd: f64) {
+        self.tracker.settle(&self, Some(Spend { tokens, cost_usd }));
+        self.settled = true;
+    }
+
+    /// Count the estimate as spent, for a request whose usage is unknown.
+    pub fn keep(mut self) {
+        self.settled = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.tracker.settle(self, None);
+        }
+    }
+}
+
+/// Tracks spend per provider for the current day and server session and
+/// checks estimated requests against the configured limits.
+#[derive(Debug)]
+pub struct BudgetTracker {
+    limits: BTreeMap<String, BudgetConfig>,
+    state: Mutex<BudgetState>,
+}
+
+impl BudgetTracker {
+    /// Create a tracker, seeding today's spend from the usage ledger so that
+    /// restarting the server does not reset daily limits.
+    pub fn new(limits: BTreeMap<String, BudgetConfig>, ledger: &UsageLedger) -> Self {
+        let today = Utc::now().date_naive();
+        let mut daily: BTreeMap<String, Spend> = BTreeMap::new();
+        match ledger.read(Some(today), Some(today)) {
+            Ok(records) => {
+                for record in records {
+                    daily.entry(record.provider).or_default().add(
+                        record.input_tokens as u64 + record.output_tokens as u64,
+                        record.cost_usd.unwrap_or(0.0),
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Failed to load today's usage for budgets: {}", e),
+        }
+
+        Self {
+            limits,
+            state: Mutex::new(BudgetState {
+                day: today,
+                daily,
+                session: BTreeMap::new(),
+            }),
+        }
+    }
+
+    pub fn limits(&self, provider: &str) -> Option<&BudgetConfig> {
+        self.limits.get(provider)
+    }
+
+    /// Hold a request estimated at `tokens` / `cost_usd` against every
+    /// configured limit for `provider`, failing if it does not fit. The
+    /// estimate counts as spent until the reservation is recorded with the
+    /// actual usage or dropped, so concurrent requests cannot all pass the
+    /// check before any of them is recorded. `cost_usd` is `None` for
+    /// models without a known price, which never fit a cost limit.
+    pub fn reserve(
+        self: &Arc<Self>,
+        provider: &str,
+        tokens: u64,
+        cost_usd: Option<f64>,
+    ) -> Result<Reservation, QuotaExceeded> {
+        let mut state = self.lock();
+        if let Some(limits) = self.limits.get(provider) {
+            let daily = state.daily.get(provider).copied().unwrap_or_default();
+            let session = state.session.get(provider).copied().unwrap_or_default();
+            let checks = [
+                ("daily", daily, limits.daily_cost_usd, limits.daily_tokens),
+                (
+                    "session",
+                    session,
+                    limits.session_cost_usd,
+                    limits.session_tokens,
+                ),
+            ];
+            for (scope, spent, cost_limit, token_limit) in checks {
+                if let Some(limit) = cost_limit {
+                    if cost_usd.is_none_or(|cost| spent.cost_usd + cost > limit) {
+                        return Err(QuotaExceeded {
+                            provider: provider.to_string(),
+                            scope,
+                            limit_kind: "cost_usd",
+                            limit,
+                            used: spent.cost_usd,
+                            estimated: cost_usd,
+                        });
+                    }
+                }
+                if let Some(limit) = token_limit {
+                    if spent.tokens + tokens > limit {
+                        return Err(QuotaExceeded {
+                            provider: provider.to_string(),
+                            scope,
+                            limit_kind: "tokens",
+                            limit: limit as f64,
+                            used: spent.tokens as f64,
+                            estimated: Some(tokens as f64),
+                        });
+                    }
+                }
+            }
+        }
+
+        let held = Spend {
+            tokens,
+            cost_usd: cost_usd.unwrap_or(0.0),
+        };
+        state.add(provider, held);
+        Ok(Reservation {
+            tracker: self.clone(),
+            provider: provider.to_string(),
+            day: state.day,
+            held,
+            settled: false,
+        })
+    }
+
+    /// Give back what `reservation` held, adding the actual usage in the
+    /// same step.
+    fn settle(&self, reservation: &Reservation, actual: Option<Spend>) {
+        let mut state = self.lock();
+        let held = reservation.held;
+        // Yesterday's hold went when the daily counters were reset
+        if state.day == reservation.day {
+            if let Some(daily) = state.daily.get_mut(&reservation.provider) {
+                daily.sub(held.tokens, held.cost_usd);
+            }
+        }
+        if let Some(session) = state.session.get_mut(&reservation.provider) {
+            session.sub(held.tokens, held.cost_usd);
+        }
+        if let Some(actual) = actual {
+            state.add(&reservation.provider, actual);
+        }
+    }
+
+    /// Limits, spend and remaining budget for every provider in `providers`.
+    pub fn status(&self, providers: &[&str]) -> serde_json::Value {
+        let state = self.lock();
+        let mut result = serde_json::Map::new();
+        for &provider in providers {
+            let limits = self.limits.get(provider).cloned().unwrap_or_default();
+            let daily = state.daily.get(provider).copied().unwrap_or_default();
+            let session = state.session.get(provider).copied().unwrap_or_default();
+            let scope = |spent: Spend, cost_limit: Option<f64>, token_limit: Option<u64>| {
+                serde_json::json!({
+                    "used": spent,
+                    "limits": { "cost_usd": cost_limit, "tokens": token_limit },
+                    "remaining": {
+                        "cost_usd": cost_limit.map(|l| (l - spent.cost_usd).max(0.0)),
+                        "tokens": token_limit.map(|l| l.saturating_sub(spent.tokens)),
+                    },
+                })
+            };
+            result.insert(
+                provider.to_string(),
+                serde_json::json!({
+                    "daily": scope(daily, limits.daily_cost_usd, limits.daily_tokens),
+                    "session": scope(session, limits.session_cost_usd, limits.session_tokens),
+                    "fallback_model": limits.fallback_model,
+                }),
+            );
+        }
+        serde_json::json!({
+            "day": state.day,
+            "providers": result,
+        })
+    }
+
+    /// Lock the state, resetting daily counters when the UTC day rolls over.
+    fn lock(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let today = Utc::now().date_naive();
+        if state.day != today {
+            state.day = today;
+            state.daily.clear();
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_daily_and_session_limits() {
+        let temp_dir = TempDir::new().unwrap();
+        let ledger = UsageLedger::open(Some(&temp_dir.path().join("usage.jsonl")));
+        let mut limits = BTreeMap::new();
+        limits.insert(
+            "openai".to_string(),
+            BudgetConfig {
+                daily_cost_usd: Some(1.0),
+                session_tokens: Some(10_000),
+                ..Default::default()
+            },
+        );
+        let tracker = Arc::new(BudgetTracker::new(limits, &ledger));
+
+        let held = tracker.reserve("openai", 5_000, Some(0.5)).unwrap();
+        // The hold counts until it is recorded or dropped
+        assert!(tracker.reserve("openai", 5_000, Some(0.6)).is_err());
+        drop(held);
+        assert!(tracker.reserve("anthropic", 1_000_000, Some(100.0)).is_ok());
+
+        tracker
+            .reserve("openai", 5_000, Some(0.5))
+            .unwrap()
+            .record(8_000, 0.6);
+        let err = tracker.reserve("openai", 1_000, Some(0.5)).unwrap_err();
+        assert_eq!((err.scope, err.limit_kind), ("daily", "cost_usd"));
+
+        let err = tracker.reserve("openai", 3_000, Some(0.1)).unwrap_err();
+        assert_eq!((err.scope, err.limit_kind), ("session", "tokens"));
+        assert_eq!(err.data()["kind"], "quota_exceeded");
+
+        // Without a price, a request never fits a cost limit
+        let err = tracker.reserve("openai", 10, None).unwrap_err();
+        assert_eq!((err.scope, err.estimated), ("daily", None));
+        assert!(tracker.reserve("anthropic", 10, None).is_ok());
+
+        let status = tracker.status(&["openai"]);
+        assert_eq!(
+            status["providers"]["openai"]["session"]["remaining"]["tokens"],
+            2_000
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use crate::budget::BudgetConfig;
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Server configuration, read from `config.toml` in the platform config
+/// directory (e.g. `~/.config/multi-model-mcp/config.toml`) and overridden
+/// by `MULTI_MODEL_MCP_*` environment variables.
+///
+/// ```toml
+/// [anthropic]
+/// default_model = "claude-sonnet-4-5-20250929"
+/// summary_model = "claude-haiku-4-5-20251001"
+///
+/// [anthropic.http]
+/// timeout_secs = 300
+/// proxy = "http://proxy.corp.example:3128"
+/// ca_bundle = "/etc/ssl/certs/corp-root.pem"
+///
+/// [anthropic.http.headers]
+/// X-Gateway-Tenant = "platform"
+///
+/// [budgets.anthropic]
+/// daily_cost_usd = 20.0
+/// fallback_model = "claude-haiku-4-5-20251001"
+///
+/// [local_map]
+/// max_timeout_secs = 60
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Model metadata override file (default: `<config dir>/multi-model-mcp/models.toml`).
+    pub models_file: Option<PathBuf>,
+    /// Usage ledger file (default: `<data dir>/multi-model-mcp/usage.jsonl`).
+    pub usage_ledger: Option<PathBuf>,
+    /// Session store; each workspace gets a directory below it (default:
+    /// `<data dir>/multi-model-mcp/sessions`).
+    pub sessions_dir: Option<PathBuf>,
+    /// Spending limits keyed by provider name.
+    pub budgets: BTreeMap<String, BudgetConfig>,
+    pub anthropic: ProviderConfig,
+    pub openai: ProviderConfig,
+    pub local_map: LocalMapConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Model used when a tool call does not name one.
+    pub default_model: Option<String>,
+    /// Cheap model used to summarize context that does not fit the window.
+    pub summary_model: Option<String>,
+    /// How long a fetched model catalog is reused before querying the API again.
+    pub models_cache_ttl_secs: u64,
+    pub http: HttpConfig,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            default_model: None,
+            summary_model: None,
+            models_cache_ttl_secs: 24 * 60 * 60,
+            http: HttpConfig::default(),
+        }
+    }
+}
+
+/// Upper bounds for the limits a `local_map` call may ask for.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalMapConfig {
+    pub max_timeout_secs: u64,
+    pub max_entries: usize,
+}
+
+impl Default for LocalMapConfig {
+    fn default() -> Self {
+        Self {
+            max_timeout_secs: 30,
+            max_entries: 50_000,
+        }
+    }
+}
+
+impl LocalMapConfig {
+    /// Refuse limits that would make every `local_map` call fail.
+    fn validate(&self) -> Result<()> {
+        if self.max_timeout_secs == 0 {
+            bail!("local_map.max_timeout_secs must be at least 1");
+        }
+        if self.max_entries == 0 {
+            bail!("local_map.max_entries must be at least 1");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Total request timeout, including reading the response body.
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// Proxy URL applied to all requests (`http://`, `https://` or `socks5://`).
+    /// When unset, the standard `HTTPS_PROXY`/`HTTP_PROXY` variables apply.
+    pub proxy: Option<String>,
+    /// PEM file with one or more additional root certificates to trust.
+    pub ca_bundle: Option<PathBuf>,
+    /// Extra headers sent with every request (e.g. for an API gateway).
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+            proxy: None,
+            ca_bundle: None,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration from `path`, or from the default location when
+    /// `path` is `None`. A missing default file is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os("MULTI_MODEL_MCP_CONFIG").map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => match Self::default_path() {
+                Some(path) if path.exists() => Self::from_file(&path)?,
+                _ => Self::default(),
+            },
+        };
+
+        config.anthropic.apply_env("ANTHROPIC")?;
+        config.openai.apply_env("OPENAI")?;
+        config.local_map.validate()?;
+        Ok(config)
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("multi-model-mcp").join("config.toml"))
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        tracing::info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+}
+
+impl ProviderConfig {
+    /// Apply `MULTI_MODEL_MCP_<PROVIDER>_*` overrides on top of the file values.
+    fn apply_env(&mut self, provider: &str) -> Result<()> {
+        let var = |name: &str| env::var(format!("MULTI_MODEL_MCP_{}_{}", provider, name)).ok();
+        let parse_secs = |name: &str, value: String| -> Result<u64> {
+            value.trim().parse().with_context(|| {
+                format!(
+                    "MULTI_MODEL_MCP_{}_{} must be a number of seconds",
+                    provider, name
+                )
+            })
+        };
+
+        if let Some(value) = var("DEFAULT_MODEL") {
+            self.default_model = Some(value);
+        }
+        if let Some(value) = var("SUMMARY_MODEL") {
+            self.summary_model = Some(value);
+        }
+        if let Some(value) = var("TIMEOUT_SECS") {
+            self.http.timeout_secs = parse_secs("TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("CONNECT_TIMEOUT_SECS") {
+            self.http.connect_timeout_secs = parse_secs("CONNECT_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("PROXY") {
+            self.http.proxy = Some(value);
+        }
+        if let Some(value) = var("CA_BUNDLE") {
+            self.http.ca_bundle = Some(PathBuf::from(value));
+        }
+        // Comma-separated `Name=value` pairs
+        if let Some(value) = var("HEADERS") {
+            for pair in value.split(',').filter(|p| !p.trim().is_empty()) {
+                let (name, value) = pair.split_once('=').with_context(|| {
+                    format!(
+                        "MULTI_MODEL_MCP_{}_HEADERS entries must be Name=value, got '{}'",
+                        provider, pair
+                    )
+                })?;
+                self.http
+                    .headers
+                    .insert(name.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_provider_http_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [openai.http]
+            timeout_secs = 300
+            proxy = "http://proxy.local:3128"
+
+            [openai.http.headers]
+            X-Gateway = "team-a"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.openai.http.timeout_secs, 300);
+        assert_eq!(config.openai.http.connect_timeout_secs, 10);
+        assert_eq!(
+            config.openai.http.proxy.as_deref(),
+            Some("http://proxy.local:3128")
+        );
+        assert_eq!(config.openai.http.headers["X-Gateway"], "team-a");
+        assert_eq!(config.anthropic.http.timeout_secs, 30);
+    }
+
+    #[test]
+    fn test_validate_local_map_limits() {
+        assert!(LocalMapConfig::default().validate().is_ok());
+        let config: Config = toml::from_str("[local_map]\nmax_timeout_secs = 0\n").unwrap();
+        assert!(config.local_map.validate().is_err());
+        let config: Config = toml::from_str("[local_map]\nmax_entries = 0\n").unwrap();
+        assert!(config.local_map.validate().is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_keys() {
+        let result = toml::from_str::<Config>("[anthropic.http]\ntimeout = 5\n");
+        assert!(result.is_err());
+    }
+}
@@ -1,12 +1,26 @@
+
 mod auth;
+mod budget;
+mod config;
 mod providers;
+mod schemas;
 mod tools;
+mod usage;
 
 use anyhow::{Context, Result};
+use budget::QuotaExceeded;
 use clap::Parser;
-use providers::{anthropic::AnthropicProvider, openai::OpenAIProvider, ProviderType};
+use providers::{
+    anthropic::AnthropicProvider, openai::OpenAIProvider, ModelRegistry, ProviderError,
+    ProviderType,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio_util::sync::CancellationToken;
+use tools::session::SessionStore;
 use tools::{ToolExecutor, ToolRequest};
 use tracing::{error, info};
 
@@ -17,6 +31,10 @@ struct Args {
     /// Enable debug logging
     #[arg(short, long)]
     debug: bool,
+
+    /// Path to the configuration file (default: <config dir>/multi-model-mcp/config.toml)
+    #[arg(short, long)]
+    config: Option<PathBuf>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,6 +59,35 @@ struct JsonRpcResponse {
 struct JsonRpcError {
     code: i32,
     message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    /// Map a tool failure to a JSON-RPC error, preserving the error class
+    /// (code + `data`) of provider and quota errors found in the error chain.
+    fn from_tool_error(err: &anyhow::Error) -> Self {
+        let message = format!("Tool execution failed: {}", err);
+        if let Some(provider_err) = err.chain().find_map(|e| e.downcast_ref::<ProviderError>()) {
+            return Self {
+                code: provider_err.code(),
+                message,
+                data: Some(provider_err.data()),
+            };
+        }
+        if let Some(quota_err) = err.chain().find_map(|e| e.downcast_ref::<QuotaExceeded>()) {
+            return Self {
+                code: quota_err.code(),
+                message,
+                data: Some(quota_err.data()),
+            };
+        }
+        Self {
+            code: -32603,
+            message,
+            data: None,
+        }
+    }
 }
 
 #[tokio::main]
@@ -56,6 +103,8 @@ async fn main() -> Result<()> {
 
     info!("Starting Multi-Model MCP Server");
 
+    let config = config::Config::load(args.config.as_deref())?;
+
     // Load credentials
     let creds = auth::Credentials::load()
         .context("Failed to load credentials. Please check your keychain or environment variables.")?;
@@ -64,17 +113,25 @@ async fn main() -> Result<()> {
     let mut providers = Vec::new();
 
     if let Some(anthropic_token) = creds.anthropic_token {
-        info!("Anthropic provider initialized");
-        providers.push(ProviderType::Anthropic(AnthropicProvider::new(
-            anthropic_token,
-        )));
+        match AnthropicProvider::new(anthropic_token, &config.anthropic) {
+            Ok(provider) => {
+                info!("Anthropic provider initialized");
+                providers.push(ProviderType::Anthropic(provider));
+            }
+            Err(e) => error!("Failed to initialize Anthropic provider: {:#}", e),
+        }
     } else {
         error!("No Anthropic credentials found");
     }
 
     if let Some(openai_token) = creds.openai_token {
-        info!("OpenAI provider initialized");
-        providers.push(ProviderType::OpenAI(OpenAIProvider::new(openai_token)));
+        match OpenAIProvider::new(openai_token, &config.openai) {
+            Ok(provider) => {
+                info!("OpenAI provider initialized");
+                providers.push(ProviderType::OpenAI(provider));
+            }
+            Err(e) => error!("Failed to initialize OpenAI provider: {:#}", e),
+        }
     } else {
         error!("No OpenAI credentials found");
     }
@@ -83,31 +140,39 @@ async fn main() -> Result<()> {
         anyhow::bail!("No providers configured. Please set up authentication credentials.");
     }
 
-    let executor = ToolExecutor::new(providers);
+    let registry = ModelRegistry::load(config.models_file.as_deref())?;
+    let ledger = usage::UsageLedger::open(config.usage_ledger.as_deref());
+    let budget = budget::BudgetTracker::new(config.budgets.clone(), &ledger);
+    let sessions = SessionStore::open(config.sessions_dir.as_deref());
+    let executor = ToolExecutor::new(
+        providers,
+        registry,
+        ledger,
+        budget,
+        sessions,
+        config.local_map.clone(),
+    );
 
     info!("MCP Server ready. Listening on stdin...");
 
-    // Main server loop - read from stdin with timeout, write to stdout
-    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    // Main server loop - requests are handled one at a time, while stdin is
+    // read by its own task so that a cancellation can stop the current one
+    use tokio::io::AsyncWriteExt;
     use tokio::time::{timeout, Duration};
 
-    let stdin = tokio::io::stdin();
-    let mut reader = BufReader::new(stdin);
+    let in_flight = InFlight::default();
+    let (line_tx, mut line_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(read_requests(line_tx, in_flight.clone()));
     let mut stdout = tokio::io::stdout();
-    let mut line = String::new();
 
     loop {
-        line.clear();
-
-        // Read with 60 second timeout to detect dead clients
-        match timeout(Duration::from_secs(60), reader.read_line(&mut line)).await {
-            Ok(Ok(0)) => {
-                // EOF - client disconnected
-                info!("Client disconnected (EOF), shutting down gracefully");
+        // Wake up every 60 seconds to detect dead clients
+        match timeout(Duration::from_secs(60), line_rx.recv()).await {
+            Ok(None) => {
+                // The reader hit EOF or an I/O error and has logged it
                 break;
             }
-            Ok(Ok(_)) => {
-                // Successfully read a line
+            Ok(Some((line, cancel))) => {
                 if line.trim().is_empty() {
                     continue;
                 }
@@ -123,6 +188,7 @@ async fn main() -> Result<()> {
                             error: Some(JsonRpcError {
                                 code: -32700,
                                 message: format!("Parse error: {}", e),
+                                data: None,
                             }),
                         };
                         let response_json = serde_json::to_string(&error_response)?;
@@ -133,17 +199,33 @@ async fn main() -> Result<()> {
                     }
                 };
 
-                let response = handle_request(request, &executor).await;
-                let response_json = serde_json::to_string(&response)?;
-                stdout.write_all(response_json.as_bytes()).await?;
-                stdout.write_all(b"\n").await?;
+                let id = request.id.as_ref().map(|id| id.to_string());
+                // Dropping the handler on cancellation also stops directory
+                // walks it started; model calls already sent finish in the
+                // background so that their usage is recorded
+                let response = tokio::select! {
+                    response = handle_request(request, &executor) => Some(response),
+                    _ = cancel.cancelled() => None,
+                };
+                if let Some(id) = &id {
+                    in_flight.lock().remove(id);
+                }
+                match response {
+                    Some(response) => {
+                        let response_json = serde_json::to_string(&response)?;
+                        stdout.write_all(response_json.as_bytes()).await?;
+                        stdout.write_all(b"\n").await?;
+                    }
+                    // No response is sent for a cancelled request
+                    None => info!("Request {} cancelled", id.unwrap_or_default()),
+                }
+                // Resource updates found while handling the request
+                for notification in executor.subscriptions.take() {
+                    stdout.write_all(notification.to_string().as_bytes()).await?;
+                    stdout.write_all(b"\n").await?;
+                }
                 stdout.flush().await?;
             }
-            Ok(Err(e)) => {
-                // I/O error
-                error!("Error reading from stdin: {}, shutting down", e);
-                break;
-            }
             Err(_) => {
                 // Timeout - no input for 60 seconds
                 // Check if client is still alive by continuing to read
@@ -158,6 +240,64 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Cancellation tokens of the requests read but not yet answered, keyed by
+/// their JSON-RPC id.
+#[derive(Clone, Default)]
+struct InFlight(Arc<Mutex<HashMap<String, CancellationToken>>>);
+
+impl InFlight {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CancellationToken>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Forward the lines read from stdin, each with the token that cancels it.
+/// `notifications/cancelled` is handled here rather than forwarded, since
+/// the main loop is busy with the request it cancels.
+async fn read_requests(
+    lines: tokio::sync::mpsc::UnboundedSender<(String, CancellationToken)>,
+    in_flight: InFlight,
+) {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    let mut reader = BufReader::new(tokio::io::stdin());
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) => {
+                info!("Client disconnected (EOF), shutting down gracefully");
+                break;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Error reading from stdin: {}, shutting down", e);
+                break;
+            }
+        }
+
+        let cancel = CancellationToken::new();
+        match serde_json::from_str::<JsonRpcRequest>(&line) {
+            Ok(request) if request.method == "notifications/cancelled" => {
+                let params = request.params.unwrap_or_default();
+                let id = params["requestId"].to_string();
+                if let Some(token) = in_flight.lock().get(&id) {
+                    info!("Cancelling request {}", id);
+                    token.cancel();
+                }
+                continue;
+            }
+            Ok(JsonRpcRequest { id: Some(id), .. }) => {
+                in_flight.lock().insert(id.to_string(), cancel.clone());
+            }
+            _ => {}
+        }
+        if lines.send((line.clone(), cancel)).is_err() {
+            break;
+        }
+    }
+}
+
 async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> JsonRpcResponse {
     info!("Handling request: {}", request.method);
 
@@ -174,6 +314,10 @@ async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> Jso
                 "capabilities": {
                     "tools": {
                         "listChanged": false,
+                    },
+                    "resources": {
+                        "subscribe": true,
+                        "listChanged": false,
                     }
                 }
             })),
@@ -183,114 +327,7 @@ async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> Jso
             jsonrpc: "2.0".to_string(),
             id: request.id,
             result: Some(serde_json::json!({
-                "tools": [
-                    {
-                        "name": "generate_code",
-                        "description": "Generate code based on a prompt",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "prompt": { "type": "string", "description": "Code generation prompt" },
-                                "language": { "type": "string", "description": "Programming language" },
-                                "context": { "type": "array", "items": { "type": "string" } },
-                                "model": { "type": "string", "description": "Specific model to use" }
-                            },
-                            "required": ["prompt"]
-                        }
-                    },
-                    {
-                        "name": "review_code",
-                        "description": "Review code for issues and improvements",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "code": { "type": "string", "description": "Code to review" },
-                                "language": { "type": "string", "description": "Programming language" },
-                                "focus": { "type": "array", "items": { "type": "string" }, "description": "Areas to focus on (security, performance, style)" },
-                                "model": { "type": "string", "description": "Specific model to use" }
-                            },
-                            "required": ["code"]
-                        }
-                    },
-                    {
-                        "name": "switch_model",
-                        "description": "Switch between AI providers (anthropic/openai)",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "provider": { "type": "string", "description": "Provider name: anthropic or openai" },
-                                "model": { "type": "string", "description": "Specific model (optional)" }
-                            },
-                            "required": ["provider"]
-                        }
-                    },
-                    {
-                        "name": "list_models",
-                        "description": "List all available models from all providers",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {}
-                        }
-                    },
-                    {
-                        "name": "add_context",
-                        "description": "Add context (files, notes, metadata) to the conversation",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "type": { "type": "string", "enum": ["file", "note", "metadata"] },
-                                "path": { "type": "string" },
-                                "content": { "type": "string" },
-                                "note": { "type": "string" },
-                                "key": { "type": "string" },
-                                "value": { "type": "string" }
-                            },
-                            "required": ["type"]
-                        }
-                    },
-                    {
-                        "name": "get_context",
-                        "description": "Get all context for the current conversation",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {}
-                        }
-                    },
-                    {
-                        "name": "clear_context",
-                        "description": "Clear all conversation context",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {}
-                        }
-                    },
-                    {
-                        "name": "local_map",
-                        "description": "Enumerate files and directories from a starting path with depth control",
-                        "inputSchema": {
-                            "type": "object",
-                            "properties": {
-                                "path": {
-                                    "type": "string",
-                                    "description": "Starting path (default: current directory)",
-                                    "default": "."
-                                },
-                                "depth": {
-                                    "type": "integer",
-                                    "description": "Maximum depth to traverse (0-6, default: 2)",
-                                    "minimum": 0,
-                                    "maximum": 6,
-                                    "default": 2
-                                },
-                                "follow_symlinks": {
-                                    "type": "boolean",
-                                    "description": "Follow symbolic links (default: false)",
-                                    "default": false
-                                }
-                            }
-                        }
-                    }
-                ]
+                "tools": schemas::tools()
             })),
             error: None,
         },
@@ -299,9 +336,12 @@ async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> Jso
             let tool_name = params["name"].as_str().unwrap_or("");
             let arguments = params["arguments"].clone();
 
+            let session = params["_meta"]["session_id"].as_str().map(str::to_string);
+
             let tool_request = ToolRequest {
                 tool: tool_name.to_string(),
                 arguments,
+                session,
             };
 
             match executor.execute(tool_request).await {
@@ -322,13 +362,36 @@ async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> Jso
                     jsonrpc: "2.0".to_string(),
                     id: request.id,
                     result: None,
-                    error: Some(JsonRpcError {
-                        code: -32603,
-                        message: format!("Tool execution failed: {}", e),
-                    }),
+                    error: Some(JsonRpcError::from_tool_error(&e)),
                 },
             }
         }
+        "resources/list" => {
+            let params = request.params.unwrap_or(serde_json::Value::Null);
+            let session = params["_meta"]["session_id"].as_str();
+            resource_response(request.id, executor.list_resources(session).await)
+        }
+        "resources/read" => {
+            let params = request.params.unwrap_or(serde_json::Value::Null);
+            let uri = params["uri"].as_str().unwrap_or("");
+            resource_response(request.id, executor.read_resource(uri).await)
+        }
+        "resources/subscribe" => {
+            let params = request.params.unwrap_or(serde_json::Value::Null);
+            let uri = params["uri"].as_str().unwrap_or("");
+            let result = executor
+                .subscriptions
+                .subscribe(uri)
+                .map(|_| serde_json::json!({}));
+            resource_response(request.id, result)
+        }
+        "resources/unsubscribe" => {
+            let params = request.params.unwrap_or(serde_json::Value::Null);
+            executor
+                .subscriptions
+                .unsubscribe(params["uri"].as_str().unwrap_or(""));
+            resource_response(request.id, Ok(serde_json::json!({})))
+        }
         _ => JsonRpcResponse {
             jsonrpc: "2.0".to_string(),
             id: request.id,
@@ -336,6 +399,33 @@ async fn handle_request(request: JsonRpcRequest, executor: &ToolExecutor) -> Jso
             error: Some(JsonRpcError {
                 code: -32601,
                 message: format!("Method not found: {}", request.method),
+                data: None,
+            }),
+        },
+    }
+}
+
+/// Response to a `resources/*` request; failures (unknown sessions, files
+/// or URIs) are reported as invalid params.
+fn resource_response(
+    id: Option<serde_json::Value>,
+    result: Result<serde_json::Value>,
+) -> JsonRpcResponse {
+    match result {
+        Ok(result) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        },
+        Err(e) => JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result: None,
+            error: Some(JsonRpcError {
+                code: -32602,
+                message: e.to_string(),
+                data: None,
             }),
         },
     }
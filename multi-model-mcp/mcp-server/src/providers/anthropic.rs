@@ -1,45 +1,313 @@
-use super::{CompletionRequest, CompletionResponse, Provider, UsageInfo};
-use anyhow::{Context, Result};
+use super::catalog::ModelCatalogCache;
+use super::error::{ensure_success, ProviderError};
+use super::{
+    build_http_client, check_temperature, tokens, CompletionRequest, CompletionResponse,
+    ContentPart, ModelInfo, Provider, StopReason, ThinkingBlock, ToolCall, ToolDefinition,
+    UsageInfo, MIN_ANSWER_TOKENS,
+};
+use crate::config::ProviderConfig;
+use anyhow::Result;
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
 const ANTHROPIC_VERSION: &str = "2023-06-01";
+const DEFAULT_MODEL: &str = "claude-sonnet-4-5-20250929";
+const DEFAULT_SUMMARY_MODEL: &str = "claude-haiku-4-5-20251001";
+
+/// The Messages API accepts at most this many `cache_control` breakpoints.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Highest `temperature` the Messages API accepts.
+const MAX_TEMPERATURE: f32 = 1.0;
+
+/// Lowest `top_p` accepted together with extended thinking.
+const MIN_THINKING_TOP_P: f32 = 0.95;
+
+/// Smallest extended thinking budget the API accepts.
+const MIN_THINKING_BUDGET: u32 = 1_024;
+
+/// Catalog used when `/v1/models` is unreachable and nothing is cached.
+const BUILTIN_MODELS: &[(&str, &str, &str)] = &[
+    (
+        "claude-sonnet-4-5-20250929",
+        "Claude Sonnet 4.5",
+        "2025-09-29T00:00:00Z",
+    ),
+    (
+        "claude-haiku-4-5-20251001",
+        "Claude Haiku 4.5",
+        "2025-10-01T00:00:00Z",
+    ),
+    (
+        "claude-opus-4-1-20250805",
+        "Claude Opus 4.1",
+        "2025-08-05T00:00:00Z",
+    ),
+    (
+        "claude-opus-4-20250514",
+        "Claude Opus 4",
+        "2025-05-14T00:00:00Z",
+    ),
+    (
+        "claude-sonnet-4-20250514",
+        "Claude Sonnet 4",
+        "2025-05-14T00:00:00Z",
+    ),
+    (
+        "claude-3-7-sonnet-20250219",
+        "Claude Sonnet 3.7",
+        "2025-02-19T00:00:00Z",
+    ),
+    (
+        "claude-3-5-haiku-20241022",
+        "Claude Haiku 3.5",
+        "2024-10-22T00:00:00Z",
+    ),
+];
 
 #[derive(Debug, Clone)]
 pub struct AnthropicProvider {
     client: Client,
     api_key: String,
+    default_model: String,
+    summary_model: String,
+    models_cache: ModelCatalogCache,
 }
 
 impl AnthropicProvider {
-    pub fn new(api_key: String) -> Self {
-        use std::time::Duration;
+    pub fn new(api_key: String, config: &ProviderConfig) -> Result<Self> {
+        let client = build_http_client(&config.http)?;
+        Ok(Self {
+            client,
+            api_key,
+            default_model: config
+                .default_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            summary_model: config
+                .summary_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SUMMARY_MODEL.to_string()),
+            models_cache: ModelCatalogCache::new(
+                "anthropic",
+                Duration::from_secs(config.models_cache_ttl_secs),
+            ),
+        })
+    }
+
+    /// Fetch the full catalog from `/v1/models`, following pagination.
+    async fn fetch_models(&self) -> Result<Vec<ModelInfo>> {
+        let mut models = Vec::new();
+        let mut after_id: Option<String> = None;
+
+        loop {
+            let mut query = vec![("limit", "1000".to_string())];
+            if let Some(after_id) = &after_id {
+                query.push(("after_id", after_id.clone()));
+            }
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .connect_timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to build HTTP client");
+            let response = self
+                .client
+                .get(format!("{}/models", ANTHROPIC_API_BASE))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", ANTHROPIC_VERSION)
+                .query(&query)
+                .send()
+                .await
+                .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
+            let response = ensure_success(self.name(), response).await?;
 
-        Self { client, api_key }
+            let page: ModelsPage = response
+                .json()
+                .await
+                .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
+
+            models.extend(page.data.into_iter().map(|m| ModelInfo {
+                id: m.id,
+                display_name: m.display_name,
+                created_at: m.created_at,
+            }));
+
+            match (page.has_more, page.last_id) {
+                (true, Some(last_id)) => after_id = Some(last_id),
+                _ => break,
+            }
+        }
+
+        Ok(models)
     }
 }
 
+fn builtin_models() -> Vec<ModelInfo> {
+    BUILTIN_MODELS
+        .iter()
+        .map(|(id, display_name, created_at)| ModelInfo {
+            id: id.to_string(),
+            display_name: Some(display_name.to_string()),
+            created_at: Some(created_at.to_string()),
+        })
+        .collect()
+}
+
 #[derive(Debug, Serialize)]
 struct AnthropicRequest {
     model: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<RequestBlock>,
     messages: Vec<AnthropicMessage>,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
+    /// `ToolDefinition` already has the `name`/`description`/`input_schema`
+    /// shape the Messages API expects.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ThinkingConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<RequestMetadata>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
+struct RequestMetadata {
+    user_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ThinkingConfig {
+    #[serde(rename = "type")]
+    thinking_type: &'static str,
+    budget_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
 struct AnthropicMessage {
     role: String,
-    content: String,
+    content: Vec<RequestBlock>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum RequestBlock {
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image {
+        source: MediaSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Document {
+        source: MediaSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Thinking {
+        thinking: String,
+        signature: String,
+    },
+    RedactedThinking {
+        data: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct MediaSource {
+    #[serde(rename = "type")]
+    source_type: &'static str,
+    media_type: String,
+    data: String,
+}
+
+impl From<ContentPart> for RequestBlock {
+    fn from(part: ContentPart) -> Self {
+        let base64 = |media_type, data| MediaSource {
+            source_type: "base64",
+            media_type,
+            data,
+        };
+        match part {
+            ContentPart::Text { text } => Self::Text {
+                text,
+                cache_control: None,
+            },
+            ContentPart::Image { media_type, data } => Self::Image {
+                source: base64(media_type, data),
+                cache_control: None,
+            },
+            ContentPart::Document {
+                media_type,
+                data,
+                name,
+            } => Self::Document {
+                source: base64(media_type, data),
+                title: name,
+                cache_control: None,
+            },
+        }
+    }
+}
+
+impl From<ThinkingBlock> for RequestBlock {
+    fn from(block: ThinkingBlock) -> Self {
+        match block {
+            ThinkingBlock::Thinking {
+                thinking,
+                signature,
+            } => Self::Thinking {
+                thinking,
+                signature,
+            },
+            ThinkingBlock::Redacted { data } => Self::RedactedThinking { data },
+        }
+    }
+}
+
+impl RequestBlock {
+    fn set_cache(&mut self) {
+        let breakpoint = Some(CacheControl {
+            cache_type: "ephemeral",
+        });
+        match self {
+            Self::Text { cache_control, .. }
+            | Self::ToolResult { cache_control, .. }
+            | Self::Image { cache_control, .. }
+            | Self::Document { cache_control, .. } => *cache_control = breakpoint,
+            Self::ToolUse { .. } | Self::Thinking { .. } | Self::RedactedThinking { .. } => {}
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    cache_type: &'static str,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,40 +315,230 @@ struct AnthropicResponse {
     content: Vec<ContentBlock>,
     model: String,
     usage: Usage,
+    stop_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ContentBlock {
     #[serde(rename = "type")]
-    #[allow(dead_code)]
     content_type: String,
     text: Option<String>,
+    id: Option<String>,
+    name: Option<String>,
+    input: Option<serde_json::Value>,
+    thinking: Option<String>,
+    signature: Option<String>,
+    data: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Usage {
+    /// Uncached prompt tokens only; cache reads and writes are reported
+    /// separately.
     input_tokens: u32,
     output_tokens: u32,
+    #[serde(default)]
+    cache_creation_input_tokens: u32,
+    #[serde(default)]
+    cache_read_input_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsPage {
+    data: Vec<AnthropicModel>,
+    #[serde(default)]
+    has_more: bool,
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModel {
+    id: String,
+    display_name: Option<String>,
+    created_at: Option<String>,
+}
+
+/// Translate a request: system messages become top-level `system` blocks,
+/// tool calls and results become `tool_use`/`tool_result` blocks, and
+/// messages flagged `cache` get `cache_control` breakpoints.
+fn build_request(request: CompletionRequest) -> AnthropicRequest {
+    // Only the last breakpoints are kept: each one caches the whole prefix
+    // before it, so earlier ones add little.
+    let mut breakpoints = request.messages.iter().filter(|m| m.cache).count();
+    let mut system = Vec::new();
+    let mut messages: Vec<AnthropicMessage> = Vec::new();
+    for message in request.messages {
+        let cache = message.cache && breakpoints <= MAX_CACHE_BREAKPOINTS;
+        if message.cache {
+            breakpoints -= 1;
+        }
+
+        let (role, mut blocks) =
+            match message.role.as_str() {
+                "tool" => (
+                    "user".to_string(),
+                    vec![RequestBlock::ToolResult {
+                        content: message.text(),
+                        tool_use_id: message.tool_call_id.unwrap_or_default(),
+                        cache_control: None,
+                    }],
+                ),
+                _ => {
+                    let empty = message.content.is_empty() && message.tool_calls.is_empty();
+                    // Thinking has to lead the assistant turn it belongs to
+                    let mut blocks: Vec<RequestBlock> = message
+                        .thinking
+                        .into_iter()
+                        .map(RequestBlock::from)
+                        .chain(message.content.into_iter().map(RequestBlock::from))
+                        .collect();
+                    if empty {
+                        blocks.push(RequestBlock::from(ContentPart::Text {
+                            text: String::new(),
+                        }));
+                    }
+                    blocks.extend(message.tool_calls.into_iter().map(|call| {
+                        RequestBlock::ToolUse {
+                            id: call.id,
+                            name: call.name,
+                            input: call.arguments,
+                        }
+                    }));
+                    (message.role, blocks)
+                }
+            };
+        if cache {
+            if let Some(block) = blocks.last_mut() {
+                block.set_cache();
+            }
+        }
+
+        if role == "system" {
+            system.extend(blocks);
+            continue;
+        }
+        // Tool results must share one user turn, so consecutive messages
+        // with the same role are merged.
+        match messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => messages.push(AnthropicMessage {
+                role,
+                content: blocks,
+            }),
+        }
+    }
+
+    // Structured output: force a call to a tool whose input schema is the
+    // requested schema; its input is the JSON reply.
+    let mut tools = request.tools;
+    let mut tool_choice = None;
+    if let Some(format) = request.response_format {
+        tool_choice = Some(serde_json::json!({ "type": "tool", "name": format.name }));
+        tools.push(ToolDefinition {
+            name: format.name,
+            description: "Respond by calling this tool with JSON that follows its input schema."
+                .to_string(),
+            input_schema: format.schema,
+        });
+    }
+
+    // Extended thinking rejects a forced tool choice and any temperature
+    // other than the default. The budget counts against `max_tokens`, which
+    // the registry has already raised to fit it for known models.
+    let mut max_tokens = request.max_tokens.unwrap_or(4096);
+    let thinking = match request.reasoning {
+        Some(_) if tool_choice.is_some() => {
+            tracing::warn!(
+                "Extended thinking is not available with structured output; disabling it"
+            );
+            None
+        }
+        Some(reasoning) => {
+            let budget = reasoning.budget_tokens().max(MIN_THINKING_BUDGET);
+            let needed = budget.saturating_add(MIN_ANSWER_TOKENS);
+            if max_tokens < needed {
+                tracing::warn!(
+                    "Raising max_tokens from {} to {} to fit a thinking budget of {}",
+                    max_tokens,
+                    needed,
+                    budget
+                );
+                max_tokens = needed;
+            }
+            Some(ThinkingConfig {
+                thinking_type: "enabled",
+                budget_tokens: budget,
+            })
+        }
+        None => None,
+    };
+
+    let mut sampling = request.sampling;
+    for (name, set) in [
+        ("seed", sampling.seed.is_some()),
+        ("presence_penalty", sampling.presence_penalty.is_some()),
+        ("frequency_penalty", sampling.frequency_penalty.is_some()),
+    ] {
+        if set {
+            tracing::warn!("Anthropic does not support {}; ignoring it", name);
+        }
+    }
+    if thinking.is_some() {
+        if sampling.top_k.take().is_some() {
+            tracing::warn!("top_k cannot be combined with extended thinking; ignoring it");
+        }
+        if sampling
+            .top_p
+            .is_some_and(|top_p| top_p < MIN_THINKING_TOP_P)
+        {
+            tracing::warn!(
+                "top_p below {} cannot be combined with extended thinking; ignoring it",
+                MIN_THINKING_TOP_P
+            );
+            sampling.top_p = None;
+        }
+    }
+
+    // Claude 4 models reject temperature and top_p together
+    let mut temperature = request.temperature.filter(|_| thinking.is_none());
+    if sampling.top_p.is_some() && temperature.take().is_some() {
+        tracing::warn!("temperature cannot be combined with top_p; ignoring it");
+    }
+
+    AnthropicRequest {
+        model: request.model,
+        system,
+        messages,
+        max_tokens,
+        temperature,
+        tools,
+        tool_choice,
+        thinking,
+        top_p: sampling.top_p,
+        top_k: sampling.top_k,
+        stop_sequences: sampling.stop,
+        metadata: sampling.user.map(|user_id| RequestMetadata { user_id }),
+    }
+}
+
+fn stop_reason(reason: Option<&str>) -> StopReason {
+    match reason {
+        Some("end_turn") | None => StopReason::EndTurn,
+        Some("max_tokens") => StopReason::MaxTokens,
+        Some("stop_sequence") => StopReason::StopSequence,
+        Some("tool_use") => StopReason::ToolUse,
+        Some("refusal") => StopReason::ContentFilter,
+        Some(_) => StopReason::Other,
+    }
 }
 
 #[async_trait]
 impl Provider for AnthropicProvider {
     async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
-        let messages: Vec<AnthropicMessage> = request
-            .messages
-            .into_iter()
-            .map(|m| AnthropicMessage {
-                role: m.role,
-                content: m.content,
-            })
-            .collect();
-
-        let req = AnthropicRequest {
-            model: request.model.clone(),
-            messages,
-            max_tokens: request.max_tokens.unwrap_or(4096),
-            temperature: request.temperature,
-        };
+        let format_name = request.response_format.as_ref().map(|f| f.name.clone());
+        let model = request.model.clone();
+        check_temperature(self.name(), request.temperature, MAX_TEMPERATURE)?;
+        let req = build_request(request);
 
         let response = self
             .client
@@ -91,48 +549,335 @@ impl Provider for AnthropicProvider {
             .json(&req)
             .send()
             .await
-            .context("Failed to send request to Anthropic")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("Anthropic API error {}: {}", status, text);
-        }
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
+        let response = ensure_success(self.name(), response).await?;
 
         let anthropic_response: AnthropicResponse = response
             .json()
             .await
-            .context("Failed to parse Anthropic response")?;
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
 
-        let content = anthropic_response
-            .content
-            .into_iter()
-            .filter_map(|block| block.text)
-            .collect::<Vec<_>>()
-            .join("\n");
+        let mut text = Vec::new();
+        let mut tool_calls = Vec::new();
+        let mut thinking = Vec::new();
+        for block in anthropic_response.content {
+            match block.content_type.as_str() {
+                "tool_use" => tool_calls.push(ToolCall {
+                    id: block.id.unwrap_or_default(),
+                    name: block.name.unwrap_or_default(),
+                    arguments: block.input.unwrap_or_default(),
+                }),
+                "thinking" => thinking.push(ThinkingBlock::Thinking {
+                    thinking: block.thinking.unwrap_or_default(),
+                    signature: block.signature.unwrap_or_default(),
+                }),
+                "redacted_thinking" => thinking.push(ThinkingBlock::Redacted {
+                    data: block.data.unwrap_or_default(),
+                }),
+                _ => text.extend(block.text),
+            }
+        }
+        let mut content = text.join("\n");
+        let mut stop = stop_reason(anthropic_response.stop_reason.as_deref());
+        if let Some(name) = format_name {
+            if let Some(index) = tool_calls.iter().position(|call| call.name == name) {
+                content = tool_calls.remove(index).arguments.to_string();
+                if stop == StopReason::ToolUse {
+                    stop = StopReason::EndTurn;
+                }
+            }
+        }
 
+        // Thinking tokens are billed as output but not reported separately
+        let reasoning_tokens = thinking
+            .iter()
+            .map(|block| match block {
+                ThinkingBlock::Thinking { thinking, .. } => tokens::count_tokens(&model, thinking),
+                ThinkingBlock::Redacted { .. } => 0,
+            })
+            .sum::<u32>();
+        let usage = anthropic_response.usage;
         Ok(CompletionResponse {
             content,
             model: anthropic_response.model,
             usage: Some(UsageInfo {
-                input_tokens: anthropic_response.usage.input_tokens,
-                output_tokens: anthropic_response.usage.output_tokens,
+                input_tokens: usage.input_tokens
+                    + usage.cache_creation_input_tokens
+                    + usage.cache_read_input_tokens,
+                output_tokens: usage.output_tokens,
+                cache_read_tokens: usage.cache_read_input_tokens,
+                cache_write_tokens: usage.cache_creation_input_tokens,
+                reasoning_tokens: reasoning_tokens.min(usage.output_tokens),
+                estimated_input_tokens: None,
             }),
+            tool_calls,
+            stop_reason: stop,
+            thinking,
         })
     }
 
-    async fn list_models(&self) -> Result<Vec<String>> {
-        // Anthropic doesn't have a models endpoint, so return known models
-        Ok(vec![
-            "claude-3-5-sonnet-20241022".to_string(),
-            "claude-3-5-haiku-20241022".to_string(),
-            "claude-3-opus-20240229".to_string(),
-            "claude-3-sonnet-20240229".to_string(),
-            "claude-3-haiku-20240307".to_string(),
-        ])
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        if let Some(models) = self.models_cache.load_fresh() {
+            return Ok(models);
+        }
+
+        match self.fetch_models().await {
+            Ok(models) if !models.is_empty() => {
+                self.models_cache.store(&models);
+                Ok(models)
+            }
+            Ok(_) => Ok(self
+                .models_cache
+                .load_stale()
+                .unwrap_or_else(builtin_models)),
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to fetch Anthropic models, using fallback catalog: {}",
+                    e
+                );
+                Ok(self
+                    .models_cache
+                    .load_stale()
+                    .unwrap_or_else(builtin_models))
+            }
+        }
     }
 
     fn name(&self) -> &str {
         "anthropic"
     }
+
+    fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    fn summary_model(&self) -> &str {
+        &self.summary_model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{Message, Reasoning, ReasoningEffort, ResponseFormat, SamplingParams};
+
+    #[test]
+    fn test_build_request_system_blocks_and_breakpoints() {
+        let message = |role: &str, cache: bool| Message {
+            cache,
+            ..Message::new(role, format!("{} text", role))
+        };
+        let mut messages = vec![message("system", true); 5];
+        messages.push(message("user", false));
+        let req = build_request(CompletionRequest {
+            messages,
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        });
+
+        assert_eq!(req.system.len(), 5);
+        assert_eq!(req.messages.len(), 1);
+        assert_eq!(req.messages[0].role, "user");
+        let cached: Vec<bool> = req
+            .system
+            .iter()
+            .map(|b| {
+                matches!(
+                    b,
+                    RequestBlock::Text {
+                        cache_control: Some(_),
+                        ..
+                    }
+                )
+            })
+            .collect();
+        assert_eq!(cached, [false, true, true, true, true]);
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["system"][4]["cache_control"]["type"], "ephemeral");
+        assert!(json["messages"][0]["content"][0]
+            .get("cache_control")
+            .is_none());
+    }
+
+    #[test]
+    fn test_build_request_tool_round_trip() {
+        let call = ToolCall {
+            id: "toolu_1".to_string(),
+            name: "local_map".to_string(),
+            arguments: serde_json::json!({ "path": "." }),
+        };
+        let req = build_request(CompletionRequest {
+            messages: vec![
+                Message::new("user", "Map the repo"),
+                Message {
+                    role: "assistant".to_string(),
+                    tool_calls: vec![call],
+                    ..Default::default()
+                },
+                Message {
+                    tool_call_id: Some("toolu_1".to_string()),
+                    ..Message::new("tool", "src/")
+                },
+            ],
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        });
+
+        let json = serde_json::to_value(&req).unwrap();
+        let messages = json["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["input"]["path"], ".");
+        assert_eq!(messages[2]["role"], "user");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+        assert!(json.get("tools").is_none());
+        assert_eq!(stop_reason(Some("tool_use")), StopReason::ToolUse);
+    }
+
+    #[test]
+    fn test_build_request_media_blocks() {
+        let mut message = Message::new("user", "What is wrong with this layout?");
+        message.content.push(ContentPart::Image {
+            media_type: "image/png".to_string(),
+            data: "iVBORw0KGgo=".to_string(),
+        });
+        let req = build_request(CompletionRequest {
+            messages: vec![message],
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        });
+
+        let json = serde_json::to_value(&req).unwrap();
+        let image = &json["messages"][0]["content"][1];
+        assert_eq!(image["type"], "image");
+        assert_eq!(image["source"]["type"], "base64");
+        assert_eq!(image["source"]["media_type"], "image/png");
+    }
+
+    #[test]
+    fn test_build_request_forces_schema_tool() {
+        let req = build_request(CompletionRequest {
+            messages: vec![Message::new("user", "Review this")],
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: Vec::new(),
+            response_format: Some(ResponseFormat {
+                name: "code_review".to_string(),
+                schema: serde_json::json!({ "type": "object" }),
+            }),
+            reasoning: Reasoning::new(Some(ReasoningEffort::High), None),
+            sampling: Default::default(),
+        });
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["tools"][0]["name"], "code_review");
+        assert_eq!(json["tools"][0]["input_schema"]["type"], "object");
+        assert_eq!(json["tool_choice"]["type"], "tool");
+        assert_eq!(json["tool_choice"]["name"], "code_review");
+        assert!(json.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_build_request_temperature_with_top_p() {
+        let request = |temperature, top_p| CompletionRequest {
+            messages: vec![Message::new("user", "hi")],
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: None,
+            temperature,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: SamplingParams {
+                top_p,
+                ..Default::default()
+            },
+        };
+
+        let json = serde_json::to_value(build_request(request(Some(0.5), None))).unwrap();
+        assert!((json["temperature"].as_f64().unwrap() - 0.5).abs() < 1e-6);
+        let json = serde_json::to_value(build_request(request(Some(0.5), Some(0.9)))).unwrap();
+        assert!(json.get("temperature").is_none());
+        assert!((json["top_p"].as_f64().unwrap() - 0.9).abs() < 1e-6);
+
+        assert!(check_temperature("anthropic", Some(1.0), MAX_TEMPERATURE).is_ok());
+        assert!(check_temperature("anthropic", Some(1.5), MAX_TEMPERATURE).is_err());
+        assert_eq!(
+            SamplingParams::default().temperature_or(None, 0.7),
+            Some(0.7)
+        );
+        let sampling = SamplingParams {
+            top_p: Some(0.9),
+            ..Default::default()
+        };
+        assert_eq!(sampling.temperature_or(None, 0.7), None);
+    }
+
+    #[test]
+    fn test_build_request_thinking() {
+        let signed = ThinkingBlock::Thinking {
+            thinking: "Check the tree first".to_string(),
+            signature: "sig".to_string(),
+        };
+        let req = build_request(CompletionRequest {
+            messages: vec![
+                Message::new("user", "Map the repo"),
+                Message {
+                    role: "assistant".to_string(),
+                    tool_calls: vec![ToolCall {
+                        id: "toolu_1".to_string(),
+                        name: "local_map".to_string(),
+                        arguments: serde_json::json!({}),
+                    }],
+                    thinking: vec![signed],
+                    ..Default::default()
+                },
+            ],
+            model: DEFAULT_MODEL.to_string(),
+            max_tokens: Some(8_192),
+            temperature: Some(0.7),
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: Reasoning::new(Some(ReasoningEffort::High), None),
+            sampling: SamplingParams {
+                top_p: Some(0.99),
+                top_k: Some(40),
+                stop: vec!["END".to_string()],
+                user: Some("dev-1".to_string()),
+                ..Default::default()
+            },
+        });
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["thinking"]["type"], "enabled");
+        assert!((json["top_p"].as_f64().unwrap() - 0.99).abs() < 1e-6);
+        assert!(json.get("top_k").is_none());
+        assert_eq!(json["stop_sequences"][0], "END");
+        assert_eq!(json["metadata"]["user_id"], "dev-1");
+        assert_eq!(json["thinking"]["budget_tokens"], 24_576);
+        assert_eq!(json["max_tokens"], 24_576 + MIN_ANSWER_TOKENS);
+        assert!(json.get("temperature").is_none());
+        let turn = json["messages"][1]["content"].as_array().unwrap();
+        assert_eq!(turn.len(), 2);
+        assert_eq!(turn[0]["type"], "thinking");
+        assert_eq!(turn[0]["signature"], "sig");
+        assert_eq!(turn[1]["type"], "tool_use");
+    }
 }
@@ -0,0 +1,124 @@
+use super::ModelInfo;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCatalog {
+    fetched_at: DateTime<Utc>,
+    models: Vec<ModelInfo>,
+}
+
+/// On-disk cache of a provider's model catalog, stored as JSON in the
+/// platform cache directory (e.g. `~/.cache/multi-model-mcp/anthropic-models.json`).
+#[derive(Debug, Clone)]
+pub struct ModelCatalogCache {
+    path: Option<PathBuf>,
+    ttl: Duration,
+}
+
+impl ModelCatalogCache {
+    pub fn new(provider: &str, ttl: Duration) -> Self {
+        let path = dirs::cache_dir().map(|dir| {
+            dir.join("multi-model-mcp")
+                .join(format!("{}-models.json", provider))
+        });
+        Self { path, ttl }
+    }
+
+    #[cfg(test)]
+    fn at(path: PathBuf, ttl: Duration) -> Self {
+        Self {
+            path: Some(path),
+            ttl,
+        }
+    }
+
+    /// Cached catalog if it is younger than the TTL.
+    pub fn load_fresh(&self) -> Option<Vec<ModelInfo>> {
+        let cached = self.read()?;
+        let age = Utc::now().signed_duration_since(cached.fetched_at);
+        let fresh = age.to_std().map(|age| age < self.ttl).unwrap_or(false);
+        fresh.then_some(cached.models)
+    }
+
+    /// Cached catalog regardless of age, used when the provider is unreachable.
+    pub fn load_stale(&self) -> Option<Vec<ModelInfo>> {
+        self.read().map(|cached| cached.models)
+    }
+
+    pub fn store(&self, models: &[ModelInfo]) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let cached = CachedCatalog {
+            fetched_at: Utc::now(),
+            models: models.to_vec(),
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                let json = serde_json::to_vec_pretty(&cached)?;
+                fs::write(path, json)
+            });
+        if let Err(e) = result {
+            tracing::warn!(
+                "Failed to write model catalog cache {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    fn read(&self) -> Option<CachedCatalog> {
+        let path = self.path.as_ref()?;
+        let bytes = fs::read(path).ok()?;
+        match serde_json::from_slice(&bytes) {
+            Ok(cached) => Some(cached),
+            Err(e) => {
+                tracing::warn!(
+                    "Ignoring corrupt model catalog cache {}: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample() -> Vec<ModelInfo> {
+        vec![ModelInfo {
+            id: "claude-test".to_string(),
+            display_name: Some("Claude Test".to_string()),
+            created_at: Some("2025-01-01T00:00:00Z".to_string()),
+        }]
+    }
+
+    #[test]
+    fn test_cache_roundtrip_and_ttl() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested/models.json");
+
+        let cache = ModelCatalogCache::at(path.clone(), Duration::from_secs(3600));
+        assert!(cache.load_fresh().is_none());
+
+        cache.store(&sample());
+        let models = cache.load_fresh().unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "claude-test");
+
+        // Expired entries are still available as a stale fallback
+        let expired = ModelCatalogCache::at(path, Duration::ZERO);
+        assert!(expired.load_fresh().is_none());
+        assert_eq!(expired.load_stale().unwrap()[0].id, "claude-test");
+    }
+}
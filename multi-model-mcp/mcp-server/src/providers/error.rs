@@ -0,0 +1,364 @@
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Provider failures classified so that clients can react programmatically
+/// (re-authenticate, back off, shrink the prompt, ...) instead of parsing
+/// error strings.
+#[derive(Debug, Clone, Error)]
+pub enum ProviderError {
+    #[error("{provider} rejected the credentials: {message}")]
+    Unauthorized { provider: String, message: String },
+
+    #[error("{provider} rate limit exceeded: {message}")]
+    RateLimited {
+        provider: String,
+        retry_after: Option<Duration>,
+        message: String,
+    },
+
+    #[error("{provider} is overloaded: {message}")]
+    Overloaded { provider: String, message: String },
+
+    #[error("{provider} context length exceeded: {message}")]
+    ContextTooLong {
+        provider: String,
+        limit: Option<u32>,
+        message: String,
+    },
+
+    #[error("{provider} rejected the request: {message}")]
+    InvalidRequest { provider: String, message: String },
+
+    #[error("Network error talking to {provider}: {message}")]
+    Network { provider: String, message: String },
+
+    #[error("Request to {provider} timed out: {message}")]
+    Timeout { provider: String, message: String },
+
+    #[error("{provider} upstream error{}: {message}", status.map(|s| format!(" {}", s)).unwrap_or_default())]
+    Upstream {
+        provider: String,
+        status: Option<u16>,
+        message: String,
+    },
+}
+
+/// Error envelope shared by the Anthropic and OpenAI APIs:
+/// `{"error": {"type": "...", "code": "...", "message": "..."}}`
+#[derive(Debug, Deserialize)]
+struct ErrorEnvelope {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
+    code: Option<serde_json::Value>,
+    message: Option<String>,
+}
+
+impl ProviderError {
+    /// Classify a non-success HTTP response.
+    pub fn from_response(
+        provider: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Self {
+        let provider = provider.to_string();
+        let parsed = serde_json::from_str::<ErrorEnvelope>(body)
+            .ok()
+            .map(|e| e.error);
+        let error_type = parsed
+            .as_ref()
+            .and_then(|e| e.error_type.clone())
+            .unwrap_or_default();
+        let error_code = parsed
+            .as_ref()
+            .and_then(|e| e.code.as_ref())
+            .and_then(|c| c.as_str().map(str::to_string))
+            .unwrap_or_default();
+        let message = parsed
+            .and_then(|e| e.message)
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| {
+                if body.trim().is_empty() {
+                    status.to_string()
+                } else {
+                    body.trim().to_string()
+                }
+            });
+
+        if is_context_overflow(&error_type, &error_code, &message)
+            || status == StatusCode::PAYLOAD_TOO_LARGE
+        {
+            return Self::ContextTooLong {
+                provider,
+                limit: parse_context_limit(&message),
+                message,
+            };
+        }
+
+        match status.as_u16() {
+            401 | 403 => Self::Unauthorized { provider, message },
+            429 => Self::RateLimited {
+                provider,
+                retry_after: parse_retry_after(headers),
+                message,
+            },
+            408 | 504 => Self::Timeout { provider, message },
+            503 | 529 => Self::Overloaded { provider, message },
+            _ if error_type == "overloaded_error" => Self::Overloaded { provider, message },
+            _ if error_type == "authentication_error" || error_type == "permission_error" => {
+                Self::Unauthorized { provider, message }
+            }
+            400..=499 => Self::InvalidRequest { provider, message },
+            code => Self::Upstream {
+                provider,
+                status: Some(code),
+                message,
+            },
+        }
+    }
+
+    /// Classify a transport-level failure from reqwest.
+    pub fn from_reqwest(provider: &str, err: reqwest::Error) -> Self {
+        let provider = provider.to_string();
+        let message = err.to_string();
+        if err.is_timeout() {
+            Self::Timeout { provider, message }
+        } else if err.is_decode() {
+            Self::Upstream {
+                provider,
+                status: err.status().map(|s| s.as_u16()),
+                message,
+            }
+        } else {
+            Self::Network { provider, message }
+        }
+    }
+
+    /// Stable machine-readable name of the error class.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Unauthorized { .. } => "unauthorized",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Overloaded { .. } => "overloaded",
+            Self::ContextTooLong { .. } => "context_too_long",
+            Self::InvalidRequest { .. } => "invalid_request",
+            Self::Network { .. } => "network",
+            Self::Timeout { .. } => "timeout",
+            Self::Upstream { .. } => "upstream",
+        }
+    }
+
+    /// JSON-RPC error code, taken from the implementation-defined server
+    /// error range (-32000 to -32099). These values are part of the protocol
+    /// contract with the extension and must not be renumbered.
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::Unauthorized { .. } => -32001,
+            Self::RateLimited { .. } => -32002,
+            Self::Overloaded { .. } => -32003,
+            Self::ContextTooLong { .. } => -32004,
+            Self::InvalidRequest { .. } => -32005,
+            Self::Network { .. } => -32006,
+            Self::Timeout { .. } => -32007,
+            Self::Upstream { .. } => -32008,
+        }
+    }
+
+    pub fn provider(&self) -> &str {
+        match self {
+            Self::Unauthorized { provider, .. }
+            | Self::RateLimited { provider, .. }
+            | Self::Overloaded { provider, .. }
+            | Self::ContextTooLong { provider, .. }
+            | Self::InvalidRequest { provider, .. }
+            | Self::Network { provider, .. }
+            | Self::Timeout { provider, .. }
+            | Self::Upstream { provider, .. } => provider,
+        }
+    }
+
+    /// Whether retrying the same request later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::RateLimited { .. }
+                | Self::Overloaded { .. }
+                | Self::Network { .. }
+                | Self::Timeout { .. }
+        )
+    }
+
+    /// Structured payload for the JSON-RPC `error.data` field.
+    pub fn data(&self) -> serde_json::Value {
+        let mut data = serde_json::json!({
+            "kind": self.kind(),
+            "provider": self.provider(),
+            "retryable": self.is_retryable(),
+        });
+        match self {
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+                ..
+            } => {
+                data["retry_after_secs"] = serde_json::json!(retry_after.as_secs_f64());
+            }
+            Self::ContextTooLong {
+                limit: Some(limit), ..
+            } => {
+                data["limit"] = serde_json::json!(limit);
+            }
+            Self::Upstream {
+                status: Some(status),
+                ..
+            } => {
+                data["status"] = serde_json::json!(status);
+            }
+            _ => {}
+        }
+        data
+    }
+}
+
+/// Pass successful responses through and classify everything else.
+pub async fn ensure_success(
+    provider: &str,
+    response: reqwest::Response,
+) -> Result<reqwest::Response, ProviderError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let headers = response.headers().clone();
+    let text = response.text().await.unwrap_or_default();
+    Err(ProviderError::from_response(
+        provider, status, &headers, &text,
+    ))
+}
+
+fn is_context_overflow(error_type: &str, error_code: &str, message: &str) -> bool {
+    if error_code == "context_length_exceeded" || error_type == "request_too_large" {
+        return true;
+    }
+    let message = message.to_lowercase();
+    message.contains("prompt is too long")
+        || message.contains("maximum context length")
+        || message.contains("context window")
+}
+
+/// Extract the token limit from messages such as
+/// "prompt is too long: 208310 tokens > 200000 maximum" (Anthropic) or
+/// "This model's maximum context length is 128000 tokens" (OpenAI).
+fn parse_context_limit(message: &str) -> Option<u32> {
+    let numbers = |s: &str| -> Vec<u32> {
+        s.split(|c: char| !c.is_ascii_digit())
+            .filter_map(|n| n.parse().ok())
+            .collect()
+    };
+
+    if let Some(idx) = message.find("maximum context length") {
+        return numbers(&message[idx..]).into_iter().next();
+    }
+    if let Some(idx) = message.find('>') {
+        return numbers(&message[idx..]).into_iter().next();
+    }
+    None
+}
+
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(ms) = header("retry-after-ms").and_then(|v| v.trim().parse::<f64>().ok()) {
+        return Some(Duration::from_secs_f64(ms / 1000.0));
+    }
+    header("retry-after")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|secs| *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_classifies_anthropic_errors() {
+        let body = r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#;
+        let err = ProviderError::from_response(
+            "anthropic",
+            StatusCode::UNAUTHORIZED,
+            &HeaderMap::new(),
+            body,
+        );
+        assert_eq!(err.kind(), "unauthorized");
+
+        let body = r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#;
+        let status = StatusCode::from_u16(529).unwrap();
+        let err = ProviderError::from_response("anthropic", status, &HeaderMap::new(), body);
+        assert_eq!(err.kind(), "overloaded");
+
+        let body = r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 208310 tokens > 200000 maximum"}}"#;
+        let err = ProviderError::from_response(
+            "anthropic",
+            StatusCode::BAD_REQUEST,
+            &HeaderMap::new(),
+            body,
+        );
+        assert!(matches!(
+            err,
+            ProviderError::ContextTooLong {
+                limit: Some(200000),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_classifies_openai_errors() {
+        let body = r#"{"error":{"message":"This model's maximum context length is 128000 tokens. However, your messages resulted in 130000 tokens.","type":"invalid_request_error","code":"context_length_exceeded"}}"#;
+        let err = ProviderError::from_response(
+            "openai",
+            StatusCode::BAD_REQUEST,
+            &HeaderMap::new(),
+            body,
+        );
+        assert!(matches!(
+            err,
+            ProviderError::ContextTooLong {
+                limit: Some(128000),
+                ..
+            }
+        ));
+        assert_eq!(err.code(), -32004);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("20"));
+        let body = r#"{"error":{"message":"Rate limit reached","type":"requests","code":"rate_limit_exceeded"}}"#;
+        let err =
+            ProviderError::from_response("openai", StatusCode::TOO_MANY_REQUESTS, &headers, body);
+        assert!(matches!(
+            err,
+            ProviderError::RateLimited { retry_after: Some(d), .. } if d == Duration::from_secs(20)
+        ));
+        assert_eq!(err.data()["retry_after_secs"], 20.0);
+        assert_eq!(err.data()["kind"], "rate_limited");
+
+        let err =
+            ProviderError::from_response("openai", StatusCode::BAD_GATEWAY, &HeaderMap::new(), "");
+        assert!(matches!(
+            err,
+            ProviderError::Upstream {
+                status: Some(502),
+                ..
+            }
+        ));
+    }
+}
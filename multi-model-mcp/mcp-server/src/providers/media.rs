@@ -0,0 +1,183 @@
+use super::ContentPart;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::fs;
+use std::path::Path;
+
+/// Largest image either provider accepts (Anthropic's per-image limit).
+pub const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+/// Largest PDF accepted (Anthropic's request limit for documents).
+pub const MAX_DOCUMENT_BYTES: usize = 32 * 1024 * 1024;
+
+/// Image formats accepted by both Anthropic and OpenAI.
+const IMAGE_TYPES: &[(&str, &[&str])] = &[
+    ("image/png", &["png"]),
+    ("image/jpeg", &["jpg", "jpeg"]),
+    ("image/gif", &["gif"]),
+    ("image/webp", &["webp"]),
+];
+const PDF_TYPE: &str = "application/pdf";
+
+/// Tokens charged for an image; Anthropic bills about 1,600 for an image at
+/// the maximum resolution it keeps, OpenAI somewhat less.
+const IMAGE_TOKENS: u32 = 1_600;
+/// Tokens per PDF page (extracted text plus the page image).
+const DOCUMENT_PAGE_TOKENS: u32 = 1_500;
+
+/// Media type of a file, from its extension.
+pub fn media_type_for_path(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    if ext == "pdf" {
+        return Some(PDF_TYPE);
+    }
+    IMAGE_TYPES
+        .iter()
+        .find(|(_, exts)| exts.contains(&ext.as_str()))
+        .map(|(media_type, _)| *media_type)
+}
+
+/// Media type detected from the file's magic bytes.
+fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"%PDF-") {
+        Some(PDF_TYPE)
+    } else {
+        None
+    }
+}
+
+/// Check format and size, returning the image or document part.
+pub fn from_bytes(bytes: &[u8], media_type: &str, name: Option<String>) -> Result<ContentPart> {
+    let is_image = IMAGE_TYPES.iter().any(|(t, _)| *t == media_type);
+    if !is_image && media_type != PDF_TYPE {
+        bail!(
+            "Unsupported media type '{}' (expected PNG, JPEG, GIF, WebP or PDF)",
+            media_type
+        );
+    }
+    match sniff(bytes) {
+        Some(detected) if detected == media_type => {}
+        detected => bail!(
+            "Content does not look like {} (detected: {})",
+            media_type,
+            detected.unwrap_or("unknown")
+        ),
+    }
+
+    let limit = if is_image {
+        MAX_IMAGE_BYTES
+    } else {
+        MAX_DOCUMENT_BYTES
+    };
+    if bytes.len() > limit {
+        bail!(
+            "{} is {} bytes; the limit for {} is {} bytes",
+            name.as_deref().unwrap_or("attachment"),
+            bytes.len(),
+            media_type,
+            limit
+        );
+    }
+
+    let data = STANDARD.encode(bytes);
+    let media_type = media_type.to_string();
+    Ok(if is_image {
+        ContentPart::Image { media_type, data }
+    } else {
+        ContentPart::Document {
+            media_type,
+            data,
+            name,
+        }
+    })
+}
+
+/// Validate base64 data supplied by a caller.
+pub fn from_base64(data: &str, media_type: &str, name: Option<String>) -> Result<ContentPart> {
+    let bytes = STANDARD
+        .decode(data.trim())
+        .context("Attachment data is not valid base64")?;
+    from_bytes(&bytes, media_type, name)
+}
+
+/// Load an image or PDF from disk.
+pub fn from_path(path: &Path) -> Result<ContentPart> {
+    let media_type = media_type_for_path(path).with_context(|| {
+        format!(
+            "Unsupported attachment {} (expected .png, .jpg, .gif, .webp or .pdf)",
+            path.display()
+        )
+    })?;
+    let size = fs::metadata(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .len();
+    if size > MAX_DOCUMENT_BYTES as u64 {
+        bail!("{} is too large ({} bytes)", path.display(), size);
+    }
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned());
+    from_bytes(&bytes, media_type, name)
+}
+
+/// Estimated prompt tokens of a non-text part.
+pub fn estimate_tokens(part: &ContentPart) -> u32 {
+    match part {
+        ContentPart::Text { .. } => 0,
+        ContentPart::Image { .. } => IMAGE_TOKENS,
+        ContentPart::Document { data, .. } => {
+            // Count page objects without a PDF parser; `/Type /Pages` is
+            // the page tree node and must not be counted.
+            let bytes = STANDARD.decode(data).unwrap_or_default();
+            let text = String::from_utf8_lossy(&bytes);
+            let pages = text.matches("/Type /Page").count() - text.matches("/Type /Pages").count();
+            pages.max(1) as u32 * DOCUMENT_PAGE_TOKENS
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn test_validation() {
+        let part = from_bytes(PNG, "image/png", None).unwrap();
+        assert!(
+            matches!(part, ContentPart::Image { ref media_type, .. } if media_type == "image/png")
+        );
+        assert_eq!(estimate_tokens(&part), IMAGE_TOKENS);
+
+        let err = from_bytes(PNG, "image/jpeg", None).unwrap_err();
+        assert!(err.to_string().contains("detected: image/png"));
+        assert!(from_bytes(b"hello", "text/plain", None).is_err());
+        assert!(from_base64("not base64!", "image/png", None).is_err());
+
+        let mut large = PNG.to_vec();
+        large.resize(MAX_IMAGE_BYTES + 1, 0);
+        assert!(from_bytes(&large, "image/png", None).is_err());
+
+        let pdf = b"%PDF-1.7\n1 0 obj << /Type /Pages >>\n2 0 obj << /Type /Page >>\n3 0 obj << /Type /Page >>";
+        let part = from_base64(&STANDARD.encode(pdf), PDF_TYPE, Some("spec.pdf".into())).unwrap();
+        assert_eq!(estimate_tokens(&part), 2 * DOCUMENT_PAGE_TOKENS);
+
+        let asset = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/assets/misfit.png");
+        assert!(matches!(
+            from_path(&asset).unwrap(),
+            ContentPart::Image { .. }
+        ));
+        assert_eq!(
+            media_type_for_path(Path::new("ui/Shot.JPG")),
+            Some("image/jpeg")
+        );
+        assert_eq!(media_type_for_path(Path::new("notes.txt")), None);
+    }
+}
@@ -1,14 +1,190 @@
-use anyhow::Result;
+use crate::config::HttpConfig;
+use anyhow::{Context, Result};
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 pub mod anthropic;
+pub mod catalog;
+pub mod error;
+pub mod media;
 pub mod openai;
+pub mod registry;
+#[cfg(test)]
+pub mod scripted;
+pub mod tokens;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub use error::ProviderError;
+pub use registry::{ModelMetadata, ModelRegistry};
+
+/// A chat message. Besides the usual roles, `assistant` messages may carry
+/// `tool_calls`, and a `tool` message returns the result of the call named
+/// by `tool_call_id`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
-    pub content: String,
+    pub content: Vec<ContentPart>,
+    /// Mark the prompt prefix ending with this message as cacheable.
+    /// Providers without explicit cache control ignore it.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cache: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Reasoning that preceded an assistant message, sent back as-is
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thinking: Vec<ThinkingBlock>,
+}
+
+impl Message {
+    /// A message with a single text part (no part at all for empty text).
+    pub fn new(role: &str, text: impl Into<String>) -> Self {
+        let text = text.into();
+        Self {
+            role: role.to_string(),
+            content: if text.is_empty() {
+                Vec::new()
+            } else {
+                vec![ContentPart::Text { text }]
+            },
+            ..Default::default()
+        }
+    }
+
+    /// The text parts joined together.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|part| match part {
+                ContentPart::Text { text } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn has_media(&self) -> bool {
+        self.content
+            .iter()
+            .any(|part| !matches!(part, ContentPart::Text { .. }))
+    }
+}
+
+/// One typed part of a message. Images and documents carry base64 data
+/// validated by the `media` module.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text {
+        text: String,
+    },
+    /// PNG, JPEG, GIF or WebP
+    Image {
+        media_type: String,
+        data: String,
+    },
+    /// PDF
+    Document {
+        media_type: String,
+        data: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+}
+
+/// A tool the model may call, described by a JSON Schema for its input.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// Reasoning returned along with an answer. Anthropic requires thinking
+/// blocks to be passed back unchanged in the assistant turn when continuing
+/// after a tool call; other providers ignore them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ThinkingBlock {
+    /// Readable thinking (summarized by Claude 4 models) and its signature
+    Thinking { thinking: String, signature: String },
+    /// Thinking encrypted by the provider's safety systems
+    Redacted { data: String },
+}
+
+/// How much a reasoning model should think before answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+/// Reasoning counts against `max_tokens`; requests with a reasoning setting
+/// get at least this much on top of the budget for the answer.
+pub const MIN_ANSWER_TOKENS: u32 = 1_024;
+
+/// Provider-neutral reasoning setting, sent as an extended thinking budget
+/// to Anthropic and as `reasoning_effort` to OpenAI. Whichever of the two
+/// fields is missing is derived from the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reasoning {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effort: Option<ReasoningEffort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_tokens: Option<u32>,
+}
+
+impl Reasoning {
+    /// `None` unless at least one of the settings is given.
+    pub fn new(effort: Option<ReasoningEffort>, budget_tokens: Option<u32>) -> Option<Self> {
+        (effort.is_some() || budget_tokens.is_some()).then_some(Self {
+            effort,
+            budget_tokens,
+        })
+    }
+
+    pub fn effort(&self) -> ReasoningEffort {
+        self.effort.unwrap_or(match self.budget_tokens {
+            Some(budget) if budget < 4_096 => ReasoningEffort::Low,
+            Some(budget) if budget >= 16_384 => ReasoningEffort::High,
+            _ => ReasoningEffort::Medium,
+        })
+    }
+
+    pub fn budget_tokens(&self) -> u32 {
+        self.budget_tokens.unwrap_or(match self.effort() {
+            ReasoningEffort::Low => 2_048,
+            ReasoningEffort::Medium => 8_192,
+            ReasoningEffort::High => 24_576,
+        })
+    }
+}
+
+/// Why the model stopped generating, normalized across providers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    #[default]
+    EndTurn,
+    MaxTokens,
+    StopSequence,
+    ToolUse,
+    /// Refused or filtered by the provider's safety systems
+    ContentFilter,
+    Other,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +193,83 @@ pub struct CompletionRequest {
     pub model: String,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolDefinition>,
+    /// Ask for a JSON reply matching a schema instead of free text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Let reasoning models think before answering.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<Reasoning>,
+    #[serde(default, flatten)]
+    pub sampling: SamplingParams,
+}
+
+/// Sampling parameters beyond `temperature`. Providers ignore the ones
+/// they do not support, with a warning.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SamplingParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Anthropic only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Stop sequences; OpenAI accepts at most 4
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// OpenAI only, best-effort determinism
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    /// OpenAI only, -2.0 to 2.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// OpenAI only, -2.0 to 2.0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// End-user id passed on for abuse monitoring
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl SamplingParams {
+    /// Reject values no provider accepts.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(top_p) = self.top_p {
+            if !(top_p > 0.0 && top_p <= 1.0) {
+                anyhow::bail!("top_p must be in (0, 1] (got {})", top_p);
+            }
+        }
+        for (name, penalty) in [
+            ("presence_penalty", self.presence_penalty),
+            ("frequency_penalty", self.frequency_penalty),
+        ] {
+            if let Some(penalty) = penalty {
+                if !(-2.0..=2.0).contains(&penalty) {
+                    anyhow::bail!("{} must be between -2 and 2 (got {})", name, penalty);
+                }
+            }
+        }
+        if self.stop.iter().any(String::is_empty) {
+            anyhow::bail!("Stop sequences must not be empty");
+        }
+        Ok(())
+    }
+
+    /// The requested `temperature`, or `default` unless `top_p` is set:
+    /// Claude rejects requests that set both.
+    pub fn temperature_or(&self, temperature: Option<f32>, default: f32) -> Option<f32> {
+        temperature.or(self.top_p.is_none().then_some(default))
+    }
+}
+
+/// A JSON Schema the reply must follow. Providers return the JSON document
+/// as the response `content`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseFormat {
+    /// Identifier for the schema (`[a-zA-Z0-9_-]`, at most 64 characters)
+    pub name: String,
+    /// Root must be an object schema
+    pub schema: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,25 +277,150 @@ pub struct CompletionResponse {
     pub content: String,
     pub model: String,
     pub usage: Option<UsageInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default)]
+    pub stop_reason: StopReason,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub thinking: Vec<ThinkingBlock>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CompletionResponse {
+    /// The readable thinking, if the provider returned any.
+    pub fn thinking_text(&self) -> Option<String> {
+        let text: Vec<&str> = self
+            .thinking
+            .iter()
+            .filter_map(|block| match block {
+                ThinkingBlock::Thinking { thinking, .. } => Some(thinking.as_str()),
+                ThinkingBlock::Redacted { .. } => None,
+            })
+            .collect();
+        (!text.is_empty()).then(|| text.join("\n\n"))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UsageInfo {
+    /// All prompt tokens, including those read from or written to the cache.
     pub input_tokens: u32,
     pub output_tokens: u32,
+    /// Prompt tokens served from the provider's prompt cache.
+    #[serde(default)]
+    pub cache_read_tokens: u32,
+    /// Prompt tokens written to the prompt cache (Anthropic only).
+    #[serde(default)]
+    pub cache_write_tokens: u32,
+    /// Part of `output_tokens` spent on reasoning. Reported by OpenAI; for
+    /// Anthropic it is counted from the returned thinking, which Claude 4
+    /// models summarize, so it can undercount.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+    /// Local pre-flight estimate of `input_tokens`, for comparison.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_input_tokens: Option<u32>,
+}
+
+impl UsageInfo {
+    /// Accumulate the usage of another request, e.g. one step of a
+    /// multi-request tool call.
+    pub fn add(&mut self, other: &UsageInfo) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        if let Some(estimate) = other.estimated_input_tokens {
+            *self.estimated_input_tokens.get_or_insert(0) += estimate;
+        }
+    }
+}
+
+/// A model advertised by a provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// RFC 3339 release/creation timestamp, when the provider reports one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+/// Refuse a `temperature` outside `0..=max`, the range `provider` accepts.
+pub(crate) fn check_temperature(provider: &str, temperature: Option<f32>, max: f32) -> Result<()> {
+    match temperature {
+        Some(temperature) if !(0.0..=max).contains(&temperature) => {
+            Err(ProviderError::InvalidRequest {
+                provider: provider.to_string(),
+                message: format!(
+                    "temperature must be between 0 and {} (got {})",
+                    max, temperature
+                ),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Build the HTTP client for a provider from its `[<provider>.http]` settings.
+pub(crate) fn build_http_client(config: &HttpConfig) -> Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs));
+
+    if let Some(proxy) = &config.proxy {
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .with_context(|| format!("Invalid proxy URL '{}'", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if let Some(path) = &config.ca_bundle {
+        let pem = std::fs::read(path)
+            .with_context(|| format!("Failed to read CA bundle {}", path.display()))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("Invalid PEM in CA bundle {}", path.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("CA bundle {} contains no certificates", path.display());
+        }
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if !config.headers.is_empty() {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .with_context(|| format!("Invalid header name '{}'", name))?;
+            let value = HeaderValue::from_str(value)
+                .with_context(|| format!("Invalid value for header '{}'", name))?;
+            headers.insert(name, value);
+        }
+        builder = builder.default_headers(headers);
+    }
+
+    builder.build().context("Failed to build HTTP client")
 }
 
 #[async_trait]
 pub trait Provider: Send + Sync {
     async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse>;
-    async fn list_models(&self) -> Result<Vec<String>>;
+    async fn list_models(&self) -> Result<Vec<ModelInfo>>;
     fn name(&self) -> &str;
+    /// Model used when a request does not specify one.
+    fn default_model(&self) -> &str;
+    /// Cheap model used to summarize context that does not fit a window.
+    fn summary_model(&self) -> &str;
 }
 
 #[derive(Clone)]
 pub enum ProviderType {
     Anthropic(anthropic::AnthropicProvider),
     OpenAI(openai::OpenAIProvider),
+    #[cfg(test)]
+    Scripted(scripted::ScriptedProvider),
 }
 
 impl ProviderType {
@@ -50,13 +428,17 @@ impl ProviderType {
         match self {
             Self::Anthropic(p) => p.complete(request).await,
             Self::OpenAI(p) => p.complete(request).await,
+            #[cfg(test)]
+            Self::Scripted(p) => p.complete(request).await,
         }
     }
 
-    pub async fn list_models(&self) -> Result<Vec<String>> {
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         match self {
             Self::Anthropic(p) => p.list_models().await,
             Self::OpenAI(p) => p.list_models().await,
+            #[cfg(test)]
+            Self::Scripted(p) => p.list_models().await,
         }
     }
 
@@ -64,6 +446,37 @@ impl ProviderType {
         match self {
             Self::Anthropic(p) => p.name(),
             Self::OpenAI(p) => p.name(),
+            #[cfg(test)]
+            Self::Scripted(p) => p.name(),
+        }
+    }
+
+    pub fn default_model(&self) -> &str {
+        match self {
+            Self::Anthropic(p) => p.default_model(),
+            Self::OpenAI(p) => p.default_model(),
+            #[cfg(test)]
+            Self::Scripted(p) => p.default_model(),
+        }
+    }
+
+    pub fn summary_model(&self) -> &str {
+        match self {
+            Self::Anthropic(p) => p.summary_model(),
+            Self::OpenAI(p) => p.summary_model(),
+            #[cfg(test)]
+            Self::Scripted(p) => p.summary_model(),
+        }
+    }
+
+    /// Whether a trailing assistant message is continued as a prefill of
+    /// the answer rather than answered as a new turn.
+    pub fn supports_prefill(&self) -> bool {
+        match self {
+            Self::Anthropic(_) => true,
+            Self::OpenAI(_) => false,
+            #[cfg(test)]
+            Self::Scripted(p) => p.prefill,
         }
     }
 }
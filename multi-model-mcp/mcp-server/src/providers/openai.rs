@@ -1,45 +1,220 @@
-use super::{CompletionRequest, CompletionResponse, Provider, UsageInfo};
-use anyhow::{Context, Result};
+use super::error::{ensure_success, ProviderError};
+use super::{
+    build_http_client, check_temperature, CompletionRequest, CompletionResponse, ContentPart,
+    Message, ModelInfo, Provider, ReasoningEffort, StopReason, ToolCall, UsageInfo,
+};
+use crate::config::ProviderConfig;
+use anyhow::Result;
 use async_trait::async_trait;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 
 const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+const DEFAULT_MODEL: &str = "gpt-4o";
+const DEFAULT_SUMMARY_MODEL: &str = "gpt-4o-mini";
+
+/// Chat completions accept at most this many stop sequences.
+const MAX_STOP_SEQUENCES: usize = 4;
+
+/// Highest `temperature` the Chat Completions API accepts.
+const MAX_TEMPERATURE: f32 = 2.0;
+
+/// Model families served by `/v1/models` that cannot be used with chat completions.
+const NON_CHAT_MARKERS: &[&str] = &[
+    "embedding",
+    "moderation",
+    "whisper",
+    "tts",
+    "dall-e",
+    "image",
+    "audio",
+    "realtime",
+    "transcribe",
+    "davinci",
+    "babbage",
+    "sora",
+];
 
 #[derive(Debug, Clone)]
 pub struct OpenAIProvider {
     client: Client,
     api_key: String,
+    default_model: String,
+    summary_model: String,
 }
 
 impl OpenAIProvider {
-    pub fn new(api_key: String) -> Self {
-        use std::time::Duration;
-
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .connect_timeout(Duration::from_secs(10))
-            .build()
-            .expect("Failed to build HTTP client");
-
-        Self { client, api_key }
+    pub fn new(api_key: String, config: &ProviderConfig) -> Result<Self> {
+        let client = build_http_client(&config.http)?;
+        Ok(Self {
+            client,
+            api_key,
+            default_model: config
+                .default_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            summary_model: config
+                .summary_model
+                .clone()
+                .unwrap_or_else(|| DEFAULT_SUMMARY_MODEL.to_string()),
+        })
     }
 }
 
+fn is_chat_model(id: &str) -> bool {
+    let id = id.to_lowercase();
+    let is_chat_family = id.starts_with("gpt-")
+        || id.starts_with("chatgpt-")
+        || id.starts_with("o1")
+        || id.starts_with("o3")
+        || id.starts_with("o4")
+        || id.starts_with("ft:gpt-")
+        || id.starts_with("ft:o");
+    is_chat_family && !NON_CHAT_MARKERS.iter().any(|marker| id.contains(marker))
+}
+
 #[derive(Debug, Serialize)]
 struct OpenAIRequest {
     model: String,
     messages: Vec<OpenAIMessage>,
+    /// Replaces the deprecated `max_tokens`, which reasoning models reject.
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
+    max_completion_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OpenAITool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<OpenAIResponseFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<ReasoningEffort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIResponseFormat {
+    #[serde(rename = "type")]
+    format_type: &'static str,
+    json_schema: JsonSchemaFormat,
+}
+
+/// `strict` is left off: strict mode rejects schemas that do not list every
+/// property as required, and the reply is validated locally anyway.
+#[derive(Debug, Serialize)]
+struct JsonSchemaFormat {
+    name: String,
+    schema: serde_json::Value,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    /// `null` on assistant messages that only call tools.
+    content: Option<OpenAIContent>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OpenAIToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+/// Plain text, or typed parts when a message carries images or files.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum OpenAIContent {
+    Text(String),
+    Parts(Vec<OpenAIPart>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAIPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    File { file: FileData },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImageUrl {
+    /// `data:` URL with the base64 image
+    url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    /// `data:` URL with the base64 PDF
+    file_data: String,
+}
+
+fn openai_content(message: &mut Message) -> Option<OpenAIContent> {
+    if !message.has_media() {
+        let text = message.text();
+        return (!text.is_empty() || message.tool_calls.is_empty())
+            .then_some(OpenAIContent::Text(text));
+    }
+    let parts = std::mem::take(&mut message.content)
+        .into_iter()
+        .map(|part| match part {
+            ContentPart::Text { text } => OpenAIPart::Text { text },
+            ContentPart::Image { media_type, data } => OpenAIPart::ImageUrl {
+                image_url: ImageUrl {
+                    url: format!("data:{};base64,{}", media_type, data),
+                },
+            },
+            ContentPart::Document {
+                media_type,
+                data,
+                name,
+            } => OpenAIPart::File {
+                file: FileData {
+                    filename: name,
+                    file_data: format!("data:{};base64,{}", media_type, data),
+                },
+            },
+        })
+        .collect();
+    Some(OpenAIContent::Parts(parts))
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAITool {
+    #[serde(rename = "type")]
+    tool_type: &'static str,
+    function: OpenAIFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAIFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    call_type: String,
+    function: OpenAIFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAIFunctionCall {
+    name: String,
+    /// JSON-encoded arguments
+    arguments: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,42 +227,145 @@ struct OpenAIResponse {
 #[derive(Debug, Deserialize)]
 struct Choice {
     message: OpenAIMessage,
+    finish_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Usage {
     prompt_tokens: u32,
     completion_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(default)]
+    completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+/// Reasoning models report the hidden reasoning part of `completion_tokens`.
+#[derive(Debug, Deserialize)]
+struct CompletionTokensDetails {
+    #[serde(default)]
+    reasoning_tokens: u32,
+}
+
+/// Prompts over 1024 tokens are cached automatically; `cached_tokens` is the
+/// part of `prompt_tokens` that was served from the cache.
+#[derive(Debug, Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u32,
 }
 
 #[derive(Debug, Deserialize)]
 struct ModelsResponse {
-    data: Vec<ModelInfo>,
+    data: Vec<OpenAIModel>,
 }
 
 #[derive(Debug, Deserialize)]
-struct ModelInfo {
+struct OpenAIModel {
     id: String,
+    /// Unix timestamp (seconds)
+    created: Option<i64>,
 }
 
-#[async_trait]
-impl Provider for OpenAIProvider {
-    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
-        let messages: Vec<OpenAIMessage> = request
-            .messages
+fn build_request(request: CompletionRequest) -> OpenAIRequest {
+    let mut sampling = request.sampling;
+    if sampling.top_k.is_some() {
+        tracing::warn!("OpenAI does not support top_k; ignoring it");
+    }
+    if sampling.stop.len() > MAX_STOP_SEQUENCES {
+        tracing::warn!(
+            "OpenAI accepts at most {} stop sequences; ignoring {} more",
+            MAX_STOP_SEQUENCES,
+            sampling.stop.len() - MAX_STOP_SEQUENCES
+        );
+        sampling.stop.truncate(MAX_STOP_SEQUENCES);
+    }
+
+    let messages = request
+        .messages
+        .into_iter()
+        .map(|mut m| OpenAIMessage {
+            content: openai_content(&mut m),
+            role: m.role,
+            tool_calls: m
+                .tool_calls
+                .into_iter()
+                .map(|call| OpenAIToolCall {
+                    id: call.id,
+                    call_type: "function".to_string(),
+                    function: OpenAIFunctionCall {
+                        name: call.name,
+                        arguments: call.arguments.to_string(),
+                    },
+                })
+                .collect(),
+            tool_call_id: m.tool_call_id,
+        })
+        .collect();
+
+    OpenAIRequest {
+        model: request.model,
+        messages,
+        max_completion_tokens: request.max_tokens,
+        temperature: request.temperature,
+        tools: request
+            .tools
             .into_iter()
-            .map(|m| OpenAIMessage {
-                role: m.role,
-                content: m.content,
+            .map(|tool| OpenAITool {
+                tool_type: "function",
+                function: OpenAIFunction {
+                    name: tool.name,
+                    description: tool.description,
+                    parameters: tool.input_schema,
+                },
             })
-            .collect();
+            .collect(),
+        response_format: request.response_format.map(|format| OpenAIResponseFormat {
+            format_type: "json_schema",
+            json_schema: JsonSchemaFormat {
+                name: format.name,
+                schema: format.schema,
+            },
+        }),
+        reasoning_effort: request.reasoning.map(|reasoning| reasoning.effort()),
+        top_p: sampling.top_p,
+        stop: sampling.stop,
+        seed: sampling.seed,
+        presence_penalty: sampling.presence_penalty,
+        frequency_penalty: sampling.frequency_penalty,
+        user: sampling.user,
+    }
+}
 
-        let req = OpenAIRequest {
-            model: request.model.clone(),
-            messages,
-            max_tokens: request.max_tokens,
-            temperature: request.temperature,
-        };
+fn stop_reason(reason: Option<&str>) -> StopReason {
+    match reason {
+        Some("stop") | None => StopReason::EndTurn,
+        Some("length") => StopReason::MaxTokens,
+        Some("tool_calls") | Some("function_call") => StopReason::ToolUse,
+        Some("content_filter") => StopReason::ContentFilter,
+        Some(_) => StopReason::Other,
+    }
+}
+
+/// Tool calls from the response; arguments that are not valid JSON are
+/// passed through as a string so the tool can report the problem.
+fn tool_calls(calls: Vec<OpenAIToolCall>) -> Vec<ToolCall> {
+    calls
+        .into_iter()
+        .map(|call| ToolCall {
+            id: call.id,
+            arguments: serde_json::from_str(&call.function.arguments)
+                .unwrap_or(serde_json::Value::String(call.function.arguments)),
+            name: call.function.name,
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Provider for OpenAIProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        check_temperature(self.name(), request.temperature, MAX_TEMPERATURE)?;
+        let req = build_request(request);
 
         let response = self
             .client
@@ -97,24 +375,33 @@ impl Provider for OpenAIProvider {
             .json(&req)
             .send()
             .await
-            .context("Failed to send request to OpenAI")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("OpenAI API error {}: {}", status, text);
-        }
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
+        let response = ensure_success(self.name(), response).await?;
 
         let openai_response: OpenAIResponse = response
             .json()
             .await
-            .context("Failed to parse OpenAI response")?;
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
 
-        let content = openai_response
-            .choices
-            .first()
-            .map(|c| c.message.content.clone())
-            .unwrap_or_default();
+        let (content, calls, finish_reason) = match openai_response.choices.into_iter().next() {
+            Some(choice) => (
+                match choice.message.content {
+                    Some(OpenAIContent::Text(text)) => text,
+                    Some(OpenAIContent::Parts(parts)) => parts
+                        .into_iter()
+                        .filter_map(|part| match part {
+                            OpenAIPart::Text { text } => Some(text),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    None => String::new(),
+                },
+                choice.message.tool_calls,
+                choice.finish_reason,
+            ),
+            None => (String::new(), Vec::new(), None),
+        };
 
         Ok(CompletionResponse {
             content,
@@ -122,39 +409,174 @@ impl Provider for OpenAIProvider {
             usage: Some(UsageInfo {
                 input_tokens: openai_response.usage.prompt_tokens,
                 output_tokens: openai_response.usage.completion_tokens,
+                cache_read_tokens: openai_response
+                    .usage
+                    .prompt_tokens_details
+                    .map_or(0, |details| details.cached_tokens),
+                cache_write_tokens: 0,
+                reasoning_tokens: openai_response
+                    .usage
+                    .completion_tokens_details
+                    .map_or(0, |details| details.reasoning_tokens),
+                estimated_input_tokens: None,
             }),
+            tool_calls: tool_calls(calls),
+            stop_reason: stop_reason(finish_reason.as_deref()),
+            // Chat completions do not return reasoning summaries
+            thinking: Vec::new(),
         })
     }
 
-    async fn list_models(&self) -> Result<Vec<String>> {
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
         let response = self
             .client
             .get(format!("{}/models", OPENAI_API_BASE))
             .header("Authorization", format!("Bearer {}", self.api_key))
             .send()
             .await
-            .context("Failed to list OpenAI models")?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            anyhow::bail!("OpenAI API error {}: {}", status, text);
-        }
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
+        let response = ensure_success(self.name(), response).await?;
 
         let models_response: ModelsResponse = response
             .json()
             .await
-            .context("Failed to parse OpenAI models response")?;
+            .map_err(|e| ProviderError::from_reqwest(self.name(), e))?;
 
-        Ok(models_response
+        let mut models: Vec<OpenAIModel> = models_response
             .data
             .into_iter()
-            .filter(|m| m.id.starts_with("gpt-"))
-            .map(|m| m.id)
+            .filter(|m| is_chat_model(&m.id))
+            .collect();
+        // Newest first, matching the Anthropic catalog ordering
+        models.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| a.id.cmp(&b.id)));
+
+        Ok(models
+            .into_iter()
+            .map(|m| ModelInfo {
+                created_at: m
+                    .created
+                    .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                    .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                display_name: None,
+                id: m.id,
+            })
             .collect())
     }
 
     fn name(&self) -> &str {
         "openai"
     }
+
+    fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    fn summary_model(&self) -> &str {
+        &self.summary_model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{SamplingParams, ToolDefinition};
+
+    #[test]
+    fn test_is_chat_model() {
+        for id in [
+            "gpt-4o",
+            "gpt-4.1-mini",
+            "o1",
+            "o3-mini",
+            "o4-mini",
+            "chatgpt-4o-latest",
+        ] {
+            assert!(is_chat_model(id), "{} should be listed", id);
+        }
+        for id in [
+            "text-embedding-3-large",
+            "whisper-1",
+            "tts-1-hd",
+            "dall-e-3",
+            "gpt-4o-realtime-preview",
+            "gpt-4o-mini-transcribe",
+            "omni-moderation-latest",
+            "davinci-002",
+        ] {
+            assert!(!is_chat_model(id), "{} should be filtered out", id);
+        }
+    }
+
+    #[test]
+    fn test_tool_calls_translation() {
+        let req = build_request(CompletionRequest {
+            messages: vec![
+                Message {
+                    role: "assistant".to_string(),
+                    tool_calls: vec![ToolCall {
+                        id: "call_1".to_string(),
+                        name: "local_map".to_string(),
+                        arguments: serde_json::json!({ "depth": 2 }),
+                    }],
+                    ..Default::default()
+                },
+                Message {
+                    tool_call_id: Some("call_1".to_string()),
+                    ..Message::new("tool", "src/")
+                },
+                Message {
+                    content: vec![ContentPart::Document {
+                        media_type: "application/pdf".to_string(),
+                        data: "JVBERi0=".to_string(),
+                        name: Some("spec.pdf".to_string()),
+                    }],
+                    ..Message::new("user", "")
+                },
+            ],
+            model: "gpt-4o".to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: vec![ToolDefinition {
+                name: "local_map".to_string(),
+                description: "Map a directory".to_string(),
+                input_schema: serde_json::json!({ "type": "object" }),
+            }],
+            response_format: None,
+            reasoning: None,
+            sampling: SamplingParams {
+                top_k: Some(40),
+                stop: (1..=5).map(|i| format!("STOP{}", i)).collect(),
+                seed: Some(7),
+                ..Default::default()
+            },
+        });
+
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["tools"][0]["function"]["parameters"]["type"], "object");
+        assert_eq!(json["seed"], 7);
+        assert_eq!(json["stop"].as_array().unwrap().len(), MAX_STOP_SEQUENCES);
+        assert!(json.get("top_k").is_none());
+        assert!(json["messages"][0]["content"].is_null());
+        assert_eq!(
+            json["messages"][0]["tool_calls"][0]["function"]["arguments"],
+            r#"{"depth":2}"#
+        );
+        assert_eq!(json["messages"][1]["tool_call_id"], "call_1");
+        assert_eq!(json["messages"][1]["content"], "src/");
+        let file = &json["messages"][2]["content"][0];
+        assert_eq!(file["type"], "file");
+        assert_eq!(
+            file["file"]["file_data"],
+            "data:application/pdf;base64,JVBERi0="
+        );
+
+        let calls: Vec<OpenAIToolCall> = serde_json::from_value(serde_json::json!([
+            { "id": "call_2", "type": "function",
+              "function": { "name": "get_context", "arguments": "{}" } }
+        ]))
+        .unwrap();
+        let calls = tool_calls(calls);
+        assert_eq!(calls[0].arguments, serde_json::json!({}));
+        assert_eq!(stop_reason(Some("tool_calls")), StopReason::ToolUse);
+    }
 }
@@ -0,0 +1,564 @@
+use super::tokens::estimate_request_tokens;
+use super::{CompletionRequest, ProviderError, UsageInfo, MIN_ANSWER_TOKENS};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Upper bound on the `max_tokens` we send by default. Models advertise much
+/// larger output limits, but non-streaming requests that large risk hitting
+/// the HTTP timeout, so callers have to ask for more explicitly.
+const DEFAULT_MAX_TOKENS_CAP: u32 = 16_384;
+
+/// Capabilities and list prices of a model.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelMetadata {
+    pub context_window: u32,
+    pub max_output_tokens: u32,
+    pub supports_vision: bool,
+    pub supports_tools: bool,
+    /// Reasoning models (OpenAI o-series) reject custom temperatures.
+    pub supports_temperature: bool,
+    /// Extended thinking (Claude) or `reasoning_effort` (OpenAI).
+    pub supports_reasoning: bool,
+    /// USD per million input tokens
+    pub input_price_per_mtok: f64,
+    /// USD per million output tokens
+    pub output_price_per_mtok: f64,
+    /// USD per million prompt tokens read from the prompt cache
+    pub cache_read_price_per_mtok: f64,
+    /// USD per million prompt tokens written to the prompt cache
+    pub cache_write_price_per_mtok: f64,
+}
+
+impl ModelMetadata {
+    /// Conservative assumptions for models missing from the registry.
+    pub fn fallback() -> Self {
+        Self {
+            context_window: 8_192,
+            max_output_tokens: 4_096,
+            supports_vision: false,
+            supports_tools: false,
+            supports_temperature: true,
+            supports_reasoning: false,
+            input_price_per_mtok: 0.0,
+            output_price_per_mtok: 0.0,
+            cache_read_price_per_mtok: 0.0,
+            cache_write_price_per_mtok: 0.0,
+        }
+    }
+
+    pub fn default_max_tokens(&self) -> u32 {
+        self.max_output_tokens.min(DEFAULT_MAX_TOKENS_CAP)
+    }
+
+    /// Cost in USD of a completion with the given token counts.
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_price_per_mtok
+            + output_tokens as f64 * self.output_price_per_mtok)
+            / 1_000_000.0
+    }
+
+    /// Cost in USD of a completed request, billing cached prompt tokens at
+    /// the cache read/write prices.
+    pub fn usage_cost(&self, usage: &UsageInfo) -> f64 {
+        let uncached = usage
+            .input_tokens
+            .saturating_sub(usage.cache_read_tokens + usage.cache_write_tokens);
+        self.cost(uncached, usage.output_tokens)
+            + (usage.cache_read_tokens as f64 * self.cache_read_price_per_mtok
+                + usage.cache_write_tokens as f64 * self.cache_write_price_per_mtok)
+                / 1_000_000.0
+    }
+
+    /// What the request would have cost without prompt caching, minus what
+    /// it did cost. Negative when cache writes were not (yet) paid back.
+    pub fn cache_savings(&self, usage: &UsageInfo) -> f64 {
+        self.cost(usage.input_tokens, usage.output_tokens) - self.usage_cost(usage)
+    }
+}
+
+/// Cache (read, write) prices as a multiple of the input price, by model id
+/// prefix. Anthropic charges a premium for cache writes; OpenAI caches
+/// automatically at no extra cost. Models not listed get no discount.
+#[rustfmt::skip]
+const CACHE_PRICE_RATIOS: &[(&str, f64, f64)] = &[
+    ("claude",  0.1,  1.25),
+    ("gpt-5",   0.1,  1.0),
+    ("gpt-4.1", 0.25, 1.0),
+    ("o3-mini", 0.5,  1.0),
+    ("o3",      0.25, 1.0),
+    ("o4-mini", 0.25, 1.0),
+    ("gpt-4o",  0.5,  1.0),
+    ("o1",      0.5,  1.0),
+];
+
+fn cache_price_ratios(model: &str) -> (f64, f64) {
+    CACHE_PRICE_RATIOS
+        .iter()
+        .filter(|entry| model.starts_with(entry.0))
+        .max_by_key(|entry| entry.0.len())
+        .map_or((1.0, 1.0), |&(_, read, write)| (read, write))
+}
+
+/// Partial metadata from the user override file; unset fields keep the
+/// built-in (or fallback) value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ModelOverride {
+    context_window: Option<u32>,
+    max_output_tokens: Option<u32>,
+    supports_vision: Option<bool>,
+    supports_tools: Option<bool>,
+    supports_temperature: Option<bool>,
+    supports_reasoning: Option<bool>,
+    input_price_per_mtok: Option<f64>,
+    output_price_per_mtok: Option<f64>,
+    cache_read_price_per_mtok: Option<f64>,
+    cache_write_price_per_mtok: Option<f64>,
+}
+
+impl ModelOverride {
+    fn apply(&self, meta: &mut ModelMetadata) {
+        if let Some(v) = self.context_window {
+            meta.context_window = v;
+        }
+        if let Some(v) = self.max_output_tokens {
+            meta.max_output_tokens = v;
+        }
+        if let Some(v) = self.supports_vision {
+            meta.supports_vision = v;
+        }
+        if let Some(v) = self.supports_tools {
+            meta.supports_tools = v;
+        }
+        if let Some(v) = self.supports_temperature {
+            meta.supports_temperature = v;
+        }
+        if let Some(v) = self.supports_reasoning {
+            meta.supports_reasoning = v;
+        }
+        if let Some(v) = self.input_price_per_mtok {
+            meta.input_price_per_mtok = v;
+        }
+        if let Some(v) = self.output_price_per_mtok {
+            meta.output_price_per_mtok = v;
+        }
+        if let Some(v) = self.cache_read_price_per_mtok {
+            meta.cache_read_price_per_mtok = v;
+        }
+        if let Some(v) = self.cache_write_price_per_mtok {
+            meta.cache_write_price_per_mtok = v;
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideFile {
+    #[serde(default)]
+    models: BTreeMap<String, ModelOverride>,
+}
+
+/// (id prefix, context window, max output, vision, tools, temperature, reasoning, $/MTok in, $/MTok out)
+type BuiltinEntry = (&'static str, u32, u32, bool, bool, bool, bool, f64, f64);
+
+/// Built-in metadata keyed by model id prefix; the longest matching prefix
+/// wins, so dated snapshots resolve to their family.
+#[rustfmt::skip]
+const BUILTIN: &[BuiltinEntry] = &[
+    ("claude-opus-4-1",   200_000,   32_000,  true,  true,  true,  true,  15.0,  75.0),
+    ("claude-opus-4",     200_000,   32_000,  true,  true,  true,  true,  15.0,  75.0),
+    ("claude-sonnet-4-5", 200_000,   64_000,  true,  true,  true,  true,  3.0,   15.0),
+    ("claude-sonnet-4",   200_000,   64_000,  true,  true,  true,  true,  3.0,   15.0),
+    ("claude-haiku-4-5",  200_000,   64_000,  true,  true,  true,  true,  1.0,   5.0),
+    ("claude-3-7-sonnet", 200_000,   64_000,  true,  true,  true,  true,  3.0,   15.0),
+    ("claude-3-5-sonnet", 200_000,   8_192,   true,  true,  true,  false, 3.0,   15.0),
+    ("claude-3-5-haiku",  200_000,   8_192,   true,  true,  true,  false, 0.8,   4.0),
+    ("claude-3-opus",     200_000,   4_096,   true,  true,  true,  false, 15.0,  75.0),
+    ("claude-3-haiku",    200_000,   4_096,   true,  true,  true,  false, 0.25,  1.25),
+    ("gpt-5-nano",        400_000,   128_000, true,  true,  false, true,  0.05,  0.4),
+    ("gpt-5-mini",        400_000,   128_000, true,  true,  false, true,  0.25,  2.0),
+    ("gpt-5",             400_000,   128_000, true,  true,  false, true,  1.25,  10.0),
+    ("gpt-4.1-nano",      1_047_576, 32_768,  true,  true,  true,  false, 0.1,   0.4),
+    ("gpt-4.1-mini",      1_047_576, 32_768,  true,  true,  true,  false, 0.4,   1.6),
+    ("gpt-4.1",           1_047_576, 32_768,  true,  true,  true,  false, 2.0,   8.0),
+    ("gpt-4o-mini",       128_000,   16_384,  true,  true,  true,  false, 0.15,  0.6),
+    ("gpt-4o",            128_000,   16_384,  true,  true,  true,  false, 2.5,   10.0),
+    ("chatgpt-4o",        128_000,   16_384,  true,  false, true,  false, 5.0,   15.0),
+    ("gpt-4-turbo",       128_000,   4_096,   true,  true,  true,  false, 10.0,  30.0),
+    ("gpt-4",             8_192,     8_192,   false, true,  true,  false, 30.0,  60.0),
+    ("gpt-3.5-turbo",     16_385,    4_096,   false, true,  true,  false, 0.5,   1.5),
+    ("o1-mini",           128_000,   65_536,  false, false, false, false, 1.1,   4.4),
+    ("o1",                200_000,   100_000, true,  true,  false, true,  15.0,  60.0),
+    ("o3-mini",           200_000,   100_000, false, true,  false, true,  1.1,   4.4),
+    ("o3",                200_000,   100_000, true,  true,  false, true,  2.0,   8.0),
+    ("o4-mini",           200_000,   100_000, true,  true,  false, true,  1.1,   4.4),
+];
+
+/// Model capability and pricing lookup: the built-in table plus the user
+/// override file (`<config dir>/multi-model-mcp/models.toml` by default).
+///
+/// ```toml
+/// [models."gpt-4o"]
+/// input_price_per_mtok = 2.0
+///
+/// [models."my-gateway-model"]
+/// context_window = 32000
+/// max_output_tokens = 4096
+/// supports_tools = true
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ModelRegistry {
+    overrides: BTreeMap<String, ModelOverride>,
+}
+
+impl ModelRegistry {
+    /// Load overrides from `path`, or from the default location when `path`
+    /// is `None`. A missing default file is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read model overrides {}", path.display()))?;
+        let file: OverrideFile = toml::from_str(&text)
+            .with_context(|| format!("Failed to parse model overrides {}", path.display()))?;
+        tracing::info!(
+            "Loaded {} model override(s) from {}",
+            file.models.len(),
+            path.display()
+        );
+        Ok(Self {
+            overrides: file.models,
+        })
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("multi-model-mcp").join("models.toml"))
+    }
+
+    /// Metadata for `model`, or `None` if neither the built-in table nor the
+    /// override file knows it.
+    pub fn lookup(&self, model: &str) -> Option<ModelMetadata> {
+        let builtin = BUILTIN
+            .iter()
+            .filter(|entry| model.starts_with(entry.0))
+            .max_by_key(|entry| entry.0.len())
+            .map(
+                |&(
+                    _,
+                    window,
+                    output,
+                    vision,
+                    tools,
+                    temperature,
+                    reasoning,
+                    input,
+                    output_price,
+                )| {
+                    let (cache_read, cache_write) = cache_price_ratios(model);
+                    ModelMetadata {
+                        context_window: window,
+                        max_output_tokens: output,
+                        supports_vision: vision,
+                        supports_tools: tools,
+                        supports_temperature: temperature,
+                        supports_reasoning: reasoning,
+                        input_price_per_mtok: input,
+                        output_price_per_mtok: output_price,
+                        cache_read_price_per_mtok: input * cache_read,
+                        cache_write_price_per_mtok: input * cache_write,
+                    }
+                },
+            );
+        match (builtin, self.override_for(model)) {
+            (None, None) => None,
+            (builtin, overrides) => {
+                let mut meta = builtin.unwrap_or_else(ModelMetadata::fallback);
+                if let Some(overrides) = overrides {
+                    // Cache prices follow an overridden input price unless
+                    // they are overridden themselves.
+                    if let Some(input) = overrides.input_price_per_mtok {
+                        let (cache_read, cache_write) = cache_price_ratios(model);
+                        meta.cache_read_price_per_mtok = input * cache_read;
+                        meta.cache_write_price_per_mtok = input * cache_write;
+                    }
+                    overrides.apply(&mut meta);
+                }
+                Some(meta)
+            }
+        }
+    }
+
+    /// Whether the list price of `model` is known. Models only described by
+    /// capability overrides are priced at 0 by `lookup`, which budgets must
+    /// not take for free.
+    pub fn is_priced(&self, model: &str) -> bool {
+        BUILTIN.iter().any(|entry| model.starts_with(entry.0))
+            || self.override_for(model).is_some_and(|o| {
+                o.input_price_per_mtok.is_some() || o.output_price_per_mtok.is_some()
+            })
+    }
+
+    fn override_for(&self, model: &str) -> Option<&ModelOverride> {
+        self.overrides
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, o)| o)
+    }
+
+    /// Metadata for `model`, falling back to conservative defaults.
+    pub fn resolve(&self, model: &str) -> ModelMetadata {
+        self.lookup(model).unwrap_or_else(ModelMetadata::fallback)
+    }
+
+    /// Fit a request to what the model supports: default and clamp
+    /// `max_tokens`, drop unsupported sampling parameters, and refuse
+    /// prompts that cannot fit in the context window.
+    pub fn adapt(&self, provider: &str, request: &mut CompletionRequest) -> Result<()> {
+        let Some(meta) = self.lookup(&request.model) else {
+            request
+                .max_tokens
+                .get_or_insert(ModelMetadata::fallback().default_max_tokens());
+            return Ok(());
+        };
+
+        let input_tokens = estimate_request_tokens(request);
+        if input_tokens >= meta.context_window {
+            return Err(ProviderError::ContextTooLong {
+                provider: provider.to_string(),
+                limit: Some(meta.context_window),
+                message: format!(
+                    "prompt is ~{} tokens but {} accepts at most {}",
+                    input_tokens, request.model, meta.context_window
+                ),
+            }
+            .into());
+        }
+
+        if !meta.supports_reasoning && request.reasoning.take().is_some() {
+            tracing::warn!(
+                "{} does not support reasoning controls; omitting them",
+                request.model
+            );
+        }
+
+        // Reasoning counts against max_tokens, so make room for the budget
+        // and an answer rather than letting it crowd the answer out.
+        let mut requested = request.max_tokens.unwrap_or(meta.default_max_tokens());
+        let needed = request
+            .reasoning
+            .map(|reasoning| reasoning.budget_tokens().saturating_add(MIN_ANSWER_TOKENS));
+        if let Some(needed) = needed.filter(|&needed| needed > requested) {
+            tracing::debug!(
+                "Raising max_tokens for {} from {} to {} to fit the reasoning budget",
+                request.model,
+                requested,
+                needed
+            );
+            requested = needed;
+        }
+        let available = meta.context_window - input_tokens;
+        let max_tokens = requested.min(meta.max_output_tokens).min(available);
+        if let Some(needed) = needed.filter(|&needed| needed > max_tokens) {
+            return Err(ProviderError::InvalidRequest {
+                provider: provider.to_string(),
+                message: format!(
+                    "a reasoning budget of {} tokens plus {} for the answer does not fit in the {} output tokens available to {}; lower thinking_budget or reasoning_effort",
+                    needed - MIN_ANSWER_TOKENS,
+                    MIN_ANSWER_TOKENS,
+                    max_tokens,
+                    request.model
+                ),
+            }
+            .into());
+        }
+        if max_tokens < requested {
+            tracing::warn!(
+                "Reducing max_tokens for {} from {} to {}",
+                request.model,
+                requested,
+                max_tokens
+            );
+        }
+        request.max_tokens = Some(max_tokens);
+
+        if !meta.supports_vision && request.messages.iter().any(|m| m.has_media()) {
+            return Err(ProviderError::InvalidRequest {
+                provider: provider.to_string(),
+                message: format!("{} does not accept images or documents", request.model),
+            }
+            .into());
+        }
+
+        if !meta.supports_tools && !request.tools.is_empty() {
+            tracing::warn!(
+                "{} does not support tool calling; omitting tools",
+                request.model
+            );
+            request.tools.clear();
+        }
+
+        if !meta.supports_temperature && request.temperature.take().is_some() {
+            tracing::debug!("{} does not accept temperature; omitting it", request.model);
+        }
+        // Reasoning models reject the other sampling knobs for the same reason
+        if !meta.supports_temperature {
+            let sampling = &mut request.sampling;
+            for (name, dropped) in [
+                ("top_p", sampling.top_p.take().is_some()),
+                (
+                    "presence_penalty",
+                    sampling.presence_penalty.take().is_some(),
+                ),
+                (
+                    "frequency_penalty",
+                    sampling.frequency_penalty.take().is_some(),
+                ),
+            ] {
+                if dropped {
+                    tracing::warn!("{} does not accept {}; omitting it", request.model, name);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::{Message, Reasoning, ReasoningEffort};
+
+    fn request(model: &str, content: String) -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![Message::new("user", content)],
+            model: model.to_string(),
+            max_tokens: None,
+            temperature: Some(0.7),
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_lookup_longest_prefix_and_overrides() {
+        let registry = ModelRegistry::default();
+        let price = |model: &str| registry.lookup(model).unwrap().input_price_per_mtok;
+        assert_eq!(price("gpt-4o-mini-2024-07-18"), 0.15);
+        assert_eq!(price("gpt-4o-2024-08-06"), 2.5);
+        assert_eq!(registry.lookup("gpt-4-0613").unwrap().context_window, 8_192);
+        assert!(registry.lookup("unknown-model").is_none());
+
+        let file: OverrideFile = toml::from_str(
+            r#"
+            [models."gpt-4o"]
+            input_price_per_mtok = 2.0
+
+            [models."local-llm"]
+            context_window = 32000
+            "#,
+        )
+        .unwrap();
+        let registry = ModelRegistry {
+            overrides: file.models,
+        };
+        let meta = registry.lookup("gpt-4o").unwrap();
+        assert_eq!(meta.input_price_per_mtok, 2.0);
+        assert_eq!(meta.cache_read_price_per_mtok, 1.0);
+        assert_eq!(meta.context_window, 128_000);
+        assert_eq!(registry.lookup("local-llm").unwrap().context_window, 32_000);
+        assert!(registry.is_priced("gpt-4o-mini"));
+        assert!(!registry.is_priced("local-llm"));
+    }
+
+    #[test]
+    fn test_usage_cost_with_cache() {
+        let meta = ModelRegistry::default()
+            .lookup("claude-sonnet-4-5")
+            .unwrap();
+        let usage = UsageInfo {
+            input_tokens: 1_000_000,
+            output_tokens: 0,
+            cache_read_tokens: 800_000,
+            cache_write_tokens: 0,
+            reasoning_tokens: 0,
+            estimated_input_tokens: None,
+        };
+        // 200k uncached at $3 + 800k cached at $0.30
+        assert!((meta.usage_cost(&usage) - 0.84).abs() < 1e-9);
+        assert!((meta.cache_savings(&usage) - 2.16).abs() < 1e-9);
+
+        let write = UsageInfo {
+            cache_read_tokens: 0,
+            cache_write_tokens: 1_000_000,
+            ..usage
+        };
+        assert!(meta.cache_savings(&write) < 0.0);
+    }
+
+    #[test]
+    fn test_adapt_request() {
+        let registry = ModelRegistry::default();
+
+        let mut req = request("claude-3-5-haiku-20241022", "hi".to_string());
+        req.max_tokens = Some(100_000);
+        registry.adapt("anthropic", &mut req).unwrap();
+        assert_eq!(req.max_tokens, Some(8_192));
+
+        let mut req = request("o3-mini", "hi".to_string());
+        registry.adapt("openai", &mut req).unwrap();
+        assert_eq!(req.max_tokens, Some(DEFAULT_MAX_TOKENS_CAP));
+        assert_eq!(req.temperature, None);
+
+        let mut req = request("o3-mini", "hi".to_string());
+        req.sampling.top_p = Some(0.5);
+        req.sampling.seed = Some(1);
+        registry.adapt("openai", &mut req).unwrap();
+        assert_eq!(req.sampling.top_p, None);
+        assert_eq!(req.sampling.seed, Some(1));
+
+        // The thinking budget gets room for an answer, or is refused
+        let mut req = request("claude-sonnet-4-5-20250929", "hi".to_string());
+        req.max_tokens = Some(4_096);
+        req.reasoning = Reasoning::new(Some(ReasoningEffort::High), None);
+        registry.adapt("anthropic", &mut req).unwrap();
+        assert_eq!(req.max_tokens, Some(24_576 + MIN_ANSWER_TOKENS));
+        req.reasoning = Reasoning::new(None, Some(64_000));
+        let err = registry.adapt("anthropic", &mut req).unwrap_err();
+        let err = err.downcast_ref::<ProviderError>().unwrap();
+        assert_eq!(err.kind(), "invalid_request");
+
+        let mut req = request("gpt-4o", "hi".to_string());
+        req.reasoning = Reasoning::new(Some(ReasoningEffort::Low), None);
+        registry.adapt("openai", &mut req).unwrap();
+        assert_eq!(req.reasoning, None);
+
+        let mut req = request("gpt-3.5-turbo", "hi".to_string());
+        req.messages[0]
+            .content
+            .push(crate::providers::ContentPart::Image {
+                media_type: "image/png".to_string(),
+                data: String::new(),
+            });
+        let err = registry.adapt("openai", &mut req).unwrap_err();
+        let err = err.downcast_ref::<ProviderError>().unwrap();
+        assert_eq!(err.kind(), "invalid_request");
+
+        let mut req = request("gpt-4", "word ".repeat(10_000));
+        let err = registry.adapt("openai", &mut req).unwrap_err();
+        let err = err.downcast_ref::<ProviderError>().unwrap();
+        assert_eq!(err.kind(), "context_too_long");
+    }
+}
@@ -0,0 +1,75 @@
+//! A provider that replays canned responses, for testing tools that make
+//! several completions per call.
+
+use super::{CompletionRequest, CompletionResponse, ModelInfo, Provider, StopReason};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub struct ScriptedProvider {
+    /// Whether trailing assistant messages are continued, as with Anthropic
+    pub prefill: bool,
+    responses: Arc<Mutex<VecDeque<CompletionResponse>>>,
+    requests: Arc<Mutex<Vec<CompletionRequest>>>,
+}
+
+impl ScriptedProvider {
+    pub fn new(prefill: bool) -> Self {
+        Self {
+            prefill,
+            responses: Arc::default(),
+            requests: Arc::default(),
+        }
+    }
+
+    /// Queue a reply with `content` that stopped for `stop_reason`.
+    pub fn reply(self, content: &str, stop_reason: StopReason) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .push_back(CompletionResponse {
+                content: content.to_string(),
+                model: "scripted".to_string(),
+                usage: None,
+                tool_calls: Vec::new(),
+                stop_reason,
+                thinking: Vec::new(),
+            });
+        self
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<CompletionRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Provider for ScriptedProvider {
+    async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        self.requests.lock().unwrap().push(request);
+        self.responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("No scripted response left"))
+    }
+
+    async fn list_models(&self) -> Result<Vec<ModelInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn default_model(&self) -> &str {
+        "scripted"
+    }
+
+    fn summary_model(&self) -> &str {
+        "scripted"
+    }
+}
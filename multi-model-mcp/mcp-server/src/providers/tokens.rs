@@ -0,0 +1,174 @@
+use super::{media, CompletionRequest, ContentPart, Message, ThinkingBlock};
+use tiktoken_rs::{cl100k_base_singleton, o200k_base_singleton, CoreBPE};
+
+/// Claude's tokenizer is not public. Measured against `count_tokens` on a
+/// mix of prose and source code, it produces about 15% more tokens than
+/// cl100k, so Claude counts are cl100k counts scaled by this factor.
+const CLAUDE_CL100K_RATIO: f64 = 1.15;
+
+/// Framing tokens added per message (role markers, separators).
+const TOKENS_PER_MESSAGE: u32 = 4;
+
+/// Token counting strategy for a model family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tokenizer {
+    /// GPT-4o, GPT-4.1, GPT-5 and o-series models
+    O200k,
+    /// GPT-4 and GPT-3.5; also used for unknown models
+    Cl100k,
+    /// Calibrated estimate for Anthropic models
+    Claude,
+}
+
+impl Tokenizer {
+    pub fn for_model(model: &str) -> Self {
+        let model = model.to_lowercase();
+        if model.starts_with("claude") {
+            Self::Claude
+        } else if model.starts_with("gpt-4o")
+            || model.starts_with("gpt-4.1")
+            || model.starts_with("gpt-5")
+            || model.starts_with("chatgpt-")
+            || model.starts_with("o1")
+            || model.starts_with("o3")
+            || model.starts_with("o4")
+        {
+            Self::O200k
+        } else {
+            Self::Cl100k
+        }
+    }
+
+    fn bpe(&self) -> &'static CoreBPE {
+        match self {
+            Self::O200k => o200k_base_singleton(),
+            Self::Cl100k | Self::Claude => cl100k_base_singleton(),
+        }
+    }
+
+    pub fn count(&self, text: &str) -> u32 {
+        if text.is_empty() {
+            return 0;
+        }
+        let tokens = self.bpe().encode_ordinary(text).len() as u32;
+        match self {
+            Self::Claude => (tokens as f64 * CLAUDE_CL100K_RATIO).ceil() as u32,
+            _ => tokens,
+        }
+    }
+
+    /// Longest prefix of `text` that fits in `max_tokens`, cut at a line
+    /// boundary when possible.
+    pub fn truncate<'a>(&self, text: &'a str, max_tokens: u32) -> &'a str {
+        if self.count(text) <= max_tokens {
+            return text;
+        }
+        // Binary search over line boundaries; fall back to characters for
+        // single huge lines.
+        let mut cuts: Vec<usize> = text.match_indices('\n').map(|(i, _)| i + 1).collect();
+        if cuts.len() < 2 {
+            cuts = text.char_indices().map(|(i, _)| i).skip(1).collect();
+        }
+        let (mut lo, mut hi) = (0usize, cuts.len());
+        while lo < hi {
+            let mid = (lo + hi).div_ceil(2);
+            if self.count(&text[..cuts[mid - 1]]) <= max_tokens {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        if lo == 0 {
+            ""
+        } else {
+            &text[..cuts[lo - 1]]
+        }
+    }
+
+    /// Estimated tokens of one message: framing, text and media parts, tool
+    /// calls and thinking.
+    pub fn count_message(&self, m: &Message) -> u32 {
+        TOKENS_PER_MESSAGE
+            + m.content
+                .iter()
+                .map(|part| match part {
+                    ContentPart::Text { text } => self.count(text),
+                    media => media::estimate_tokens(media),
+                })
+                .sum::<u32>()
+            + m.tool_calls
+                .iter()
+                .map(|call| self.count(&call.name) + self.count(&call.arguments.to_string()))
+                .sum::<u32>()
+            + m.thinking
+                .iter()
+                .map(|block| match block {
+                    ThinkingBlock::Thinking { thinking, .. } => self.count(thinking),
+                    ThinkingBlock::Redacted { .. } => 0,
+                })
+                .sum::<u32>()
+    }
+}
+
+/// Count the tokens of `text` for `model`.
+pub fn count_tokens(model: &str, text: &str) -> u32 {
+    Tokenizer::for_model(model).count(text)
+}
+
+/// Estimated input tokens of a request, including per-message framing,
+/// tool calls, tool definitions and the response schema.
+pub fn estimate_request_tokens(request: &CompletionRequest) -> u32 {
+    let tokenizer = Tokenizer::for_model(&request.model);
+    let messages: u32 = request
+        .messages
+        .iter()
+        .map(|m| tokenizer.count_message(m))
+        .sum();
+    let tools: u32 = request
+        .tools
+        .iter()
+        .map(|tool| {
+            tokenizer.count(&tool.name)
+                + tokenizer.count(&tool.description)
+                + tokenizer.count(&tool.input_schema.to_string())
+        })
+        .sum();
+    let schema = request
+        .response_format
+        .as_ref()
+        .map_or(0, |format| tokenizer.count(&format.schema.to_string()));
+    messages + tools + schema + 3
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer_selection_and_counts() {
+        assert_eq!(Tokenizer::for_model("gpt-4o-mini"), Tokenizer::O200k);
+        assert_eq!(Tokenizer::for_model("o3-mini"), Tokenizer::O200k);
+        assert_eq!(Tokenizer::for_model("gpt-4-turbo"), Tokenizer::Cl100k);
+        assert_eq!(Tokenizer::for_model("claude-sonnet-4-5"), Tokenizer::Claude);
+
+        assert_eq!(count_tokens("gpt-4o", "hello world"), 2);
+        assert_eq!(count_tokens("gpt-4", "hello world"), 2);
+        assert_eq!(count_tokens("claude-sonnet-4-5", ""), 0);
+
+        let code = "fn main() {\n    println!(\"Hello, world!\");\n}\n".repeat(20);
+        let gpt = count_tokens("gpt-4", &code);
+        let claude = count_tokens("claude-sonnet-4-5", &code);
+        assert!(claude > gpt);
+    }
+
+    #[test]
+    fn test_truncate_at_line_boundary() {
+        let tokenizer = Tokenizer::Cl100k;
+        let text = "line one\nline two\nline three\nline four\n";
+        let truncated = tokenizer.truncate(text, 5);
+        assert!(tokenizer.count(truncated) <= 5);
+        assert!(truncated.ends_with('\n'));
+        assert!(text.starts_with(truncated));
+        assert_eq!(tokenizer.truncate(text, 1_000), text);
+    }
+}
@@ -0,0 +1,546 @@
+//! Input schemas of the tools listed by `tools/list`, one function per
+//! tool.
+
+use serde_json::{json, Value};
+
+/// Every tool the server offers, in the order `tools/list` returns them.
+pub fn tools() -> Vec<Value> {
+    vec![
+        generate_code(),
+        review_code(),
+        switch_model(),
+        list_models(),
+        add_context(),
+        get_context(),
+        clear_context(),
+        remove_context(),
+        list_context(),
+        set_context_priority(),
+        refresh_context(),
+        chat(),
+        get_chat_history(),
+        clear_chat_history(),
+        create_session(),
+        list_sessions(),
+        switch_session(),
+        rename_session(),
+        fork_session(),
+        delete_session(),
+        export_session(),
+        import_session(),
+        usage_report(),
+        budget_status(),
+        local_map(),
+    ]
+}
+
+fn generate_code() -> Value {
+    json!({
+        "name": "generate_code",
+        "description": "Generate code based on a prompt",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "prompt": { "type": "string", "description": "Code generation prompt" },
+                "language": { "type": "string", "description": "Programming language" },
+                "context": { "type": "array", "items": { "type": "string" } },
+                "model": { "type": "string", "description": "Specific model to use" },
+                "max_tokens": { "type": "integer", "description": "Maximum output tokens (default and upper bound come from the model's metadata)" },
+                "include_context": { "type": "boolean", "description": "Append the conversation context, trimmed to fit the model's context window (default: false)" },
+                "use_tools": { "type": "boolean", "description": "Let the model call local_map and get_context before answering (default: false)" },
+                "attachments": { "type": "array", "description": "Images (PNG, JPEG, GIF, WebP; max 5 MB) or PDFs (max 32 MB) for vision-capable models", "items": { "type": "object", "properties": { "path": { "type": "string" }, "data": { "type": "string" }, "media_type": { "type": "string" }, "name": { "type": "string" } } } },
+                "response_schema": { "type": "object", "description": "JSON schema (object root) the reply must match; the result's code is then the validated JSON value" },
+                "reasoning_effort": { "type": "string", "enum": ["low", "medium", "high"], "description": "Reasoning effort for models that support it (OpenAI reasoning models, Claude extended thinking)" },
+                "thinking_budget": { "type": "integer", "minimum": 1024, "description": "Extended thinking budget in tokens (Claude); derived from reasoning_effort when omitted" },
+                "max_continuations": { "type": "integer", "minimum": 0, "maximum": 10, "description": "Follow-up requests made while the answer stops at max_tokens (default: 3)" },
+                "temperature": { "type": "number", "minimum": 0, "maximum": 2, "description": "Sampling temperature, at most 1 for Claude (default: 0.7, or unset when top_p is given)" },
+                "top_p": { "type": "number", "exclusiveMinimum": 0, "maximum": 1, "description": "Nucleus sampling probability mass" },
+                "top_k": { "type": "integer", "minimum": 1, "description": "Sample from the k most likely tokens (Anthropic only)" },
+                "stop": { "type": "array", "items": { "type": "string" }, "description": "Stop sequences (OpenAI uses the first 4)" },
+                "seed": { "type": "integer", "description": "Seed for best-effort deterministic sampling (OpenAI only)" },
+                "presence_penalty": { "type": "number", "minimum": -2, "maximum": 2, "description": "Penalty for tokens already present (OpenAI only)" },
+                "frequency_penalty": { "type": "number", "minimum": -2, "maximum": 2, "description": "Penalty proportional to token frequency (OpenAI only)" },
+                "user": { "type": "string", "description": "End-user id passed to the provider for abuse monitoring" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            },
+            "required": ["prompt"]
+        }
+    })
+}
+
+fn review_code() -> Value {
+    json!({
+        "name": "review_code",
+        "description": "Review code for issues and improvements",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "code": { "type": "string", "description": "Code to review" },
+                "language": { "type": "string", "description": "Programming language" },
+                "focus": { "type": "array", "items": { "type": "string" }, "description": "Areas to focus on (security, performance, style)" },
+                "model": { "type": "string", "description": "Specific model to use" },
+                "max_tokens": { "type": "integer", "description": "Maximum output tokens (default and upper bound come from the model's metadata)" },
+                "include_context": { "type": "boolean", "description": "Append the conversation context, trimmed to fit the model's context window (default: false). Code too large for one request is reviewed in line-aligned parts, without the context" },
+                "use_tools": { "type": "boolean", "description": "Let the model call local_map and get_context before answering (default: false)" },
+                "attachments": { "type": "array", "description": "Images (PNG, JPEG, GIF, WebP; max 5 MB) or PDFs (max 32 MB) for vision-capable models", "items": { "type": "object", "properties": { "path": { "type": "string" }, "data": { "type": "string" }, "media_type": { "type": "string" }, "name": { "type": "string" } } } },
+                "format": { "type": "string", "enum": ["markdown", "json"], "description": "Review as markdown (default) or as JSON with summary, issues, improvements and positives" },
+                "response_schema": { "type": "object", "description": "Custom JSON schema (object root) for the review; implies JSON output" },
+                "reasoning_effort": { "type": "string", "enum": ["low", "medium", "high"], "description": "Reasoning effort for models that support it (OpenAI reasoning models, Claude extended thinking)" },
+                "thinking_budget": { "type": "integer", "minimum": 1024, "description": "Extended thinking budget in tokens (Claude); derived from reasoning_effort when omitted" },
+                "temperature": { "type": "number", "minimum": 0, "maximum": 2, "description": "Sampling temperature, at most 1 for Claude (default: 0.3, or unset when top_p is given)" },
+                "top_p": { "type": "number", "exclusiveMinimum": 0, "maximum": 1, "description": "Nucleus sampling probability mass" },
+                "top_k": { "type": "integer", "minimum": 1, "description": "Sample from the k most likely tokens (Anthropic only)" },
+                "stop": { "type": "array", "items": { "type": "string" }, "description": "Stop sequences (OpenAI uses the first 4)" },
+                "seed": { "type": "integer", "description": "Seed for best-effort deterministic sampling (OpenAI only)" },
+                "presence_penalty": { "type": "number", "minimum": -2, "maximum": 2, "description": "Penalty for tokens already present (OpenAI only)" },
+                "frequency_penalty": { "type": "number", "minimum": -2, "maximum": 2, "description": "Penalty proportional to token frequency (OpenAI only)" },
+                "user": { "type": "string", "description": "End-user id passed to the provider for abuse monitoring" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            },
+            "required": ["code"]
+        }
+    })
+}
+
+fn switch_model() -> Value {
+    json!({
+        "name": "switch_model",
+        "description": "Switch between AI providers (anthropic/openai)",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "provider": { "type": "string", "description": "Provider name: anthropic or openai" },
+                "model": { "type": "string", "description": "Specific model (optional)" }
+            },
+            "required": ["provider"]
+        }
+    })
+}
+
+fn list_models() -> Value {
+    json!({
+        "name": "list_models",
+        "description": "List all available models from all providers, with context window, output limit, capabilities and pricing",
+        "inputSchema": {
+            "type": "object",
+            "properties": {}
+        }
+    })
+}
+
+fn add_context() -> Value {
+    json!({
+        "name": "add_context",
+        "description": "Add context (files, notes, metadata, image/PDF attachments) to the conversation",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "type": { "type": "string", "enum": ["file", "note", "metadata", "attachment"] },
+                "path": { "type": "string", "description": "File path; without content, a file, directory or glob (e.g. src/**/*.rs) read by the server. For attachments, a PNG, JPEG, GIF, WebP or PDF relative to the workspace root" },
+                "content": { "type": "string", "description": "File content; omit to have the server read path" },
+                "note": { "type": "string" },
+                "key": { "type": "string" },
+                "value": { "type": "string" },
+                "data": { "type": "string", "description": "Base64 attachment data (instead of path)" },
+                "media_type": { "type": "string", "description": "Media type of base64 data, e.g. image/png or application/pdf" },
+                "name": { "type": "string", "description": "Attachment name (required with data)" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            },
+            "required": ["type"]
+        }
+    })
+}
+
+fn get_context() -> Value {
+    json!({
+        "name": "get_context",
+        "description": "Get all context of the session; files read from disk report whether they changed since (stale)",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn clear_context() -> Value {
+    json!({
+        "name": "clear_context",
+        "description": "Clear all context of the session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn remove_context() -> Value {
+    json!({
+        "name": "remove_context",
+        "description": "Remove one file, note, metadata key or attachment from the context",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "type": { "type": "string", "enum": ["file", "note", "metadata", "attachment"] },
+                "path": { "type": "string", "description": "File to remove" },
+                "index": { "type": "integer", "minimum": 0, "description": "Note to remove, by its index in list_context" },
+                "key": { "type": "string", "description": "Metadata key to remove" },
+                "name": { "type": "string", "description": "Attachment to remove" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            },
+            "required": ["type"]
+        }
+    })
+}
+
+fn list_context() -> Value {
+    json!({
+        "name": "list_context",
+        "description": "List context items with their sizes and token estimates, without their contents",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "model": { "type": "string", "description": "Model whose tokenizer is used for the estimates (default: the current model)" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn set_context_priority() -> Value {
+    json!({
+        "name": "set_context_priority",
+        "description": "Pin a context file or attachment, or set its priority for when the prompt budget forces dropping context",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "description": "Path of a context file or name of an attachment" },
+                "pinned": { "type": "boolean", "description": "Include before all other context" },
+                "priority": { "type": "integer", "description": "Higher priorities are kept first (default: 0)" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            },
+            "required": ["name"]
+        }
+    })
+}
+
+fn refresh_context() -> Value {
+    json!({
+        "name": "refresh_context",
+        "description": "Re-read context files that changed on disk since they were added",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "paths": { "type": "array", "items": { "type": "string" }, "description": "Context files to refresh (default: all files read from disk)" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn chat() -> Value {
+    json!({
+        "name": "chat",
+        "description": "Send a message in a multi-turn chat that keeps its history on the server; edit or regenerate the last turn",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "message": { "type": "string", "description": "User message (required for send and edit)" },
+                "action": { "type": "string", "enum": ["send", "edit", "regenerate"], "description": "send a new message (default), edit the last one, or regenerate the last reply" },
+                "model": { "type": "string", "description": "Specific model to use (optional)" },
+                "max_tokens": { "type": "integer", "description": "Maximum tokens to generate" },
+                "include_context": { "type": "boolean", "description": "Include the conversation context (default: true)" },
+                "attachments": { "type": "array", "description": "Images (PNG, JPEG, GIF, WebP; max 5 MB) or PDFs (max 32 MB) for vision-capable models", "items": { "type": "object", "properties": { "path": { "type": "string" }, "data": { "type": "string" }, "media_type": { "type": "string" }, "name": { "type": "string" } } } },
+                "reasoning_effort": { "type": "string", "enum": ["low", "medium", "high"], "description": "Reasoning effort for models that support it" },
+                "thinking_budget": { "type": "integer", "minimum": 1024, "description": "Extended thinking budget in tokens (Claude)" },
+                "temperature": { "type": "number", "minimum": 0, "maximum": 2, "description": "Sampling temperature, at most 1 for Claude (default: 0.7, or unset when top_p is given)" },
+                "top_p": { "type": "number", "exclusiveMinimum": 0, "maximum": 1 },
+                "top_k": { "type": "integer", "minimum": 1 },
+                "stop": { "type": "array", "items": { "type": "string" } },
+                "seed": { "type": "integer" },
+                "presence_penalty": { "type": "number", "minimum": -2, "maximum": 2 },
+                "frequency_penalty": { "type": "number", "minimum": -2, "maximum": 2 },
+                "user": { "type": "string" },
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn get_chat_history() -> Value {
+    json!({
+        "name": "get_chat_history",
+        "description": "Get the chat turns of the session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn clear_chat_history() -> Value {
+    json!({
+        "name": "clear_chat_history",
+        "description": "Clear the chat history of the session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "session_id": { "type": "string", "description": "Session to work in (default: the current session; created on first use)" }
+            }
+        }
+    })
+}
+
+fn create_session() -> Value {
+    json!({
+        "name": "create_session",
+        "description": "Create a named session with its own context and chat history",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Session id (letters, digits, '-', '_' and '.')" }
+            },
+            "required": ["id"]
+        }
+    })
+}
+
+fn list_sessions() -> Value {
+    json!({
+        "name": "list_sessions",
+        "description": "List sessions with their context and history sizes",
+        "inputSchema": {
+            "type": "object",
+            "properties": {}
+        }
+    })
+}
+
+fn switch_session() -> Value {
+    json!({
+        "name": "switch_session",
+        "description": "Make a session current for calls that name none",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" }
+            },
+            "required": ["id"]
+        }
+    })
+}
+
+fn rename_session() -> Value {
+    json!({
+        "name": "rename_session",
+        "description": "Rename a session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "new_id": { "type": "string" }
+            },
+            "required": ["id", "new_id"]
+        }
+    })
+}
+
+fn fork_session() -> Value {
+    json!({
+        "name": "fork_session",
+        "description": "Copy a session's context and chat history into a new session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Session to copy (default: the current session)" },
+                "new_id": { "type": "string" },
+                "switch": { "type": "boolean", "description": "Make the new session current (default: false)" }
+            },
+            "required": ["new_id"]
+        }
+    })
+}
+
+fn delete_session() -> Value {
+    json!({
+        "name": "delete_session",
+        "description": "Delete a session; the default session cannot be deleted",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" }
+            },
+            "required": ["id"]
+        }
+    })
+}
+
+fn export_session() -> Value {
+    json!({
+        "name": "export_session",
+        "description": "Export a session's context and chat history as JSON, to share it or keep a copy",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string", "description": "Session to export (default: the current session)" },
+                "path": { "type": "string", "description": "File to write, relative to the workspace root; the session is returned when omitted" },
+                "overwrite": { "type": "boolean", "description": "Replace path if it already exists (default: false)" }
+            }
+        }
+    })
+}
+
+fn import_session() -> Value {
+    json!({
+        "name": "import_session",
+        "description": "Import a session written by export_session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "description": "Exported session file, relative to the workspace root" },
+                "session": { "type": "object", "description": "Exported session (instead of path)" },
+                "id": { "type": "string", "description": "Id to import as (default: the exported id)" },
+                "overwrite": { "type": "boolean", "description": "Replace an existing session with the same id (default: false)" }
+            }
+        }
+    })
+}
+
+fn usage_report() -> Value {
+    json!({
+        "name": "usage_report",
+        "description": "Report token usage, cost and latency from the usage ledger, grouped by day, provider, model and/or tool",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "group_by": {
+                    "type": "array",
+                    "items": { "type": "string", "enum": ["day", "provider", "model", "tool"] },
+                    "description": "Dimensions to group by (default: [\"day\"]; [] for grand totals only)"
+                },
+                "from": { "type": "string", "description": "Inclusive start date, YYYY-MM-DD (UTC)" },
+                "to": { "type": "string", "description": "Inclusive end date, YYYY-MM-DD (UTC)" },
+                "provider": { "type": "string", "description": "Only include this provider" },
+                "model": { "type": "string", "description": "Only include this model" },
+                "tool": { "type": "string", "description": "Only include this tool" }
+            }
+        }
+    })
+}
+
+fn budget_status() -> Value {
+    json!({
+        "name": "budget_status",
+        "description": "Show configured spending limits, usage and remaining budget per provider for today and this server session",
+        "inputSchema": {
+            "type": "object",
+            "properties": {}
+        }
+    })
+}
+
+fn local_map() -> Value {
+    json!({
+        "name": "local_map",
+        "description": "Enumerate files and directories from a starting path with depth control",
+        "inputSchema": {
+            "type": "object",
+            "properties": {
+                "path": {
+                    "type": "string",
+                    "description": "Starting path (default: current directory)",
+                    "default": "."
+                },
+                "depth": {
+                    "type": "integer",
+                    "description": "Maximum depth to traverse (0-6, default: 2)",
+                    "minimum": 0,
+                    "maximum": 6,
+                    "default": 2
+                },
+                "follow_symlinks": {
+                    "type": "boolean",
+                    "description": "Follow symbolic links (default: false)",
+                    "default": false
+                },
+                "include": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Only list files matching one of these globs, e.g. [\"*.rs\", \"docs/**\"]"
+                },
+                "exclude": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Leave out files and directories matching one of these globs"
+                },
+                "show_hidden": {
+                    "type": "boolean",
+                    "description": "List entries whose name starts with '.' (default: false)",
+                    "default": false
+                },
+                "gitignore": {
+                    "type": "boolean",
+                    "description": "Honor .gitignore, .ignore and global git excludes (default: true)",
+                    "default": true
+                },
+                "skip": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "description": "Directory names never entered (default: .git, node_modules, __pycache__, venv, .venv); [] to enter all"
+                },
+                "timeout_ms": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Wall-clock limit for the walk (default: 2000, at most the server's local_map.max_timeout_secs)"
+                },
+                "max_entries": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "Most entries in one page, also accepted as page_size (default: 8000, at most the server's local_map.max_entries)"
+                },
+                "cursor": {
+                    "type": "string",
+                    "description": "next_cursor from the previous page, to continue the listing"
+                },
+                "details": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "enum": ["modified", "permissions", "language", "lines", "git_status", "dir_size"]
+                    },
+                    "description": "Extra per-entry details to collect; dir_size sets directories' size_bytes to the total of the files listed below them"
+                },
+                "format": {
+                    "type": "string",
+                    "enum": ["json", "tree", "paths"],
+                    "description": "json: entry objects; tree: indented text like `tree`; paths: relative paths, directories ending in '/' (default: json)",
+                    "default": "json"
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tools_have_object_schemas() {
+        let tools = tools();
+        let mut names: Vec<&str> = tools.iter().filter_map(|t| t["name"].as_str()).collect();
+        assert_eq!(names.len(), tools.len());
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), tools.len());
+        for tool in &tools {
+            assert!(tool["description"].is_string());
+            assert_eq!(tool["inputSchema"]["type"], "object");
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use super::context::{Attachment, ConversationContext};
+use super::{compaction, prompt, ModelClient, ToolResponse};
+use crate::providers::tokens::Tokenizer;
+use crate::providers::{
+    CompletionRequest, ContentPart, Message, Reasoning, ReasoningEffort, SamplingParams,
+};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const SYSTEM_PROMPT: &str = "You are an expert software engineer chatting with a developer \
+     from their editor. Answer precisely, include code when it helps, and build on the \
+     earlier turns of the conversation.";
+
+/// One exchange of a chat session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub user: Message,
+    pub assistant: Message,
+    pub model: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Message history of a chat session, kept next to its conversation
+/// context.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatHistory {
+    turns: Vec<ChatTurn>,
+}
+
+impl ChatHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn turns(&self) -> &[ChatTurn] {
+        &self.turns
+    }
+
+    pub fn push(&mut self, turn: ChatTurn) {
+        self.turns.push(turn);
+    }
+
+    pub fn pop(&mut self) -> Option<ChatTurn> {
+        self.turns.pop()
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ChatAction {
+    /// Add a new turn
+    #[default]
+    Send,
+    /// Replace the last user message and answer it again
+    Edit,
+    /// Answer the last user message again
+    Regenerate,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatArgs {
+    message: Option<String>,
+    #[serde(default)]
+    action: ChatAction,
+    model: Option<String>,
+    max_tokens: Option<u32>,
+    /// Include the shared conversation context (files, notes, metadata)
+    #[serde(default = "default_true")]
+    include_context: bool,
+    /// Images or PDFs sent along with the message
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    reasoning_effort: Option<ReasoningEffort>,
+    thinking_budget: Option<u32>,
+    temperature: Option<f32>,
+    #[serde(flatten)]
+    sampling: SamplingParams,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How much of the history made it into the prompt.
+#[derive(Debug, Serialize)]
+struct HistoryReport {
+    included_turns: usize,
+    /// Older turns sent as a summary
+    summarized_turns: usize,
+    omitted_turns: usize,
+}
+
+pub async fn execute(
+    args: serde_json::Value,
+    client: ModelClient,
+    context: Arc<RwLock<ConversationContext>>,
+    history: Arc<RwLock<ChatHistory>>,
+) -> Result<ToolResponse> {
+    let args: ChatArgs = serde_json::from_value(args)?;
+    args.sampling.validate()?;
+
+    // Work from a copy so the history is not locked while the model answers
+    let turns = history.read().await.turns().to_vec();
+    let turns = turns.as_slice();
+    let (earlier, user_message) = match args.action {
+        ChatAction::Send | ChatAction::Edit => {
+            let Some(text) = args.message else {
+                bail!("'message' is required");
+            };
+            if args.action == ChatAction::Edit && turns.is_empty() {
+                bail!("There is no previous turn to edit");
+            }
+            let mut message = Message::new("user", text);
+            for attachment in args.attachments {
+                message.content.push(attachment.load()?.part);
+            }
+            let earlier = match args.action {
+                ChatAction::Edit => &turns[..turns.len() - 1],
+                _ => turns,
+            };
+            (earlier, message)
+        }
+        ChatAction::Regenerate => {
+            if args.message.is_some() || !args.attachments.is_empty() {
+                bail!("'regenerate' reuses the last message; use 'edit' to change it");
+            }
+            let Some((last, earlier)) = turns.split_last() else {
+                bail!("There is no previous turn to regenerate");
+            };
+            (earlier, last.user.clone())
+        }
+    };
+
+    let model = match args.model {
+        Some(model) => model,
+        None => client.default_model().await,
+    };
+    let mut request = CompletionRequest {
+        messages: vec![
+            Message {
+                cache: true,
+                ..Message::new("system", SYSTEM_PROMPT)
+            },
+            user_message.clone(),
+        ],
+        model: model.clone(),
+        max_tokens: args.max_tokens,
+        temperature: args.sampling.temperature_or(args.temperature, 0.7),
+        tools: Vec::new(),
+        response_format: None,
+        reasoning: Reasoning::new(args.reasoning_effort, args.thinking_budget),
+        sampling: args.sampling,
+    };
+
+    // Keep the most recent turns that fit; the older ones are replaced by a
+    // summary when the summary model can make one, and the conversation
+    // context gets what is left.
+    let meta = client.registry.resolve(&model);
+    let budget = prompt::remaining_tokens(&request, &meta);
+    let mut kept = recent_turns(earlier, &model, budget);
+    let mut summary = None;
+    let mut summarized_turns = 0;
+    if kept < earlier.len() {
+        let reserved = budget.saturating_sub(compaction::SUMMARY_RESERVE_TOKENS);
+        let fitting = recent_turns(earlier, &model, reserved);
+        // Whole blocks counted from the first turn, so that their summaries
+        // are reused as the conversation grows
+        let block = compaction::SUMMARY_BLOCK_TURNS;
+        let older = ((earlier.len() - fitting).div_ceil(block) * block).min(earlier.len());
+        let blocks: Vec<String> = earlier[..older].chunks(block).map(transcript).collect();
+        if let Some(text) =
+            compaction::summarize_blocks(&client, &context, "conversation turns", &blocks).await
+        {
+            kept = earlier.len() - older;
+            summarized_turns = older;
+            summary = Some(Message::new(
+                "system",
+                format!(
+                    "Summary of the {} earlier turns of this conversation:\n{}",
+                    summarized_turns, text
+                ),
+            ));
+        }
+    }
+    let mut past: Vec<Message> = summary
+        .into_iter()
+        .chain(
+            earlier[earlier.len() - kept..]
+                .iter()
+                .flat_map(|turn| [turn.user.clone(), turn.assistant.clone()]),
+        )
+        .collect();
+    if let Some(last) = past.last_mut() {
+        last.cache = true;
+    }
+    request.messages.splice(1..1, past);
+    let history_report = HistoryReport {
+        included_turns: kept,
+        summarized_turns,
+        omitted_turns: earlier.len() - kept - summarized_turns,
+    };
+
+    let mut context_report = None;
+    if args.include_context {
+        let report = compaction::attach_context(&client, &mut request, &context, &meta).await;
+        context_report = Some(report);
+    }
+
+    let response = client.complete("chat", request).await?;
+
+    // An empty reply would leave an assistant message providers reject
+    let stored = !response.content.is_empty();
+    let mut history = history.write().await;
+    if stored {
+        // Replace the turn answered again, unless another call changed the
+        // history in the meantime
+        let answered = turns.last().map(|turn| turn.timestamp);
+        if args.action != ChatAction::Send
+            && history.turns().last().map(|turn| turn.timestamp) == answered
+        {
+            history.pop();
+        }
+        history.push(ChatTurn {
+            user: user_message,
+            assistant: Message::new("assistant", response.content.clone()),
+            model: response.model.clone(),
+            timestamp: Utc::now(),
+        });
+    }
+
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "reply": response.content,
+            "model": response.model,
+            "usage": response.usage,
+            "stop_reason": response.stop_reason,
+            "thinking": response.thinking_text(),
+            "context": context_report,
+            "history": history_report,
+            "stored": stored,
+            "turns": history.turns().len(),
+        }),
+        error: None,
+    })
+}
+
+/// The text of `turns`, for summarizing.
+fn transcript(turns: &[ChatTurn]) -> String {
+    turns
+        .iter()
+        .map(|turn| {
+            format!(
+                "User: {}\n\nAssistant: {}\n\n",
+                turn.user.text(),
+                turn.assistant.text()
+            )
+        })
+        .collect()
+}
+
+/// Number of most recent turns that fit in `budget` tokens.
+fn recent_turns(turns: &[ChatTurn], model: &str, budget: u32) -> usize {
+    let tokenizer = Tokenizer::for_model(model);
+    let mut budget = budget;
+    let mut kept = 0;
+    for turn in turns.iter().rev() {
+        let tokens = tokenizer.count_message(&turn.user) + tokenizer.count_message(&turn.assistant);
+        if tokens > budget {
+            break;
+        }
+        budget -= tokens;
+        kept += 1;
+    }
+    kept
+}
+
+pub async fn get_history(history: Arc<RwLock<ChatHistory>>) -> Result<ToolResponse> {
+    let history = history.read().await;
+    let turns: Vec<_> = history
+        .turns()
+        .iter()
+        .map(|turn| {
+            serde_json::json!({
+                "user": turn.user.text(),
+                "assistant": turn.assistant.text(),
+                "attachments": turn.user.content.iter().filter(|part| !matches!(part, ContentPart::Text { .. })).count(),
+                "model": turn.model,
+                "timestamp": turn.timestamp,
+            })
+        })
+        .collect();
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({ "turns": turns }),
+        error: None,
+    })
+}
+
+pub async fn clear_history(history: Arc<RwLock<ChatHistory>>) -> Result<ToolResponse> {
+    history.write().await.clear();
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": "Chat history cleared",
+        }),
+        error: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_turns_fit_budget() {
+        let turn = |text: &str| ChatTurn {
+            user: Message::new("user", text),
+            assistant: Message::new("assistant", text),
+            model: "gpt-4o".to_string(),
+            timestamp: Utc::now(),
+        };
+        let turns = vec![
+            turn(&"old context ".repeat(500)),
+            turn("short follow-up"),
+            turn("another one"),
+        ];
+        assert_eq!(recent_turns(&turns, "gpt-4o", 100), 2);
+        assert_eq!(recent_turns(&turns, "gpt-4o", 100_000), 3);
+        assert_eq!(recent_turns(&turns, "gpt-4o", 0), 0);
+
+        let mut history = ChatHistory::new();
+        history.push(turn("hi"));
+        assert_eq!(history.pop().unwrap().user.text(), "hi");
+        assert!(history.turns().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_edit_and_regenerate() {
+        use crate::providers::scripted::ScriptedProvider;
+        use crate::providers::StopReason;
+
+        let provider = ScriptedProvider::new(false)
+            .reply("first", StopReason::EndTurn)
+            .reply("second", StopReason::EndTurn)
+            .reply("second, edited", StopReason::EndTurn)
+            .reply("second, again", StopReason::EndTurn)
+            .reply("", StopReason::MaxTokens);
+        let client = ModelClient::scripted(provider.clone());
+        let context = Arc::new(RwLock::new(ConversationContext::new()));
+        let history = Arc::new(RwLock::new(ChatHistory::new()));
+        let chat = |args: serde_json::Value| {
+            let mut args = args;
+            args["include_context"] = false.into();
+            execute(args, client.clone(), context.clone(), history.clone())
+        };
+        let texts = |history: &ChatHistory| -> Vec<(String, String)> {
+            history
+                .turns()
+                .iter()
+                .map(|turn| (turn.user.text(), turn.assistant.text()))
+                .collect()
+        };
+        let pair = |user: &str, assistant: &str| (user.to_string(), assistant.to_string());
+
+        chat(serde_json::json!({ "message": "one" })).await.unwrap();
+        chat(serde_json::json!({ "message": "two" })).await.unwrap();
+        let result = chat(serde_json::json!({ "action": "edit", "message": "two!" }))
+            .await
+            .unwrap();
+        assert_eq!(result.result["reply"], "second, edited");
+        assert_eq!(
+            texts(&*history.read().await),
+            [pair("one", "first"), pair("two!", "second, edited")]
+        );
+        // The edited message replaces the old one in the prompt
+        let prompt: Vec<String> = provider.requests()[2]
+            .messages
+            .iter()
+            .map(Message::text)
+            .collect();
+        assert_eq!(prompt[1..], ["one", "first", "two!"]);
+
+        assert!(
+            chat(serde_json::json!({ "action": "regenerate", "message": "x" }))
+                .await
+                .is_err()
+        );
+        chat(serde_json::json!({ "action": "regenerate" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            texts(&*history.read().await),
+            [pair("one", "first"), pair("two!", "second, again")]
+        );
+        assert_eq!(
+            provider.requests()[3].messages.last().unwrap().text(),
+            "two!"
+        );
+
+        // An empty reply is returned but not stored
+        let result = chat(serde_json::json!({ "message": "three" }))
+            .await
+            .unwrap();
+        assert_eq!(result.result["stored"], false);
+        assert_eq!(history.read().await.turns().len(), 2);
+    }
+}
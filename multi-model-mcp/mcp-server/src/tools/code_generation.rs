@@ -1,6 +1,8 @@
-use super::ToolResponse;
-use crate::providers::{CompletionRequest, Message, ProviderType};
-use anyhow::Result;
+use super::context::{Attachment, ConversationContext};
+use super::{compaction, continuation, structured, tool_loop, ModelClient, ToolResponse};
+use crate::config::LocalMapConfig;
+use crate::providers::{CompletionRequest, Message, Reasoning, ReasoningEffort, SamplingParams};
+use anyhow::{bail, Result};
 use serde::Deserialize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -12,13 +14,41 @@ struct GenerateCodeArgs {
     #[serde(default)]
     context: Vec<String>,
     model: Option<String>,
+    max_tokens: Option<u32>,
+    /// Append the shared conversation context (files, notes, metadata)
+    #[serde(default)]
+    include_context: bool,
+    /// Let the model call local_map and get_context while answering
+    #[serde(default)]
+    use_tools: bool,
+    /// Images or PDFs sent along with the prompt
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// JSON schema the reply must match, e.g. for files plus explanations
+    response_schema: Option<serde_json::Value>,
+    /// How much a reasoning model should think before answering
+    reasoning_effort: Option<ReasoningEffort>,
+    /// Token budget for extended thinking (Claude)
+    thinking_budget: Option<u32>,
+    temperature: Option<f32>,
+    /// Follow-up requests made while the answer stops at max_tokens
+    max_continuations: Option<u32>,
+    /// top_p, top_k, stop, seed, penalties and user
+    #[serde(flatten)]
+    sampling: SamplingParams,
 }
 
 pub async fn execute(
     args: serde_json::Value,
-    provider: Arc<RwLock<ProviderType>>,
+    client: ModelClient,
+    context: Arc<RwLock<ConversationContext>>,
+    local_map: &LocalMapConfig,
 ) -> Result<ToolResponse> {
     let args: GenerateCodeArgs = serde_json::from_value(args)?;
+    args.sampling.validate()?;
+    if args.response_schema.is_some() && args.use_tools {
+        bail!("use_tools cannot be combined with response_schema");
+    }
 
     let language = args.language.unwrap_or_else(|| "generic".to_string());
     let mut system_message = format!(
@@ -33,35 +63,91 @@ pub async fn execute(
         }
     }
 
+    let mut prompt_message = Message::new("user", args.prompt);
+    for attachment in args.attachments {
+        prompt_message.content.push(attachment.load()?.part);
+    }
+
     let messages = vec![
         Message {
-            role: "system".to_string(),
-            content: system_message,
-        },
-        Message {
-            role: "user".to_string(),
-            content: args.prompt,
+            cache: true,
+            ..Message::new("system", system_message)
         },
+        prompt_message,
     ];
 
-    let model = args.model.unwrap_or_else(|| {
-        // Default models
-        match provider.blocking_read().name() {
-            "anthropic" => "claude-3-5-sonnet-20241022".to_string(),
-            "openai" => "gpt-4-turbo-preview".to_string(),
-            _ => "default".to_string(),
-        }
-    });
+    let model = match args.model {
+        Some(model) => model,
+        None => client.default_model().await,
+    };
 
-    let request = CompletionRequest {
+    let mut request = CompletionRequest {
         messages,
         model,
-        max_tokens: Some(4096),
-        temperature: Some(0.7),
+        max_tokens: args.max_tokens,
+        temperature: args.sampling.temperature_or(args.temperature, 0.7),
+        tools: Vec::new(),
+        response_format: None,
+        reasoning: Reasoning::new(args.reasoning_effort, args.thinking_budget),
+        sampling: args.sampling,
     };
 
-    let provider = provider.read().await;
-    let response = provider.complete(request).await?;
+    if args.use_tools {
+        request.tools = tool_loop::definitions();
+    }
+
+    let mut context_report = None;
+    if args.include_context {
+        let meta = client.registry.resolve(&request.model);
+        let report = compaction::attach_context(&client, &mut request, &context, &meta).await;
+        context_report = Some(report);
+    }
+
+    if let Some(schema) = args.response_schema {
+        let structured =
+            structured::complete(&client, "generate_code", request, "generated_code", schema)
+                .await?;
+        let response = structured.response;
+        return Ok(ToolResponse {
+            success: true,
+            result: serde_json::json!({
+                "code": structured.value,
+                "model": response.model,
+                "usage": response.usage,
+                "stop_reason": response.stop_reason,
+                "thinking": response.thinking_text(),
+                "context": context_report,
+                "attempts": structured.attempts,
+            }),
+            error: None,
+        });
+    }
+
+    if args.use_tools {
+        let (response, tool_calls) =
+            tool_loop::run(&client, "generate_code", request, context, local_map).await?;
+        return Ok(ToolResponse {
+            success: true,
+            result: serde_json::json!({
+                "code": response.content,
+                "model": response.model,
+                "usage": response.usage,
+                "stop_reason": response.stop_reason,
+                "thinking": response.thinking_text(),
+                "context": context_report,
+                "tool_calls": tool_calls,
+            }),
+            error: None,
+        });
+    }
+
+    let max_continuations = args
+        .max_continuations
+        .unwrap_or(continuation::DEFAULT_MAX_CONTINUATIONS);
+    let continued =
+        continuation::complete(&client, "generate_code", request, max_continuations).await?;
+    let truncated = continued.truncated();
+    let response = continued.response;
 
     Ok(ToolResponse {
         success: true,
@@ -69,6 +155,11 @@ pub async fn execute(
             "code": response.content,
             "model": response.model,
             "usage": response.usage,
+            "stop_reason": response.stop_reason,
+            "thinking": response.thinking_text(),
+            "context": context_report,
+            "continuations": continued.continuations,
+            "truncated": truncated,
         }),
         error: None,
     })
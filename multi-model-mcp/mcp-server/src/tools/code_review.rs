@@ -1,6 +1,11 @@
-use super::ToolResponse;
-use crate::providers::{CompletionRequest, Message, ProviderType};
-use anyhow::Result;
+use super::context::{Attachment, ConversationContext};
+use super::{compaction, prompt, structured, tool_loop, ModelClient, ToolResponse};
+use crate::config::LocalMapConfig;
+use crate::providers::tokens::count_tokens;
+use crate::providers::{
+    CompletionRequest, Message, Reasoning, ReasoningEffort, SamplingParams, StopReason, UsageInfo,
+};
+use anyhow::{bail, Result};
 use serde::Deserialize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -11,13 +16,54 @@ struct ReviewCodeArgs {
     language: Option<String>,
     focus: Option<Vec<String>>, // e.g., ["security", "performance", "style"]
     model: Option<String>,
+    max_tokens: Option<u32>,
+    /// Append the shared conversation context (files, notes, metadata)
+    #[serde(default)]
+    include_context: bool,
+    /// Let the model call local_map and get_context while reviewing
+    #[serde(default)]
+    use_tools: bool,
+    /// Images or PDFs (e.g. a UI screenshot) sent along with the code
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// "markdown" (default) or "json" for a review matching the built-in
+    /// review schema
+    #[serde(default = "default_format")]
+    format: String,
+    /// JSON schema the review must match; implies JSON output
+    response_schema: Option<serde_json::Value>,
+    /// How much a reasoning model should think before answering
+    reasoning_effort: Option<ReasoningEffort>,
+    /// Token budget for extended thinking (Claude)
+    thinking_budget: Option<u32>,
+    temperature: Option<f32>,
+    /// top_p, top_k, stop, seed, penalties and user
+    #[serde(flatten)]
+    sampling: SamplingParams,
+}
+
+fn default_format() -> String {
+    "markdown".to_string()
 }
 
 pub async fn execute(
     args: serde_json::Value,
-    provider: Arc<RwLock<ProviderType>>,
+    client: ModelClient,
+    context: Arc<RwLock<ConversationContext>>,
+    local_map: &LocalMapConfig,
 ) -> Result<ToolResponse> {
     let args: ReviewCodeArgs = serde_json::from_value(args)?;
+    args.sampling.validate()?;
+
+    let schema = match (args.format.as_str(), args.response_schema) {
+        (_, Some(schema)) => Some(schema),
+        ("json", None) => Some(structured::review_schema()),
+        ("markdown", None) => None,
+        (other, None) => bail!("Unknown format '{}' (expected markdown or json)", other),
+    };
+    if schema.is_some() && args.use_tools {
+        bail!("use_tools cannot be combined with JSON output");
+    }
 
     let language = args.language.unwrap_or_else(|| "unknown".to_string());
     let mut system_message = format!(
@@ -35,49 +81,209 @@ pub async fn execute(
         }
     }
 
-    system_message.push_str(
-        "\n\nProvide your review in the following format:\n\
-         1. **Summary**: Brief overview of code quality\n\
-         2. **Issues**: List any bugs, security concerns, or anti-patterns\n\
-         3. **Improvements**: Suggestions for optimization and better practices\n\
-         4. **Positive**: What the code does well",
-    );
+    if schema.is_some() {
+        system_message.push_str(
+            "\n\nRespond with a single JSON object matching the provided schema, \
+             with no surrounding text.",
+        );
+    } else {
+        system_message.push_str(
+            "\n\nProvide your review in the following format:\n\
+             1. **Summary**: Brief overview of code quality\n\
+             2. **Issues**: List any bugs, security concerns, or anti-patterns\n\
+             3. **Improvements**: Suggestions for optimization and better practices\n\
+             4. **Positive**: What the code does well",
+        );
+    }
 
-    let messages = vec![
-        Message {
-            role: "system".to_string(),
-            content: system_message,
-        },
-        Message {
-            role: "user".to_string(),
-            content: format!("Please review this code:\n\n```{}\n{}\n```", language, args.code),
-        },
-    ];
-
-    let model = args.model.unwrap_or_else(|| {
-        match provider.blocking_read().name() {
-            "anthropic" => "claude-3-5-sonnet-20241022".to_string(),
-            "openai" => "gpt-4-turbo-preview".to_string(),
-            _ => "default".to_string(),
-        }
-    });
+    let model = match args.model {
+        Some(model) => model,
+        None => client.default_model().await,
+    };
 
-    let request = CompletionRequest {
-        messages,
-        model,
-        max_tokens: Some(4096),
-        temperature: Some(0.3), // Lower temperature for more focused reviews
+    let attachments = args
+        .attachments
+        .into_iter()
+        .map(|attachment| Ok(attachment.load()?.part))
+        .collect::<Result<Vec<_>>>()?;
+
+    let build = |code: &str, header: &str| CompletionRequest {
+        messages: vec![
+            Message {
+                cache: true,
+                ..Message::new("system", system_message.clone())
+            },
+            {
+                let mut message = Message::new(
+                    "user",
+                    format!(
+                        "Please review this code{}:\n\n```{}\n{}\n```",
+                        header, language, code
+                    ),
+                );
+                message.content.extend(attachments.iter().cloned());
+                message
+            },
+        ],
+        model: model.clone(),
+        max_tokens: args.max_tokens,
+        // Lower temperature for more focused reviews
+        temperature: args.sampling.temperature_or(args.temperature, 0.3),
+        tools: Vec::new(),
+        response_format: None,
+        reasoning: Reasoning::new(args.reasoning_effort, args.thinking_budget),
+        sampling: args.sampling.clone(),
     };
 
-    let provider = provider.read().await;
-    let response = provider.complete(request).await?;
+    let meta = client.registry.resolve(&model);
+    let available = prompt::remaining_tokens(&build("", ""), &meta);
+    let code_tokens = count_tokens(&model, &args.code);
+
+    if code_tokens <= available {
+        let mut request = build(&args.code, "");
+        if args.use_tools {
+            request.tools = tool_loop::definitions();
+        }
+        let mut context_report = None;
+        if args.include_context {
+            let report = compaction::attach_context(&client, &mut request, &context, &meta).await;
+            context_report = Some(report);
+        }
+
+        if let Some(schema) = schema {
+            let structured =
+                structured::complete(&client, "review_code", request, "code_review", schema)
+                    .await?;
+            let response = structured.response;
+            return Ok(ToolResponse {
+                success: true,
+                result: serde_json::json!({
+                    "review": structured.value,
+                    "model": response.model,
+                    "usage": response.usage,
+                    "stop_reason": response.stop_reason,
+                    "thinking": response.thinking_text(),
+                    "context": context_report,
+                    "attempts": structured.attempts,
+                }),
+                error: None,
+            });
+        }
+
+        let (response, tool_calls) = if args.use_tools {
+            tool_loop::run(&client, "review_code", request, context, local_map).await?
+        } else {
+            (client.complete("review_code", request).await?, Vec::new())
+        };
+
+        return Ok(ToolResponse {
+            success: true,
+            result: serde_json::json!({
+                "review": response.content,
+                "model": response.model,
+                "usage": response.usage,
+                "stop_reason": response.stop_reason,
+                "thinking": response.thinking_text(),
+                "context": context_report,
+                "tool_calls": tool_calls,
+            }),
+            error: None,
+        });
+    }
+
+    // Too large for one request: review line-aligned chunks separately,
+    // leaving out the conversation context and tools to make room for the
+    // code. A requested context is reported as skipped.
+    if available == 0 {
+        bail!(
+            "{} has no room for code after the review prompt and max_tokens",
+            model
+        );
+    }
+    let chunks = prompt::chunk_lines(&args.code, &model, available);
+    let total_lines = args.code.lines().count().max(1);
+    let mut sections = Vec::new();
+    let mut thinking = Vec::new();
+    let mut parts = Vec::new();
+    let mut usage = UsageInfo::default();
+    let mut response_model = model.clone();
+    let mut stop_reason = StopReason::EndTurn;
+    for (index, (first_line, chunk)) in chunks.iter().enumerate() {
+        let last_line = (first_line + chunk.lines().count().max(1) - 1).min(total_lines);
+        let header = format!(
+            " (part {} of {}, lines {}-{})",
+            index + 1,
+            chunks.len(),
+            first_line,
+            last_line
+        );
+        let response = match &schema {
+            Some(schema) => {
+                let structured = structured::complete(
+                    &client,
+                    "review_code",
+                    build(chunk, &header),
+                    "code_review",
+                    schema.clone(),
+                )
+                .await?;
+                parts.push(serde_json::json!({
+                    "first_line": first_line,
+                    "last_line": last_line,
+                    "review": structured.value,
+                }));
+                structured.response
+            }
+            None => {
+                client
+                    .complete("review_code", build(chunk, &header))
+                    .await?
+            }
+        };
+        if let Some(part) = &response.usage {
+            usage.add(part);
+        }
+        if let Some(text) = response.thinking_text() {
+            thinking.push(format!(
+                "## Part {}/{}\n\n{}",
+                index + 1,
+                chunks.len(),
+                text
+            ));
+        }
+        response_model = response.model;
+        // Surface the first part that did not finish normally
+        if stop_reason == StopReason::EndTurn {
+            stop_reason = response.stop_reason;
+        }
+        sections.push(format!(
+            "## Part {}/{} (lines {}-{})\n\n{}",
+            index + 1,
+            chunks.len(),
+            first_line,
+            last_line,
+            response.content
+        ));
+    }
+
+    let review = if schema.is_some() {
+        serde_json::Value::Array(parts)
+    } else {
+        serde_json::Value::String(sections.join("\n\n"))
+    };
 
     Ok(ToolResponse {
         success: true,
         result: serde_json::json!({
-            "review": response.content,
-            "model": response.model,
-            "usage": response.usage,
+            "review": review,
+            "model": response_model,
+            "usage": usage,
+            "stop_reason": stop_reason,
+            "thinking": (!thinking.is_empty()).then(|| thinking.join("\n\n")),
+            "chunks": chunks.len(),
+            "context": args.include_context.then(|| serde_json::json!({
+                "skipped": "the code was reviewed in parts, which leaves no room for the context",
+            })),
         }),
         error: None,
     })
@@ -0,0 +1,323 @@
+use super::context::ConversationContext;
+use super::prompt::{self, ContextReport};
+use super::ModelClient;
+use crate::providers::tokens::Tokenizer;
+use crate::providers::{CompletionRequest, Message, ModelMetadata};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use tokio::sync::RwLock;
+
+/// Most recent notes always sent verbatim; older ones may be summarized.
+pub const RECENT_NOTES: usize = 3;
+
+/// Longest summary requested from the summary model.
+const SUMMARY_MAX_TOKENS: u32 = 1024;
+
+/// Prompt tokens set aside for a summary: the summary itself plus its
+/// heading.
+pub const SUMMARY_RESERVE_TOKENS: u32 = SUMMARY_MAX_TOKENS + 64;
+
+/// Older notes shorter than this are not worth a summary request.
+const MIN_SUMMARIZED_TOKENS: u32 = 2 * SUMMARY_MAX_TOKENS;
+
+const SUMMARY_PROMPT: &str = "You condense material that no longer fits a model's context \
+     window. Keep decisions, requirements, names, file paths and open questions; drop \
+     pleasantries and repetition. Reply with the summary only, as terse bullet points.";
+
+/// Start of the declaration lines kept in an outline, after indentation.
+const OUTLINE_PREFIXES: &[&str] = &[
+    // Rust
+    "fn ",
+    "pub ",
+    "pub(",
+    "async fn ",
+    "unsafe fn ",
+    "const fn ",
+    "impl ",
+    "impl<",
+    "trait ",
+    "struct ",
+    "enum ",
+    "type ",
+    "mod ",
+    "macro_rules!",
+    // Python
+    "def ",
+    "async def ",
+    "class ",
+    // JavaScript and TypeScript
+    "function ",
+    "async function ",
+    "export ",
+    "interface ",
+    // Go
+    "func ",
+    "package ",
+    // Java, C# and Kotlin
+    "public ",
+    "private ",
+    "protected ",
+    "internal ",
+    "abstract ",
+    "fun ",
+];
+
+/// Extensions of Markdown files, whose outline is their headings.
+const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown", "mdx"];
+
+/// Turns summarized together when a conversation no longer fits.
+pub const SUMMARY_BLOCK_TURNS: usize = 8;
+
+/// The declaration lines of the file `path` (function signatures, type and
+/// module declarations, or headings in Markdown files), each prefixed with
+/// its line number. `None` when there are none or they are most of the
+/// file anyway.
+pub fn outline(path: &str, content: &str) -> Option<String> {
+    let markdown = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| MARKDOWN_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()));
+    let mut outline = String::new();
+    let mut kept = 0;
+    for (index, line) in content.lines().enumerate() {
+        let code = line.trim_start();
+        let declaration = if markdown {
+            // `#` starts a comment in Python or shell, a heading only here
+            let level = line.len() - line.trim_start_matches('#').len();
+            (1..=6).contains(&level) && line[level..].starts_with(' ')
+        } else {
+            OUTLINE_PREFIXES
+                .iter()
+                .any(|prefix| code.starts_with(prefix))
+        };
+        if declaration {
+            outline.push_str(&format!("{:>5}: {}\n", index + 1, line.trim_end()));
+            kept += 1;
+        }
+    }
+    (kept > 0 && outline.len() < content.len() / 2).then_some(outline)
+}
+
+/// Cache key of the summary of `text`.
+pub fn summary_key(kind: &str, text: &str) -> String {
+    format!("{}:{:x}", kind, Sha256::digest(text.as_bytes()))
+}
+
+/// The notes older than the `RECENT_NOTES` most recent ones, as one text.
+pub fn older_notes(ctx: &ConversationContext) -> Option<String> {
+    let older = ctx.notes().len().checked_sub(RECENT_NOTES)?;
+    (older > 0).then(|| {
+        ctx.notes()[..older]
+            .iter()
+            .map(|note| format!("- {}\n", note))
+            .collect()
+    })
+}
+
+/// Summarize `text` with the summary model, reusing the summary cached in
+/// `context` for the same text. Failures are logged and give `None`, so
+/// callers fall back to truncating.
+pub async fn summarize(
+    client: &ModelClient,
+    context: &RwLock<ConversationContext>,
+    kind: &str,
+    text: &str,
+) -> Option<String> {
+    summarize_as(client, context, summary_key(kind, text), kind, text).await
+}
+
+/// Summarize `blocks` of text that only ever grows at the end, such as
+/// conversation turns, one block at a time: each summary folds the next
+/// block into the previous summary and is cached under a key chained from
+/// the previous key, so a later call only summarizes the blocks added
+/// since.
+pub async fn summarize_blocks(
+    client: &ModelClient,
+    context: &RwLock<ConversationContext>,
+    kind: &str,
+    blocks: &[String],
+) -> Option<String> {
+    let mut key = String::new();
+    let keys: Vec<String> = blocks
+        .iter()
+        .map(|block| {
+            key = summary_key(kind, &format!("{}\n{}", key, block));
+            key.clone()
+        })
+        .collect();
+    let (start, mut summary) = {
+        let ctx = context.read().await;
+        keys.iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, key)| Some((index + 1, ctx.summary(key)?.to_string())))
+            .unwrap_or_default()
+    };
+    for (block, key) in blocks.iter().zip(keys).skip(start) {
+        let text = if summary.is_empty() {
+            block.clone()
+        } else {
+            format!(
+                "Summary of the earlier {}:\n{}\n\nThe {} that followed:\n{}",
+                kind, summary, kind, block
+            )
+        };
+        summary = summarize_as(client, context, key, kind, &text).await?;
+    }
+    (!summary.is_empty()).then_some(summary)
+}
+
+async fn summarize_as(
+    client: &ModelClient,
+    context: &RwLock<ConversationContext>,
+    key: String,
+    kind: &str,
+    text: &str,
+) -> Option<String> {
+    if let Some(summary) = context.read().await.summary(&key) {
+        return Some(summary.to_string());
+    }
+
+    let model = client.summary_model().await;
+    let mut request = CompletionRequest {
+        messages: vec![Message::new("system", SUMMARY_PROMPT)],
+        model: model.clone(),
+        max_tokens: Some(SUMMARY_MAX_TOKENS),
+        temperature: Some(0.2),
+        tools: Vec::new(),
+        response_format: None,
+        reasoning: None,
+        sampling: Default::default(),
+    };
+    let room = prompt::remaining_tokens(&request, &client.registry.resolve(&model));
+    let text = Tokenizer::for_model(&model).truncate(text, room.saturating_sub(64));
+    request.messages.push(Message::new(
+        "user",
+        format!("Summarize these {}:\n\n{}", kind, text),
+    ));
+
+    match client.complete("summarize", request).await {
+        Ok(response) if !response.content.trim().is_empty() => {
+            let summary = response.content.trim().to_string();
+            context.write().await.cache_summary(key, summary.clone());
+            Some(summary)
+        }
+        Ok(_) => {
+            tracing::warn!("Summary model returned an empty summary of {}", kind);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Failed to summarize {}: {}", kind, e);
+            None
+        }
+    }
+}
+
+/// Attach the conversation context to `request` like
+/// [`prompt::attach_context`], first summarizing older notes with the
+/// summary model when the context does not fit, and record in the context
+/// how each item was sent.
+pub async fn attach_context(
+    client: &ModelClient,
+    request: &mut CompletionRequest,
+    context: &RwLock<ConversationContext>,
+    meta: &ModelMetadata,
+) -> ContextReport {
+    let budget = prompt::remaining_tokens(request, meta);
+    let older = {
+        let ctx = context.read().await;
+        let tokenizer = Tokenizer::for_model(&request.model);
+        let total: u32 = ctx
+            .files()
+            .values()
+            .chain(ctx.notes())
+            .map(|text| tokenizer.count(text))
+            .sum();
+        older_notes(&ctx)
+            .filter(|older| total > budget && tokenizer.count(older) >= MIN_SUMMARIZED_TOKENS)
+    };
+    if let Some(older) = older {
+        summarize(client, context, "notes", &older).await;
+    }
+
+    let mut ctx = context.write().await;
+    let report = prompt::attach_context(request, &ctx, meta.supports_vision, budget);
+    ctx.record_prompt(&report);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outline_keeps_declarations() {
+        let body = "    let value = compute();\n".repeat(20);
+        let source = format!(
+            "use std::fmt;\n\npub struct Point {{\n    x: i32,\n}}\n\nimpl Point {{\n    pub fn norm(&self) -> i32 {{\n{}    }}\n}}\n",
+            body
+        );
+        let declarations = outline("src/point.rs", &source).unwrap();
+        assert_eq!(
+            declarations,
+            "    3: pub struct Point {\n    7: impl Point {\n    8:     pub fn norm(&self) -> i32 {\n"
+        );
+        assert!(outline("a.rs", "let x = 1;\n".repeat(100).as_str()).is_none());
+        assert!(outline("a.rs", "fn main() {}\n").is_none());
+
+        // `#` lines are headings in Markdown only
+        let script = format!(
+            "# Install the tools\n{}",
+            "apt-get install -y git\n".repeat(10)
+        );
+        assert!(outline("setup.sh", &script).is_none());
+        let readme = format!(
+            "# Title\n{}## Usage\n#not-a-heading\n",
+            "Some prose.\n".repeat(10)
+        );
+        assert_eq!(
+            outline("README.md", &readme).unwrap(),
+            "    1: # Title\n   12: ## Usage\n"
+        );
+
+        let mut ctx = ConversationContext::new();
+        for note in ["a", "b", "c"] {
+            ctx.add_note(note.to_string());
+        }
+        assert!(older_notes(&ctx).is_none());
+        ctx.add_note("d".to_string());
+        assert_eq!(older_notes(&ctx).unwrap(), "- a\n");
+        assert_ne!(summary_key("notes", "a"), summary_key("conversation", "a"));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_blocks_reuses_earlier_blocks() {
+        use crate::providers::scripted::ScriptedProvider;
+        use crate::providers::StopReason;
+
+        let provider = ScriptedProvider::new(false)
+            .reply("summary of a", StopReason::EndTurn)
+            .reply("summary of a and b", StopReason::EndTurn)
+            .reply("summary of a, b and c", StopReason::EndTurn);
+        let client = ModelClient::scripted(provider.clone());
+        let context = RwLock::new(ConversationContext::new());
+        let blocks = ["a".to_string(), "b".to_string(), "c".to_string()];
+
+        let summary = summarize_blocks(&client, &context, "turns", &blocks[..2]).await;
+        assert_eq!(summary.as_deref(), Some("summary of a and b"));
+        assert_eq!(provider.requests().len(), 2);
+
+        // Only the new block is summarized, on top of the cached summary
+        let summary = summarize_blocks(&client, &context, "turns", &blocks).await;
+        assert_eq!(summary.as_deref(), Some("summary of a, b and c"));
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].messages[1]
+            .text()
+            .contains("summary of a and b"));
+
+        let summary = summarize_blocks(&client, &context, "turns", &blocks).await;
+        assert_eq!(summary.as_deref(), Some("summary of a, b and c"));
+        assert_eq!(provider.requests().len(), 3);
+    }
+}
@@ -1,7 +1,12 @@
-use super::ToolResponse;
-use anyhow::Result;
+use super::prompt::ContextReport;
+use super::{files, workspace, ToolResponse};
+use crate::providers::tokens::Tokenizer;
+use crate::providers::{media, ContentPart};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -10,6 +15,138 @@ pub struct ConversationContext {
     files: HashMap<String, String>,
     notes: Vec<String>,
     metadata: HashMap<String, String>,
+    /// Images and PDFs keyed by path or name, sent as message parts
+    #[serde(default)]
+    attachments: BTreeMap<String, ContentPart>,
+    /// Where files read by the server came from, keyed like `files`
+    #[serde(default)]
+    file_sources: BTreeMap<String, FileSource>,
+    /// Pin and priority of files and attachments, keyed by path or name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    priorities: BTreeMap<String, Priority>,
+    /// Summaries made by the summary model, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    summaries: Vec<CachedSummary>,
+    /// How each item was sent in the most recent prompt
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    last_prompt: BTreeMap<String, ContextForm>,
+}
+
+/// Most summaries kept; the oldest is dropped to make room.
+const MAX_SUMMARIES: usize = 32;
+
+/// A summary keyed by the hash of the text it summarizes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedSummary {
+    key: String,
+    summary: String,
+}
+
+/// How a context item was sent to the model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextForm {
+    Verbatim,
+    /// Signature lines only
+    Outline,
+    Truncated,
+    Summarized,
+    Omitted,
+}
+
+/// How a file or attachment ranks when the prompt budget forces dropping
+/// context. Pinned items are included before everything else; the others
+/// go by descending `priority`, then by name.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Priority {
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// The on-disk file a context file was read from, as it was when read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileSource {
+    /// Relative to the workspace root
+    pub path: PathBuf,
+    pub sha256: String,
+    pub modified: Option<DateTime<Utc>>,
+    pub size_bytes: u64,
+}
+
+/// Outcome of re-reading context files from disk.
+#[derive(Debug, Default, Serialize)]
+pub struct RefreshReport {
+    pub refreshed: Vec<String>,
+    /// Files deleted on disk; their last content is kept
+    pub missing: Vec<String>,
+    /// Files that can no longer be read as text
+    pub failed: Vec<files::SkippedFile>,
+}
+
+/// What re-checking a context file against its source found.
+pub enum Recheck {
+    Fresh,
+    Missing,
+    Reloaded(files::LoadedFile),
+    /// The file changed but can no longer be read as text
+    Failed(String),
+}
+
+/// A context file re-checked by [`recheck`], with the source it was
+/// checked against.
+pub struct Rechecked {
+    name: String,
+    source: FileSource,
+    outcome: Recheck,
+}
+
+/// Re-read the files of `sources` that changed since they were added.
+/// Sources are resolved inside the workspace `root` again, so a shared
+/// session cannot read other files. This reads and hashes files, so callers
+/// run it on a blocking thread without holding the context.
+pub fn recheck(root: &Path, sources: Vec<(String, FileSource)>) -> Vec<Rechecked> {
+    sources
+        .into_iter()
+        .map(|(name, source)| {
+            let outcome = match files::status(root, &source) {
+                files::FileStatus::Fresh => Recheck::Fresh,
+                files::FileStatus::Missing => Recheck::Missing,
+                files::FileStatus::Modified => match files::resolve(root, &source)
+                    .map_err(|e| e.to_string())
+                    .and_then(|path| files::read(root, &path))
+                {
+                    Ok(file) => Recheck::Reloaded(file),
+                    Err(reason) => Recheck::Failed(reason),
+                },
+            };
+            Rechecked {
+                name,
+                source,
+                outcome,
+            }
+        })
+        .collect()
+}
+
+/// Sources of the context files of `context` with their status on disk,
+/// checked on a blocking thread.
+async fn statuses(
+    root: PathBuf,
+    context: &RwLock<ConversationContext>,
+) -> Result<BTreeMap<String, (FileSource, files::FileStatus)>> {
+    let sources = context.read().await.file_sources.clone();
+    Ok(tokio::task::spawn_blocking(move || {
+        sources
+            .into_iter()
+            .map(|(name, source)| {
+                let status = files::status(&root, &source);
+                (name, (source, status))
+            })
+            .collect()
+    })
+    .await?)
 }
 
 impl ConversationContext {
@@ -18,13 +155,88 @@ impl ConversationContext {
             files: HashMap::new(),
             notes: Vec::new(),
             metadata: HashMap::new(),
+            attachments: BTreeMap::new(),
+            file_sources: BTreeMap::new(),
+            priorities: BTreeMap::new(),
+            summaries: Vec::new(),
+            last_prompt: BTreeMap::new(),
         }
     }
 
     pub fn add_file(&mut self, path: String, content: String) {
+        self.file_sources.remove(&path);
         self.files.insert(path, content);
     }
 
+    /// Add a file read from disk, remembering where it came from.
+    pub fn add_file_from_disk(&mut self, file: files::LoadedFile) {
+        self.files.insert(file.name.clone(), file.content);
+        self.file_sources.insert(file.name, file.source);
+    }
+
+    /// Sources of the files in `names` (all files read from disk when
+    /// empty), to pass to [`recheck`].
+    pub fn sources(&self, names: &[String]) -> Result<Vec<(String, FileSource)>> {
+        if let Some(name) = names
+            .iter()
+            .find(|name| !self.file_sources.contains_key(*name))
+        {
+            bail!("'{}' is not a context file read from disk", name);
+        }
+        Ok(self
+            .file_sources
+            .iter()
+            .filter(|(name, _)| names.is_empty() || names.contains(name))
+            .map(|(name, source)| (name.clone(), source.clone()))
+            .collect())
+    }
+
+    /// Swap in what [`recheck`] found. A file whose source changed after it
+    /// was checked, because it was added again in the meantime, is left
+    /// alone.
+    pub fn apply_refresh(&mut self, rechecked: Vec<Rechecked>) -> RefreshReport {
+        let mut report = RefreshReport::default();
+        for Rechecked {
+            name,
+            source,
+            outcome,
+        } in rechecked
+        {
+            if self.file_sources.get(&name) != Some(&source) {
+                continue;
+            }
+            match outcome {
+                Recheck::Fresh => {}
+                Recheck::Missing => report.missing.push(name),
+                Recheck::Reloaded(file) => {
+                    self.files.insert(name.clone(), file.content);
+                    self.file_sources.insert(name.clone(), file.source);
+                    report.refreshed.push(name);
+                }
+                Recheck::Failed(reason) => report
+                    .failed
+                    .push(files::SkippedFile { path: name, reason }),
+            }
+        }
+        report
+    }
+
+    /// Forget where files came from when the recorded path is not a plain
+    /// path inside the workspace, as in a session made elsewhere; their
+    /// content is kept. Returns the names of those files.
+    pub fn drop_foreign_sources(&mut self) -> Vec<String> {
+        let foreign: Vec<String> = self
+            .file_sources
+            .iter()
+            .filter(|(_, source)| workspace::relative(&source.path.to_string_lossy()).is_err())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in &foreign {
+            self.file_sources.remove(name);
+        }
+        foreign
+    }
+
     pub fn add_note(&mut self, note: String) {
         self.notes.push(note);
     }
@@ -33,10 +245,173 @@ impl ConversationContext {
         self.metadata.insert(key, value);
     }
 
+    pub fn add_attachment(&mut self, name: String, part: ContentPart) {
+        self.attachments.insert(name, part);
+    }
+
+    /// Remove the file `path`; returns whether it was in the context.
+    pub fn remove_file(&mut self, path: &str) -> bool {
+        self.file_sources.remove(path);
+        if !self.attachments.contains_key(path) {
+            self.priorities.remove(path);
+        }
+        self.files.remove(path).is_some()
+    }
+
+    pub fn remove_note(&mut self, index: usize) -> Option<String> {
+        (index < self.notes.len()).then(|| self.notes.remove(index))
+    }
+
+    pub fn remove_metadata(&mut self, key: &str) -> Option<String> {
+        self.metadata.remove(key)
+    }
+
+    pub fn remove_attachment(&mut self, name: &str) -> bool {
+        if !self.files.contains_key(name) {
+            self.priorities.remove(name);
+        }
+        self.attachments.remove(name).is_some()
+    }
+
+    /// Pin and priority of the file or attachment `name`.
+    pub fn priority(&self, name: &str) -> Priority {
+        self.priorities.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn set_priority(&mut self, name: String, priority: Priority) {
+        if priority == Priority::default() {
+            self.priorities.remove(&name);
+        } else {
+            self.priorities.insert(name, priority);
+        }
+    }
+
+    /// Names ordered as the prompt assembler includes them: pinned first,
+    /// then by descending priority, then by name.
+    pub fn ranked<'a>(&self, names: impl IntoIterator<Item = &'a String>) -> Vec<&'a String> {
+        let mut names: Vec<_> = names.into_iter().collect();
+        names.sort_by_key(|name| {
+            let priority = self.priority(name);
+            (
+                !priority.pinned,
+                std::cmp::Reverse(priority.priority),
+                *name,
+            )
+        });
+        names
+    }
+
+    pub fn files(&self) -> &HashMap<String, String> {
+        &self.files
+    }
+
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
+
+    pub fn attachments(&self) -> &BTreeMap<String, ContentPart> {
+        &self.attachments
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+            && self.notes.is_empty()
+            && self.metadata.is_empty()
+            && self.attachments.is_empty()
+    }
+
     pub fn clear(&mut self) {
         self.files.clear();
         self.notes.clear();
         self.metadata.clear();
+        self.attachments.clear();
+        self.file_sources.clear();
+        self.priorities.clear();
+        self.summaries.clear();
+        self.last_prompt.clear();
+    }
+
+    /// The cached summary stored under `key`, if any.
+    pub fn summary(&self, key: &str) -> Option<&str> {
+        self.summaries
+            .iter()
+            .find(|cached| cached.key == key)
+            .map(|cached| cached.summary.as_str())
+    }
+
+    pub fn cache_summary(&mut self, key: String, summary: String) {
+        self.summaries.retain(|cached| cached.key != key);
+        if self.summaries.len() >= MAX_SUMMARIES {
+            self.summaries.remove(0);
+        }
+        self.summaries.push(CachedSummary { key, summary });
+    }
+
+    /// Remember how each item was sent in the prompt described by `report`.
+    pub fn record_prompt(&mut self, report: &ContextReport) {
+        let forms = [
+            (&report.included, ContextForm::Verbatim),
+            (&report.outlined, ContextForm::Outline),
+            (&report.truncated, ContextForm::Truncated),
+            (&report.summarized, ContextForm::Summarized),
+            (&report.omitted, ContextForm::Omitted),
+        ];
+        self.last_prompt = forms
+            .into_iter()
+            .flat_map(|(names, form)| names.iter().map(move |name| (name.clone(), form)))
+            .collect();
+    }
+}
+
+/// An image or PDF given to a tool, either as a `path` relative to the
+/// workspace root or as base64 `data` with its `media_type` and a `name`.
+#[derive(Debug, Deserialize)]
+pub struct Attachment {
+    path: Option<String>,
+    data: Option<String>,
+    media_type: Option<String>,
+    name: Option<String>,
+}
+
+/// An attachment read and validated by [`Attachment::load`].
+#[derive(Debug)]
+pub struct LoadedAttachment {
+    pub name: String,
+    pub media_type: String,
+    pub part: ContentPart,
+}
+
+impl Attachment {
+    /// Read and validate the attachment.
+    pub fn load(self) -> Result<LoadedAttachment> {
+        match (self.path, self.data, self.media_type) {
+            (Some(path), None, _) => {
+                let resolved = workspace::resolve_within(&workspace::root()?, &path)?;
+                let part = media::from_path(&resolved)?;
+                let media_type = media::media_type_for_path(&resolved).unwrap_or_default();
+                Ok(LoadedAttachment {
+                    name: self.name.unwrap_or(path),
+                    media_type: media_type.to_string(),
+                    part,
+                })
+            }
+            (None, Some(data), Some(media_type)) => {
+                let Some(name) = self.name else {
+                    bail!("'name' is required for attachments given as data");
+                };
+                let part = media::from_base64(&data, &media_type, Some(name.clone()))?;
+                Ok(LoadedAttachment {
+                    name,
+                    media_type,
+                    part,
+                })
+            }
+            _ => bail!("Attachments need either 'path', or 'data' with 'media_type'"),
+        }
     }
 }
 
@@ -49,12 +424,21 @@ struct AddContextArgs {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "type")]
 enum ContextContent {
+    /// A file with the given `content`; without it, `path` is read by the
+    /// server and may also be a directory or a glob.
     #[serde(rename = "file")]
-    File { path: String, content: String },
+    File {
+        path: String,
+        content: Option<String>,
+    },
     #[serde(rename = "note")]
     Note { note: String },
     #[serde(rename = "metadata")]
     Metadata { key: String, value: String },
+    /// An image or PDF, either read from `path` in the workspace or given
+    /// as base64 `data` with its `media_type`.
+    #[serde(rename = "attachment")]
+    Attachment(Attachment),
 }
 
 pub async fn add_context(
@@ -62,11 +446,13 @@ pub async fn add_context(
     context: Arc<RwLock<ConversationContext>>,
 ) -> Result<ToolResponse> {
     let args: AddContextArgs = serde_json::from_value(args)?;
-    let mut ctx = context.write().await;
 
     match args.content {
-        ContextContent::File { path, content } => {
-            ctx.add_file(path.clone(), content);
+        ContextContent::File {
+            path,
+            content: Some(content),
+        } => {
+            context.write().await.add_file(path.clone(), content);
             Ok(ToolResponse {
                 success: true,
                 result: serde_json::json!({
@@ -75,8 +461,45 @@ pub async fn add_context(
                 error: None,
             })
         }
+        ContextContent::File {
+            path,
+            content: None,
+        } => {
+            let root = workspace::root()?;
+            let pattern = path.clone();
+            let collected =
+                tokio::task::spawn_blocking(move || files::collect(&root, &pattern)).await??;
+            if collected.files.is_empty() && collected.skipped.is_empty() {
+                bail!("No files match: {}", path);
+            }
+            let added: Vec<_> = collected
+                .files
+                .iter()
+                .map(|file| {
+                    serde_json::json!({
+                        "path": file.name,
+                        "size_bytes": file.source.size_bytes,
+                        "sha256": file.source.sha256,
+                    })
+                })
+                .collect();
+            let mut ctx = context.write().await;
+            for file in collected.files {
+                ctx.add_file_from_disk(file);
+            }
+            Ok(ToolResponse {
+                success: true,
+                result: serde_json::json!({
+                    "message": format!("Added {} file(s) from {}", added.len(), path),
+                    "files": added,
+                    "skipped": collected.skipped,
+                    "truncated": collected.truncated,
+                }),
+                error: None,
+            })
+        }
         ContextContent::Note { note } => {
-            ctx.add_note(note.clone());
+            context.write().await.add_note(note.clone());
             Ok(ToolResponse {
                 success: true,
                 result: serde_json::json!({
@@ -85,9 +508,24 @@ pub async fn add_context(
                 error: None,
             })
         }
+        ContextContent::Attachment(attachment) => {
+            let LoadedAttachment {
+                name,
+                media_type,
+                part,
+            } = attachment.load()?;
+            context.write().await.add_attachment(name.clone(), part);
+            Ok(ToolResponse {
+                success: true,
+                result: serde_json::json!({
+                    "message": format!("Added attachment: {} ({})", name, media_type),
+                }),
+                error: None,
+            })
+        }
         ContextContent::Metadata { key, value } => {
             let value_clone = value.clone();
-            ctx.set_metadata(key.clone(), value);
+            context.write().await.set_metadata(key.clone(), value);
             Ok(ToolResponse {
                 success: true,
                 result: serde_json::json!({
@@ -102,13 +540,217 @@ pub async fn add_context(
 pub async fn get_context(
     context: Arc<RwLock<ConversationContext>>,
 ) -> Result<ToolResponse> {
+    let statuses = statuses(workspace::root()?, &context).await?;
+    let mut stale = Vec::new();
+    let mut sources = BTreeMap::new();
+    for (name, (source, status)) in statuses {
+        if status != files::FileStatus::Fresh {
+            stale.push(name.clone());
+        }
+        let mut entry = serde_json::to_value(source)?;
+        entry["status"] = serde_json::to_value(status)?;
+        sources.insert(name, entry);
+    }
     let ctx = context.read().await;
     Ok(ToolResponse {
         success: true,
         result: serde_json::json!({
             "files": ctx.files,
+            "sources": sources,
+            "stale": stale,
             "notes": ctx.notes,
             "metadata": ctx.metadata,
+            "attachments": ctx
+                .attachments
+                .iter()
+                .map(|(name, part)| (name.clone(), attachment_summary(part)))
+                .collect::<BTreeMap<_, _>>(),
+            "last_prompt": ctx.last_prompt,
+        }),
+        error: None,
+    })
+}
+
+/// Media type and size of an attachment; the base64 data itself is not
+/// echoed back.
+fn attachment_summary(part: &ContentPart) -> serde_json::Value {
+    match part {
+        ContentPart::Image { media_type, data }
+        | ContentPart::Document {
+            media_type, data, ..
+        } => {
+            serde_json::json!({
+                "media_type": media_type,
+                "size_bytes": data.len() / 4 * 3,
+            })
+        }
+        ContentPart::Text { text } => serde_json::json!({ "size_bytes": text.len() }),
+    }
+}
+
+/// One item of the context, as named by `remove_context`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum ContextItem {
+    #[serde(rename = "file")]
+    File { path: String },
+    /// A note by its position in `list_context`
+    #[serde(rename = "note")]
+    Note { index: usize },
+    #[serde(rename = "metadata")]
+    Metadata { key: String },
+    #[serde(rename = "attachment")]
+    Attachment { name: String },
+}
+
+pub async fn remove_context(
+    args: serde_json::Value,
+    context: Arc<RwLock<ConversationContext>>,
+) -> Result<ToolResponse> {
+    let item: ContextItem = serde_json::from_value(args)?;
+    let mut ctx = context.write().await;
+    let message = match item {
+        ContextItem::File { path } => {
+            if !ctx.remove_file(&path) {
+                bail!("No file in context: {}", path);
+            }
+            format!("Removed file: {}", path)
+        }
+        ContextItem::Note { index } => {
+            if ctx.remove_note(index).is_none() {
+                bail!("No note at index {} ({} notes)", index, ctx.notes.len());
+            }
+            format!("Removed note {}", index)
+        }
+        ContextItem::Metadata { key } => {
+            if ctx.remove_metadata(&key).is_none() {
+                bail!("No metadata key in context: {}", key);
+            }
+            format!("Removed metadata: {}", key)
+        }
+        ContextItem::Attachment { name } => {
+            if !ctx.remove_attachment(&name) {
+                bail!("No attachment in context: {}", name);
+            }
+            format!("Removed attachment: {}", name)
+        }
+    };
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({ "message": message }),
+        error: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct ListContextArgs {
+    /// Model whose tokenizer is used for the estimates
+    model: Option<String>,
+}
+
+/// Sizes and token estimates of every context item, without their
+/// contents, in the order the prompt assembler considers them.
+pub async fn list_context(
+    args: serde_json::Value,
+    context: Arc<RwLock<ConversationContext>>,
+    default_model: String,
+) -> Result<ToolResponse> {
+    let args: ListContextArgs = serde_json::from_value(args)?;
+    let model = args.model.unwrap_or(default_model);
+    let statuses = statuses(workspace::root()?, &context).await?;
+    let tokenizer = Tokenizer::for_model(&model);
+    let ctx = context.read().await;
+
+    let mut items = Vec::new();
+    for name in ctx.ranked(ctx.attachments.keys()) {
+        let part = &ctx.attachments[name];
+        let mut item = attachment_summary(part);
+        item["type"] = "attachment".into();
+        item["name"] = name.as_str().into();
+        item["tokens"] = media::estimate_tokens(part).into();
+        item["pinned"] = ctx.priority(name).pinned.into();
+        item["priority"] = ctx.priority(name).priority.into();
+        items.push(item);
+    }
+    let mut keys: Vec<_> = ctx.metadata.keys().collect();
+    keys.sort();
+    for key in keys {
+        let value = &ctx.metadata[key];
+        items.push(serde_json::json!({
+            "type": "metadata",
+            "key": key,
+            "size_bytes": value.len(),
+            "tokens": tokenizer.count(value),
+        }));
+    }
+    for (index, note) in ctx.notes.iter().enumerate() {
+        items.push(serde_json::json!({
+            "type": "note",
+            "index": index,
+            "size_bytes": note.len(),
+            "tokens": tokenizer.count(note),
+        }));
+    }
+    for path in ctx.ranked(ctx.files.keys()) {
+        let content = &ctx.files[path];
+        let priority = ctx.priority(path);
+        items.push(serde_json::json!({
+            "type": "file",
+            "path": path,
+            "size_bytes": content.len(),
+            "tokens": tokenizer.count(content),
+            "pinned": priority.pinned,
+            "priority": priority.priority,
+            "status": statuses.get(path).map(|(_, status)| status),
+        }));
+    }
+
+    let total_tokens: u64 = items
+        .iter()
+        .filter_map(|item| item["tokens"].as_u64())
+        .sum();
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "model": model,
+            "items": items,
+            "total_tokens": total_tokens,
+        }),
+        error: None,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct PriorityArgs {
+    /// Path of a context file or name of an attachment
+    name: String,
+    pinned: Option<bool>,
+    priority: Option<i32>,
+}
+
+pub async fn set_priority(
+    args: serde_json::Value,
+    context: Arc<RwLock<ConversationContext>>,
+) -> Result<ToolResponse> {
+    let args: PriorityArgs = serde_json::from_value(args)?;
+    let mut ctx = context.write().await;
+    if !ctx.files.contains_key(&args.name) && !ctx.attachments.contains_key(&args.name) {
+        bail!("No file or attachment in context: {}", args.name);
+    }
+    let mut priority = ctx.priority(&args.name);
+    if let Some(pinned) = args.pinned {
+        priority.pinned = pinned;
+    }
+    if let Some(value) = args.priority {
+        priority.priority = value;
+    }
+    ctx.set_priority(args.name.clone(), priority);
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "name": args.name,
+            "pinned": priority.pinned,
+            "priority": priority.priority,
         }),
         error: None,
     })
@@ -127,3 +769,81 @@ pub async fn clear_context(
         error: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refresh(
+        ctx: &mut ConversationContext,
+        root: &Path,
+        names: &[String],
+    ) -> Result<RefreshReport> {
+        let sources = ctx.sources(names)?;
+        Ok(ctx.apply_refresh(recheck(root, sources)))
+    }
+
+    #[test]
+    fn test_refresh_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = std::fs::canonicalize(dir.path()).unwrap();
+        let path = root.join("lib.rs");
+        std::fs::write(&path, "pub fn old() {}\n").unwrap();
+        let mut ctx = ConversationContext::new();
+        ctx.add_file_from_disk(files::read(&root, &path).unwrap());
+        let name = ctx.files().keys().next().unwrap().clone();
+        assert_eq!(name, "lib.rs");
+
+        assert!(refresh(&mut ctx, &root, &[]).unwrap().refreshed.is_empty());
+        std::fs::write(&path, "pub fn renamed() {}\n").unwrap();
+        // A file added again while it was being checked is left alone
+        let rechecked = recheck(&root, ctx.sources(&[]).unwrap());
+        ctx.add_file_from_disk(files::read(&root, &path).unwrap());
+        assert!(ctx.apply_refresh(rechecked).refreshed.is_empty());
+        std::fs::write(&path, "pub fn renamed() {}\n\n").unwrap();
+        let report = refresh(&mut ctx, &root, std::slice::from_ref(&name)).unwrap();
+        assert_eq!(report.refreshed, [name.as_str()]);
+        assert_eq!(ctx.files()[&name], "pub fn renamed() {}\n\n");
+
+        std::fs::remove_file(&path).unwrap();
+        let report = refresh(&mut ctx, &root, &[]).unwrap();
+        assert_eq!(report.missing, [name.as_str()]);
+        assert_eq!(ctx.files()[&name], "pub fn renamed() {}\n\n");
+        assert!(refresh(&mut ctx, &root, &["unknown.rs".to_string()]).is_err());
+
+        // Sources from a shared session cannot point outside the workspace
+        let shared = |path: &str| FileSource {
+            path: PathBuf::from(path),
+            sha256: String::new(),
+            modified: None,
+            size_bytes: 0,
+        };
+        ctx.add_file("hosts".to_string(), String::new());
+        ctx.file_sources
+            .insert("hosts".to_string(), shared("/etc/hosts"));
+        ctx.file_sources
+            .insert("up".to_string(), shared("../up.rs"));
+        assert_eq!(
+            files::status(&root, &ctx.file_sources["hosts"]),
+            files::FileStatus::Missing
+        );
+        let report = refresh(&mut ctx, &root, &["hosts".to_string()]).unwrap();
+        assert_eq!(report.missing, ["hosts"]);
+        assert_eq!(ctx.drop_foreign_sources(), ["hosts", "up"]);
+        assert_eq!(ctx.file_sources.keys().collect::<Vec<_>>(), ["lib.rs"]);
+    }
+
+    #[test]
+    fn test_attachment_path_stays_in_workspace() {
+        for path in ["/etc/hosts", "../Cargo.toml"] {
+            let attachment = Attachment {
+                path: Some(path.to_string()),
+                data: None,
+                media_type: None,
+                name: None,
+            };
+            let error = attachment.load().unwrap_err().to_string();
+            assert!(error.starts_with("Access denied"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use super::ModelClient;
+use crate::providers::{
+    CompletionRequest, CompletionResponse, Message, ProviderError, StopReason, UsageInfo,
+};
+use anyhow::Result;
+
+/// Continuation rounds when the caller does not choose.
+pub const DEFAULT_MAX_CONTINUATIONS: u32 = 3;
+/// Hard cap on continuation rounds, bounding the cost of one call.
+pub const MAX_CONTINUATIONS: u32 = 10;
+
+/// Shortest repeated text treated as overlap when stitching; shorter
+/// matches are more likely to be genuine repetition (e.g. closing braces).
+const MIN_OVERLAP_CHARS: usize = 16;
+/// Longest overlap searched for.
+const MAX_OVERLAP_CHARS: usize = 2_000;
+
+const CONTINUE_PROMPT: &str = "Your previous reply was cut off by the output limit. Continue \
+     exactly where it stopped, without repeating anything and without any preamble. If it \
+     stopped inside a code block, do not open a new one.";
+
+/// A completion stitched together from one or more rounds.
+pub struct Continued {
+    pub response: CompletionResponse,
+    /// Rounds made after the first request
+    pub continuations: u32,
+}
+
+impl Continued {
+    /// Whether the answer is still cut off after the last round.
+    pub fn truncated(&self) -> bool {
+        self.response.stop_reason == StopReason::MaxTokens
+    }
+}
+
+/// Complete `request`, continuing up to `max_rounds` times while the model
+/// stops at `max_tokens`. Providers that support it (Anthropic) continue
+/// from the partial answer as an assistant prefill; others get the partial
+/// answer back with a request to continue, and any text they repeat is
+/// dropped when stitching. Usage is summed over all rounds.
+pub async fn complete(
+    client: &ModelClient,
+    tool: &str,
+    request: CompletionRequest,
+    max_rounds: u32,
+) -> Result<Continued> {
+    let max_rounds = max_rounds.min(MAX_CONTINUATIONS);
+    // Extended thinking cannot be combined with a prefilled answer
+    let prefill = client.provider.read().await.supports_prefill() && request.reasoning.is_none();
+
+    let mut response = client.complete(tool, request.clone()).await?;
+    let mut content = std::mem::take(&mut response.content);
+    let mut usage = response.usage.take();
+    let mut thinking = std::mem::take(&mut response.thinking);
+    let mut continuations = 0;
+
+    while response.stop_reason == StopReason::MaxTokens && continuations < max_rounds {
+        let mut round = request.clone();
+        if prefill {
+            // The API rejects a prefill ending in whitespace
+            content.truncate(content.trim_end().len());
+        }
+        round
+            .messages
+            .push(Message::new("assistant", content.clone()));
+        if !prefill {
+            round.messages.push(Message::new("user", CONTINUE_PROMPT));
+        }
+
+        let next = match client.complete(tool, round).await {
+            Ok(next) => next,
+            Err(e) if is_context_too_long(&e) => {
+                tracing::warn!("{}: no room left to continue the answer: {}", tool, e);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        continuations += 1;
+        if let Some(round_usage) = &next.usage {
+            usage
+                .get_or_insert_with(UsageInfo::default)
+                .add(round_usage);
+        }
+        thinking.extend(next.thinking.iter().cloned());
+        if prefill {
+            content.push_str(&next.content);
+        } else {
+            stitch(&mut content, &next.content);
+        }
+        response = next;
+    }
+
+    response.content = content;
+    response.usage = usage;
+    response.thinking = thinking;
+    Ok(Continued {
+        response,
+        continuations,
+    })
+}
+
+fn is_context_too_long(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<ProviderError>())
+        .any(|e| e.kind() == "context_too_long")
+}
+
+/// Append a continuation to `content`, dropping a code fence the model
+/// reopened and any text it repeated from the end of `content`.
+fn stitch(content: &mut String, piece: &str) {
+    let mut piece = piece;
+    let open_fence = content
+        .lines()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .count()
+        % 2
+        == 1;
+    if open_fence && piece.trim_start().starts_with("```") {
+        let start = piece.trim_start();
+        piece = start.split_once('\n').map_or("", |(_, rest)| rest);
+    }
+
+    let longest = piece.len().min(content.len()).min(MAX_OVERLAP_CHARS);
+    let overlap = (MIN_OVERLAP_CHARS..=longest)
+        .rev()
+        .filter(|&len| piece.is_char_boundary(len))
+        .find(|&len| content.ends_with(&piece[..len]))
+        .unwrap_or(0);
+    content.push_str(&piece[overlap..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::scripted::ScriptedProvider;
+    use crate::providers::{Reasoning, ReasoningEffort};
+
+    fn request() -> CompletionRequest {
+        CompletionRequest {
+            messages: vec![Message::new("user", "Write a parser")],
+            model: "scripted".to_string(),
+            max_tokens: Some(64),
+            temperature: None,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_complete_continues_until_end_turn() {
+        // Without prefill the partial answer is sent back with a request to
+        // continue, and the repeated text is dropped
+        let provider = ScriptedProvider::new(false)
+            .reply("fn parse(input: &str) {\n", StopReason::MaxTokens)
+            .reply(
+                "fn parse(input: &str) {\n    todo!()\n}\n",
+                StopReason::EndTurn,
+            );
+        let client = ModelClient::scripted(provider.clone());
+        let continued = complete(&client, "test", request(), 3).await.unwrap();
+        assert_eq!(continued.continuations, 1);
+        assert!(!continued.truncated());
+        assert_eq!(
+            continued.response.content,
+            "fn parse(input: &str) {\n    todo!()\n}\n"
+        );
+        let requests = provider.requests();
+        assert_eq!(requests.len(), 2);
+        let round = &requests[1].messages;
+        assert_eq!(round[1].role, "assistant");
+        assert_eq!(round[2].text(), CONTINUE_PROMPT);
+
+        // With prefill the trimmed partial answer is the last message and the
+        // reply is appended as is
+        let provider = ScriptedProvider::new(true)
+            .reply("let total = 1 +  \n", StopReason::MaxTokens)
+            .reply(" 2;", StopReason::EndTurn);
+        let client = ModelClient::scripted(provider.clone());
+        let continued = complete(&client, "test", request(), 3).await.unwrap();
+        assert_eq!(continued.response.content, "let total = 1 + 2;");
+        let round = &provider.requests()[1].messages;
+        assert_eq!(round.len(), 2);
+        assert_eq!(round[1].text(), "let total = 1 +");
+
+        // Extended thinking cannot be prefilled
+        let provider = ScriptedProvider::new(true)
+            .reply("a", StopReason::MaxTokens)
+            .reply("b", StopReason::EndTurn);
+        let client = ModelClient::scripted(provider.clone());
+        let mut thinking = request();
+        thinking.reasoning = Reasoning::new(Some(ReasoningEffort::Low), None);
+        complete(&client, "test", thinking, 3).await.unwrap();
+        assert_eq!(provider.requests()[1].messages.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_complete_stops_after_max_rounds() {
+        let provider = ScriptedProvider::new(true)
+            .reply("one", StopReason::MaxTokens)
+            .reply(" two", StopReason::MaxTokens)
+            .reply(" three", StopReason::MaxTokens);
+        let client = ModelClient::scripted(provider.clone());
+        let continued = complete(&client, "test", request(), 2).await.unwrap();
+        assert_eq!(continued.continuations, 2);
+        assert!(continued.truncated());
+        assert_eq!(continued.response.content, "one two three");
+        assert_eq!(provider.requests().len(), 3);
+
+        let provider = ScriptedProvider::new(true).reply("one", StopReason::MaxTokens);
+        let client = ModelClient::scripted(provider.clone());
+        let continued = complete(&client, "test", request(), 0).await.unwrap();
+        assert_eq!(continued.continuations, 0);
+        assert!(continued.truncated());
+    }
+
+    #[test]
+    fn test_stitch() {
+        let mut content = "```rust\nfn main() {\n    let total = compute(1, 2);\n".to_string();
+        stitch(
+            &mut content,
+            "```rust\n    let total = compute(1, 2);\n    println!(\"{}\", total);\n}\n```",
+        );
+        assert_eq!(
+            content,
+            "```rust\nfn main() {\n    let total = compute(1, 2);\n    println!(\"{}\", total);\n}\n```"
+        );
+
+        // Short coincidental matches are kept
+        let mut content = "    }\n".to_string();
+        stitch(&mut content, "    }\n}\n");
+        assert_eq!(content, "    }\n    }\n}\n");
+    }
+}
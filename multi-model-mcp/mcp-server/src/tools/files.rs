@@ -0,0 +1,337 @@
+use super::context::FileSource;
+use super::workspace;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use globset::GlobBuilder;
+use ignore::WalkBuilder;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Largest file read into the context.
+const MAX_FILE_BYTES: u64 = 512 * 1024;
+/// Most files added by one directory or glob.
+const MAX_FILES: usize = 200;
+/// Most bytes added by one directory or glob.
+const MAX_TOTAL_BYTES: u64 = 4 * 1024 * 1024;
+/// Bytes inspected for NUL when telling text from binary files.
+const BINARY_SNIFF_BYTES: usize = 8 * 1024;
+
+/// Whether a file starting with `head` is binary: it has a NUL within the
+/// first `BINARY_SNIFF_BYTES`.
+pub(crate) fn looks_binary(head: &[u8]) -> bool {
+    head[..head.len().min(BINARY_SNIFF_BYTES)].contains(&0)
+}
+
+/// How a file on disk compares to the copy in the context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Fresh,
+    Modified,
+    Missing,
+}
+
+/// A text file read from disk.
+#[derive(Debug)]
+pub struct LoadedFile {
+    /// Path relative to the workspace root
+    pub name: String,
+    pub content: String,
+    pub source: FileSource,
+}
+
+/// A file that was found but not read.
+#[derive(Debug, Serialize)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Collected {
+    pub files: Vec<LoadedFile>,
+    pub skipped: Vec<SkippedFile>,
+    /// Files left out once `MAX_FILES` or `MAX_TOTAL_BYTES` was reached
+    pub truncated: bool,
+}
+
+/// Read the files named by `pattern`, relative to the workspace `root`: a
+/// file, a directory (walked recursively) or a glob such as `src/**/*.rs`.
+/// Absolute paths and paths leading out of the workspace are refused, and
+/// hidden and `.gitignore`d files are skipped, or refused when named
+/// directly.
+pub fn collect(root: &Path, pattern: &str) -> Result<Collected> {
+    let mut collected = Collected::default();
+    if !is_glob(pattern) {
+        let path = workspace::resolve_within(root, pattern)?;
+        if is_ignored(root, &path) {
+            bail!("Cannot add {}: hidden or ignored by .gitignore", pattern);
+        }
+        if !path.is_dir() {
+            match read(root, &path) {
+                Ok(file) => collected.files.push(file),
+                Err(reason) => bail!("Cannot add {}: {}", pattern, reason),
+            }
+            return Ok(collected);
+        }
+        walk(root, &path, |_| true, &mut collected);
+        return Ok(collected);
+    }
+
+    let (base, glob) = split_glob(pattern);
+    let matcher = GlobBuilder::new(&glob)
+        .literal_separator(true)
+        .build()
+        .with_context(|| format!("Invalid glob: {}", pattern))?
+        .compile_matcher();
+    let base = workspace::resolve_within(root, &base)?;
+    if is_ignored(root, &base) {
+        bail!("Cannot add {}: hidden or ignored by .gitignore", pattern);
+    }
+    walk(
+        root,
+        &base,
+        |path| {
+            path.strip_prefix(&base)
+                .is_ok_and(|rel| matcher.is_match(rel))
+        },
+        &mut collected,
+    );
+    Ok(collected)
+}
+
+fn walker(root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    // .gitignore applies even outside a git checkout
+    builder.require_git(false);
+    builder
+}
+
+/// Whether a walk of the workspace `root` leaves out `path`, hidden or
+/// ignored itself or below such a directory. Only the directories on the way
+/// to `path` are read.
+fn is_ignored(root: &Path, path: &Path) -> bool {
+    let target = path.to_path_buf();
+    !walker(root)
+        .filter_entry(move |entry| target.starts_with(entry.path()))
+        .build()
+        .flatten()
+        .any(|entry| entry.path() == path)
+}
+
+/// Read the files below `dir` for which `matches` holds.
+fn walk(root: &Path, dir: &Path, matches: impl Fn(&Path) -> bool, collected: &mut Collected) {
+    let mut total_bytes = 0;
+    let walker = walker(dir).sort_by_file_name(|a, b| a.cmp(b)).build();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!("Failed to read entry: {}", e);
+                continue;
+            }
+        };
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) || !matches(entry.path()) {
+            continue;
+        }
+        if collected.files.len() >= MAX_FILES {
+            collected.truncated = true;
+            break;
+        }
+        match read(root, entry.path()) {
+            Ok(file) if total_bytes + file.source.size_bytes > MAX_TOTAL_BYTES => {
+                collected.truncated = true;
+                break;
+            }
+            Ok(file) => {
+                total_bytes += file.source.size_bytes;
+                collected.files.push(file);
+            }
+            Err(reason) => collected.skipped.push(SkippedFile {
+                path: relative_name(root, entry.path()),
+                reason,
+            }),
+        }
+    }
+}
+
+/// Read a text file inside the workspace `root`, refusing large and binary
+/// files; the error is the reason it was refused.
+pub fn read(root: &Path, path: &Path) -> Result<LoadedFile, String> {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return Err("outside the workspace".to_string());
+    };
+    let metadata = fs::metadata(path).map_err(|e| e.to_string())?;
+    if metadata.len() > MAX_FILE_BYTES {
+        return Err(format!(
+            "larger than {} KB ({} bytes)",
+            MAX_FILE_BYTES / 1024,
+            metadata.len()
+        ));
+    }
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    if looks_binary(&bytes) {
+        return Err("binary file".to_string());
+    }
+    let sha256 = format!("{:x}", Sha256::digest(&bytes));
+    let content = String::from_utf8(bytes).map_err(|_| "not valid UTF-8".to_string())?;
+    Ok(LoadedFile {
+        name: relative.to_string_lossy().into_owned(),
+        source: FileSource {
+            path: relative.to_path_buf(),
+            sha256,
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            size_bytes: metadata.len(),
+        },
+        content,
+    })
+}
+
+/// Compare the file recorded in `source` with its copy. A file of another
+/// size has changed; for one of the same size with another modification
+/// time the hash decides, so a file that was only touched is not stale. A source that no
+/// longer resolves inside the workspace `root` counts as missing.
+pub fn status(root: &Path, source: &FileSource) -> FileStatus {
+    let Ok(path) = resolve(root, source) else {
+        return FileStatus::Missing;
+    };
+    let Ok(metadata) = fs::metadata(&path) else {
+        return FileStatus::Missing;
+    };
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    if metadata.len() != source.size_bytes {
+        return FileStatus::Modified;
+    }
+    if modified == source.modified {
+        return FileStatus::Fresh;
+    }
+    // Hash no more than `read` would have kept, even if the file grows
+    let mut hasher = Sha256::new();
+    let hashed = fs::File::open(&path)
+        .and_then(|file| io::copy(&mut file.take(MAX_FILE_BYTES + 1), &mut hasher));
+    match hashed {
+        Ok(_) if format!("{:x}", hasher.finalize()) == source.sha256 => FileStatus::Fresh,
+        Ok(_) => FileStatus::Modified,
+        Err(_) => FileStatus::Missing,
+    }
+}
+
+/// The file `source` was read from, checked to still be inside the
+/// workspace `root`.
+pub fn resolve(root: &Path, source: &FileSource) -> Result<PathBuf> {
+    workspace::resolve_within(root, &source.path.to_string_lossy())
+}
+
+/// `path` relative to the workspace `root`, the name files are stored under
+/// so that sessions stay meaningful when shared.
+fn relative_name(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '[', '{'])
+}
+
+/// Split a glob into the directory before its first wildcard component and
+/// the pattern below it, e.g. `src/**/*.rs` into `src` and `**/*.rs`.
+fn split_glob(pattern: &str) -> (String, String) {
+    let mut base = PathBuf::new();
+    let mut components = pattern.split('/').peekable();
+    while let Some(component) = components.next_if(|c| !is_glob(c)) {
+        base.push(if component.is_empty() { "/" } else { component });
+    }
+    let glob = components.collect::<Vec<_>>().join("/");
+    let base = if base.as_os_str().is_empty() {
+        ".".to_string()
+    } else {
+        base.to_string_lossy().into_owned()
+    };
+    (base, glob)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_glob() {
+        assert_eq!(
+            split_glob("src/**/*.rs"),
+            ("src".to_string(), "**/*.rs".to_string())
+        );
+        assert_eq!(
+            split_glob("*.toml"),
+            (".".to_string(), "*.toml".to_string())
+        );
+        assert_eq!(
+            split_glob("/tmp/project/src/*.rs"),
+            ("/tmp/project/src".to_string(), "*.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_collect_directory_respects_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::create_dir_all(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        fs::write(
+            dir.path().join("src/logo.png"),
+            [0x89, b'P', b'N', b'G', 0, 0],
+        )
+        .unwrap();
+        fs::write(dir.path().join("target/out.rs"), "// build output\n").unwrap();
+
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let collected = collect(&root, ".").unwrap();
+        let names: Vec<_> = collected
+            .files
+            .iter()
+            .map(|file| file.source.path.to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["src/main.rs"]);
+        assert_eq!(collected.skipped[0].path, "src/logo.png");
+        assert_eq!(collected.skipped.len(), 1);
+        assert_eq!(collected.skipped[0].reason, "binary file");
+        assert_eq!(collected.files[0].source.size_bytes, 13);
+        assert_eq!(collected.files[0].source.sha256.len(), 64);
+
+        let collected = collect(&root, "src/*.rs").unwrap();
+        assert_eq!(collected.files.len(), 1);
+        assert!(collected.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_collect_stays_in_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::write(root.join(".gitignore"), "secrets.txt\ntarget/\n").unwrap();
+        fs::write(root.join(".env"), "TOKEN=1\n").unwrap();
+        fs::write(root.join("secrets.txt"), "hunter2\n").unwrap();
+        fs::create_dir(root.join("target")).unwrap();
+        fs::write(root.join("target/out.rs"), "// build output\n").unwrap();
+        fs::write(root.join("lib.rs"), "pub fn lib() {}\n").unwrap();
+
+        for pattern in [
+            "/etc/passwd",
+            "../x",
+            "/etc/*.conf",
+            "../**/*.rs",
+            ".env",
+            "secrets.txt",
+            "target/out.rs",
+            "target",
+            "target/*.rs",
+        ] {
+            assert!(collect(&root, pattern).is_err(), "{}", pattern);
+        }
+        assert_eq!(collect(&root, "lib.rs").unwrap().files.len(), 1);
+    }
+}
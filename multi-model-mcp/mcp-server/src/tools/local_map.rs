@@ -1,13 +1,41 @@
-use super::ToolResponse;
-use anyhow::{bail, Result};
+use super::{files, workspace, ToolResponse};
+use crate::config::LocalMapConfig;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::{DirEntry, WalkBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
-const MAX_ENTRIES: usize = 8_000;
-const TIMEOUT_SECS: u64 = 2;
+/// Limits used when a call does not set its own, within the server's
+/// `[local_map]` maximums.
+const DEFAULT_MAX_ENTRIES: usize = 8_000;
+const DEFAULT_TIMEOUT_MS: u64 = 2_000;
+
+/// Most threads reading directories for one call.
+const MAX_READERS: usize = 8;
+
+/// Files larger than this get no line count.
+const MAX_LINE_COUNT_BYTES: u64 = 8 * 1024 * 1024;
+
+/// Directory names skipped unless `skip` says otherwise.
+const DEFAULT_SKIPS: &[&str] = &[".git", "node_modules", "__pycache__", "venv", ".venv"];
+
+/// Arguments the model may pass when it calls `local_map` itself; the
+/// others (symlinks, details, limits, ...) stay the user's choice.
+const MODEL_ARGS: &[&str] = &["path", "depth", "include", "exclude", "cursor", "format"];
 
 #[derive(Debug, Deserialize)]
 struct LocalMapArgs {
@@ -17,6 +45,64 @@ struct LocalMapArgs {
     depth: u32,
     #[serde(default)]
     follow_symlinks: bool,
+    /// Only list files matching one of these globs
+    #[serde(default)]
+    include: Vec<String>,
+    /// Leave out files and directories matching one of these globs
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// List entries whose name starts with `.`
+    #[serde(default)]
+    show_hidden: bool,
+    /// Honor `.gitignore`, `.ignore` and the global git excludes
+    #[serde(default = "default_true")]
+    gitignore: bool,
+    /// Directory names never entered; `DEFAULT_SKIPS` when not given
+    skip: Option<Vec<String>>,
+    timeout_ms: Option<u64>,
+    /// Most entries in one page
+    #[serde(alias = "page_size")]
+    max_entries: Option<usize>,
+    /// `next_cursor` of the previous page
+    cursor: Option<String>,
+    /// Optional per-entry details to collect
+    #[serde(default)]
+    details: Vec<Detail>,
+    #[serde(default)]
+    format: Format,
+}
+
+/// Entry details that cost extra work and are only collected on request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Detail {
+    Modified,
+    Permissions,
+    Language,
+    Lines,
+    GitStatus,
+    /// Total size of the files listed below a directory
+    DirSize,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    /// Entries as objects with all their fields
+    #[default]
+    Json,
+    /// Indented text like the output of `tree`
+    Tree,
+    /// Paths relative to the root, directories ending in `/`
+    Paths,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum GitStatus {
+    Modified,
+    Added,
+    Untracked,
 }
 
 fn default_path() -> String {
@@ -27,6 +113,10 @@ fn default_depth() -> u32 {
     2
 }
 
+fn default_true() -> bool {
+    true
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct LocalMapEntry {
     name: String,
@@ -35,180 +125,950 @@ struct LocalMapEntry {
     is_symlink: bool,
     size_bytes: u64,
     depth: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<DateTime<Utc>>,
+    /// `rwxr-xr-x` style on Unix, `r--` or `rw-` elsewhere
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    permissions: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lines: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git_status: Option<GitStatus>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct LocalMapResult {
     root: String,
-    entries: Vec<LocalMapEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entries: Option<Vec<LocalMapEntry>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tree: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paths: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     truncated: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     timed_out: Option<bool>,
+    /// Cursor for the next page, when there are more entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// Position of an entry in the listing: the components of its path below
+/// the root, each flagged as a file, so that a directory comes right before
+/// its contents and directories come before files, each ordered by name.
+type SortKey = Vec<(bool, String)>;
+
+fn sort_key(relative: &Path, is_dir: bool) -> SortKey {
+    let mut key: SortKey = relative
+        .components()
+        .map(|component| (false, component.as_os_str().to_string_lossy().into_owned()))
+        .collect();
+    if let Some(last) = key.last_mut() {
+        last.0 = !is_dir;
+    }
+    key
+}
+
+/// Where a page ended, tied to the arguments of the listing it belongs to.
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    listing: String,
+    after: SortKey,
 }
 
-pub async fn execute(args: serde_json::Value) -> Result<ToolResponse> {
+impl Cursor {
+    fn encode(&self) -> Result<String> {
+        Ok(URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?))
+    }
+
+    fn decode(cursor: &str, listing: &str) -> Result<Self> {
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .context("Invalid cursor")?;
+        if cursor.listing != listing {
+            bail!("The cursor belongs to a local_map call with different arguments");
+        }
+        Ok(cursor)
+    }
+}
+
+/// Identifies the arguments that decide which entries are listed, so a
+/// cursor is only used with the listing it came from.
+fn listing_id(args: &LocalMapArgs) -> String {
+    let listed = format!(
+        "{:?}",
+        (
+            &args.path,
+            args.depth,
+            args.follow_symlinks,
+            &args.include,
+            &args.exclude,
+            args.show_hidden,
+            args.gitignore,
+            &args.skip,
+        )
+    );
+    format!("{:x}", Sha256::digest(listed.as_bytes()))[..16].to_string()
+}
+
+/// Globs matched against an entry's path relative to the root and against
+/// its name, so `*.rs` and `target` match at any depth while `src/*.rs`
+/// only matches below `src`.
+fn glob_set(field: &str, patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            Glob::new(pattern.trim_end_matches('/'))
+                .with_context(|| format!("Invalid {} glob: {}", field, pattern))?,
+        );
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn matches(set: &GlobSet, root: &Path, path: &Path) -> bool {
+    path.strip_prefix(root)
+        .is_ok_and(|relative| set.is_match(relative))
+        || path.file_name().is_some_and(|name| set.is_match(name))
+}
+
+/// What a walk found, and why it stopped early.
+struct Walked {
+    /// One page, in listing order
+    entries: Vec<(SortKey, LocalMapEntry)>,
+    /// Depth of the entry after the page, when more follow
+    next_depth: Option<u32>,
+    /// The deadline passed before git status was read
+    missing_details: bool,
+    /// Directory sizes were requested
+    dir_size: bool,
+    timed_out: bool,
+    cancelled: bool,
+}
+
+/// Identity of a directory, so one reached again through a symlink is not
+/// walked twice.
+#[cfg(unix)]
+type FileId = (u64, u64);
+#[cfg(not(unix))]
+type FileId = PathBuf;
+
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata, _path: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata, path: &Path) -> Option<FileId> {
+    fs::canonicalize(path).ok()
+}
+
+pub async fn execute(args: serde_json::Value, limits: &LocalMapConfig) -> Result<ToolResponse> {
     let args: LocalMapArgs = serde_json::from_value(args)?;
+    // Security: relative paths must stay within the workspace
+    let root = workspace::resolve(&args.path)?;
+    list(args, root, limits).await
+}
 
+/// `local_map` as called by the model in a tool loop: only `MODEL_ARGS`
+/// are accepted and `path` must stay inside the workspace, since a prompt
+/// can steer the model anywhere.
+pub async fn execute_for_model(
+    args: serde_json::Value,
+    limits: &LocalMapConfig,
+) -> Result<ToolResponse> {
+    let Some(object) = args.as_object() else {
+        bail!("local_map arguments must be an object");
+    };
+    if let Some(key) = object
+        .keys()
+        .find(|key| !MODEL_ARGS.contains(&key.as_str()))
+    {
+        bail!("Unsupported local_map argument: {}", key);
+    }
+    let args: LocalMapArgs = serde_json::from_value(args)?;
+    let root = workspace::resolve_within(&workspace::root()?, &args.path)?;
+    list(args, root, limits).await
+}
+
+/// List the directory `root_canonical` that `args.path` resolved to.
+async fn list(
+    args: LocalMapArgs,
+    root_canonical: PathBuf,
+    limits: &LocalMapConfig,
+) -> Result<ToolResponse> {
     // Validate depth
     if args.depth > 6 {
         bail!("Depth must be between 0 and 6 (requested: {})", args.depth);
     }
+    let max_timeout_ms = limits.max_timeout_secs.saturating_mul(1000);
+    let timeout_ms = args
+        .timeout_ms
+        .unwrap_or(DEFAULT_TIMEOUT_MS.min(max_timeout_ms));
+    if timeout_ms == 0 || timeout_ms > max_timeout_ms {
+        bail!(
+            "timeout_ms must be between 1 and {} (requested: {})",
+            max_timeout_ms,
+            timeout_ms
+        );
+    }
+    let max_entries = args
+        .max_entries
+        .unwrap_or(DEFAULT_MAX_ENTRIES.min(limits.max_entries));
+    if max_entries == 0 || max_entries > limits.max_entries {
+        bail!(
+            "max_entries must be between 1 and {} (requested: {})",
+            limits.max_entries,
+            max_entries
+        );
+    }
+    let listing = listing_id(&args);
+    let after = match &args.cursor {
+        Some(cursor) => Some(Cursor::decode(cursor, &listing)?.after),
+        None => None,
+    };
+    let include = glob_set("include", &args.include)?;
+    let exclude = glob_set("exclude", &args.exclude)?;
+    let skip: HashSet<String> = match args.skip {
+        Some(skip) => skip.into_iter().collect(),
+        None => DEFAULT_SKIPS.iter().map(|name| name.to_string()).collect(),
+    };
 
-    // Get workspace root for security validation
-    let workspace_root = std::env::current_dir()?;
-    let workspace_canonical = fs::canonicalize(&workspace_root)
-        .map_err(|e| anyhow::anyhow!("Failed to canonicalize workspace: {}", e))?;
+    let mut details = args.details;
+    // git runs commands that a checkout's configuration names (such as
+    // clean filters), so it only runs in the workspace the user opened
+    if details.contains(&Detail::GitStatus)
+        && !workspace::root().is_ok_and(|workspace| root_canonical.starts_with(workspace))
+    {
+        tracing::debug!("Leaving out git status outside the workspace");
+        details.retain(|detail| *detail != Detail::GitStatus);
+    }
 
-    // Normalize and canonicalize requested path
-    let root_path = PathBuf::from(&args.path);
-    let is_absolute = root_path.is_absolute();
-    let root_path = if is_absolute {
-        root_path
+    // Dropping this future, as when the request is cancelled, stops the walk
+    // and git
+    let cancel = CancellationToken::new();
+    let _stop_walk = cancel.clone().drop_guard();
+    let options = WalkOptions {
+        root: root_canonical.clone(),
+        max_depth: args.depth + 1,
+        follow_symlinks: args.follow_symlinks,
+        show_hidden: args.show_hidden,
+        gitignore: args.gitignore,
+        filter: Arc::new(EntryFilter {
+            root: root_canonical.clone(),
+            skip,
+            exclude,
+        }),
+        include,
+        after: after.clone(),
+        page_size: max_entries,
+        details,
+        deadline: Instant::now() + Duration::from_millis(timeout_ms),
+    };
+    let mut walked = tokio::task::spawn_blocking(move || walk(&options, &cancel)).await?;
+    if walked.cancelled {
+        bail!("local_map was cancelled");
+    }
+
+    let truncated = walked.next_depth.is_some();
+    if walked.dir_size {
+        add_dir_sizes(&mut walked.entries);
+    }
+    // Everything before the last entry has been listed, so a page cut
+    // short by the timeout continues from there too, unless the timeout
+    // left its git status out
+    let next_cursor = if truncated || walked.timed_out {
+        let after = match walked.entries.last() {
+            Some((key, _)) if !walked.missing_details => key.clone(),
+            _ => after.unwrap_or_default(),
+        };
+        Some(Cursor { listing, after }.encode()?)
     } else {
-        workspace_root.join(&root_path)
+        None
     };
 
-    if !root_path.exists() {
-        bail!("Path does not exist: {}", root_path.display());
+    let entries: Vec<LocalMapEntry> = walked.entries.into_iter().map(|(_, entry)| entry).collect();
+    let mut result = LocalMapResult {
+        root: root_canonical.to_string_lossy().to_string(),
+        entries: None,
+        tree: None,
+        paths: None,
+        truncated: if truncated { Some(true) } else { None },
+        timed_out: if walked.timed_out { Some(true) } else { None },
+        next_cursor,
+    };
+    match args.format {
+        Format::Json => result.entries = Some(entries),
+        Format::Tree => {
+            result.tree = Some(render_tree(&root_canonical, &entries, walked.next_depth))
+        }
+        Format::Paths => {
+            result.paths = Some(
+                entries
+                    .iter()
+                    .map(|entry| relative_path(&root_canonical, entry))
+                    .collect(),
+            )
+        }
     }
 
-    let root_canonical = fs::canonicalize(&root_path)
-        .map_err(|e| anyhow::anyhow!("Failed to canonicalize path: {}", e))?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::to_value(result)?,
+        error: None,
+    })
+}
 
-    // Security: Validate relative paths stay within workspace to prevent path traversal
-    // Allow absolute paths for testing and explicit use cases
-    if !is_absolute && !root_canonical.starts_with(&workspace_canonical) {
-        bail!(
-            "Access denied: path '{}' is outside workspace root '{}'",
-            root_canonical.display(),
-            workspace_canonical.display()
-        );
-    }
+/// What to collect in a walk.
+struct WalkOptions {
+    root: PathBuf,
+    /// Entries deeper than this are not listed
+    max_depth: u32,
+    follow_symlinks: bool,
+    show_hidden: bool,
+    gitignore: bool,
+    filter: Arc<EntryFilter>,
+    include: Option<GlobSet>,
+    /// Only entries after this position in the listing are collected
+    after: Option<SortKey>,
+    /// The walk stops once it has more entries than this
+    page_size: usize,
+    details: Vec<Detail>,
+    deadline: Instant,
+}
 
-    let start_time = Instant::now();
-    let mut entries = Vec::new();
-    let mut queue: VecDeque<(PathBuf, u32)> = VecDeque::new();
-    let mut truncated = false;
-    let mut timed_out = false;
+/// The `skip` and `exclude` arguments, applied as directories are read.
+struct EntryFilter {
+    root: PathBuf,
+    skip: HashSet<String>,
+    exclude: Option<GlobSet>,
+}
 
-    queue.push_back((root_canonical.clone(), 0));
+impl EntryFilter {
+    fn keeps(&self, entry: &DirEntry) -> bool {
+        let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+        let name = entry.file_name().to_string_lossy();
+        let skipped = is_dir && self.skip.contains(name.as_ref());
+        let excluded = self
+            .exclude
+            .as_ref()
+            .is_some_and(|set| matches(set, &self.root, entry.path()));
+        entry.depth() == 0 || !(skipped || excluded)
+    }
+}
 
-    while let Some((current_path, current_depth)) = queue.pop_front() {
-        // Check timeout
-        if start_time.elapsed() > Duration::from_secs(TIMEOUT_SECS) {
-            timed_out = true;
-            break;
-        }
+/// An entry of a directory being listed.
+struct Child {
+    key: SortKey,
+    entry: LocalMapEntry,
+    metadata: fs::Metadata,
+    /// The walk goes into this directory
+    enter: bool,
+    /// Its entries, once read; `None` when it is not entered or the
+    /// deadline cut the read off
+    contents: Option<Vec<Child>>,
+}
 
-        // Check entry limit
-        if entries.len() >= MAX_ENTRIES {
-            truncated = true;
-            break;
-        }
+/// The entries of `dir`, which are at `depth`, in listing order; `None`
+/// when the deadline passes or `cancel` fires first.
+fn read_dir(
+    dir: &Path,
+    depth: u32,
+    options: &WalkOptions,
+    cancel: &CancellationToken,
+) -> Option<Vec<Child>> {
+    if cancel.is_cancelled() || Instant::now() > options.deadline {
+        return None;
+    }
+    let filter = options.filter.clone();
+    let walker = WalkBuilder::new(dir)
+        .max_depth(Some(1))
+        .follow_links(options.follow_symlinks)
+        .hidden(!options.show_hidden)
+        .git_ignore(options.gitignore)
+        .git_global(options.gitignore)
+        .git_exclude(options.gitignore)
+        .ignore(options.gitignore)
+        // Ignore files above `dir` still apply to it
+        .parents(options.gitignore)
+        // .gitignore applies even outside a git checkout
+        .require_git(false)
+        .filter_entry(move |entry| filter.keeps(entry))
+        .build();
 
-        // Don't traverse beyond requested depth
-        if current_depth > args.depth {
+    let mut children = Vec::new();
+    for entry_result in walker {
+        let entry = match entry_result {
+            Ok(e) => e,
+            Err(e) => {
+                tracing::warn!("Failed to read entry: {}", e);
+                continue;
+            }
+        };
+        if entry.depth() == 0 {
             continue;
         }
 
-        // Read directory entries
-        let read_dir = match fs::read_dir(&current_path) {
-            Ok(rd) => rd,
+        let entry_path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(m) => m,
             Err(e) => {
-                tracing::warn!("Failed to read directory {}: {}", current_path.display(), e);
+                tracing::warn!(
+                    "Failed to read metadata for {}: {}",
+                    entry_path.display(),
+                    e
+                );
                 continue;
             }
         };
+        let is_dir = metadata.is_dir();
+        let relative = entry_path.strip_prefix(&options.root).unwrap_or(entry_path);
+        children.push(Child {
+            key: sort_key(relative, is_dir),
+            entry: LocalMapEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                path: entry_path.to_string_lossy().to_string(),
+                is_dir,
+                is_symlink: entry.path_is_symlink(),
+                size_bytes: if is_dir { 0 } else { metadata.len() },
+                depth,
+                modified: None,
+                permissions: None,
+                language: None,
+                lines: None,
+                git_status: None,
+            },
+            metadata,
+            enter: false,
+            contents: None,
+        });
+    }
+    children.sort_by(|a, b| a.key.last().cmp(&b.key.last()));
+    Some(children)
+}
 
-        for entry_result in read_dir {
-            // Check timeout on each iteration
-            if start_time.elapsed() > Duration::from_secs(TIMEOUT_SECS) {
-                timed_out = true;
-                break;
-            }
+/// Read `dirs`, each with the depth of its entries, on up to
+/// `MAX_READERS` threads.
+fn read_dirs(
+    dirs: &[(PathBuf, u32)],
+    options: &WalkOptions,
+    cancel: &CancellationToken,
+) -> Vec<Option<Vec<Child>>> {
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(MAX_READERS)
+        .min(dirs.len());
+    if threads <= 1 {
+        return dirs
+            .iter()
+            .map(|(dir, depth)| read_dir(dir, *depth, options, cancel))
+            .collect();
+    }
 
-            // Check entry limit
-            if entries.len() >= MAX_ENTRIES {
-                truncated = true;
-                break;
+    let next = AtomicUsize::new(0);
+    let mut contents: Vec<Option<Vec<Child>>> = dirs.iter().map(|_| None).collect();
+    thread::scope(|scope| {
+        let readers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut read = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((dir, depth)) = dirs.get(index) else {
+                            break;
+                        };
+                        read.push((index, read_dir(dir, *depth, options, cancel)));
+                    }
+                    read
+                })
+            })
+            .collect();
+        for reader in readers {
+            let read = reader
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+            for (index, read) in read {
+                contents[index] = read;
             }
+        }
+    });
+    contents
+}
 
-            let entry = match entry_result {
-                Ok(e) => e,
-                Err(e) => {
-                    tracing::warn!("Failed to read entry: {}", e);
-                    continue;
+/// Decide which of `children`, the entries of the directory innermost in
+/// `ancestors`, the walk goes into, and read those in parallel.
+fn open(
+    mut children: Vec<Child>,
+    ancestors: &[FileId],
+    visited: &mut HashSet<FileId>,
+    options: &WalkOptions,
+    cancel: &CancellationToken,
+) -> Vec<Child> {
+    children.retain_mut(|child| {
+        if !child.entry.is_dir || child.entry.depth >= options.max_depth {
+            return true;
+        }
+        // Nothing below a directory before the cursor is listed, unless
+        // the cursor is inside it
+        if options
+            .after
+            .as_ref()
+            .is_some_and(|after| child.key <= *after && !after.starts_with(&child.key))
+        {
+            return true;
+        }
+        if options.follow_symlinks {
+            if let Some(id) = file_id(&child.metadata, Path::new(&child.entry.path)) {
+                // A link back to a directory above ends a cycle
+                if ancestors.contains(&id) {
+                    tracing::warn!("Skipping symlink cycle at {}", child.entry.path);
+                    return false;
+                }
+                // A directory seen before is listed but not entered again
+                if !visited.insert(id) {
+                    return true;
                 }
+            }
+        }
+        child.enter = true;
+        true
+    });
+
+    let dirs: Vec<(PathBuf, u32)> = children
+        .iter()
+        .filter(|child| child.enter)
+        .map(|child| (PathBuf::from(&child.entry.path), child.entry.depth + 1))
+        .collect();
+    let contents = read_dirs(&dirs, options, cancel);
+    for (child, contents) in children
+        .iter_mut()
+        .filter(|child| child.enter)
+        .zip(contents)
+    {
+        child.contents = contents;
+    }
+    children
+}
+
+/// Walk `options.root` depth first in listing order, collecting the entries
+/// after `options.after` until there are more than a page of them, the
+/// deadline passes or `cancel` fires. The entries of each directory entered
+/// are read in parallel, one level ahead of the listing, and sorted before
+/// they are listed, so the order does not depend on which read finishes
+/// first.
+fn walk(options: &WalkOptions, cancel: &CancellationToken) -> Walked {
+    let root = options.root.as_path();
+    let include = options.include.as_ref();
+    let wants = |detail| options.details.contains(&detail);
+    let mut entries = Vec::new();
+    // With `include`, directories are only listed on the way to a match, so
+    // they wait here until a file below them is
+    let mut pending: Vec<(SortKey, LocalMapEntry, fs::Metadata)> = Vec::new();
+    let mut timed_out = false;
+
+    let root_id = fs::metadata(root)
+        .ok()
+        .and_then(|metadata| file_id(&metadata, root));
+    let mut visited: HashSet<FileId> = root_id.iter().cloned().collect();
+    // The directories being listed, innermost last, with their identity
+    let mut stack: Vec<(std::vec::IntoIter<Child>, Option<FileId>)> = Vec::new();
+    match read_dir(root, 1, options, cancel) {
+        Some(children) => {
+            let ancestors: Vec<FileId> = root_id.iter().cloned().collect();
+            let children = open(children, &ancestors, &mut visited, options, cancel);
+            stack.push((children.into_iter(), root_id));
+        }
+        None => timed_out = Instant::now() > options.deadline,
+    }
+
+    while entries.len() <= options.page_size && !cancel.is_cancelled() {
+        if Instant::now() > options.deadline {
+            timed_out = true;
+            break;
+        }
+        let Some((children, _)) = stack.last_mut() else {
+            break;
+        };
+        let Some(child) = children.next() else {
+            stack.pop();
+            continue;
+        };
+        let Child {
+            key,
+            entry: listed,
+            metadata,
+            enter,
+            contents,
+        } = child;
+        if enter {
+            // The read of its entries was cut off
+            let Some(contents) = contents else {
+                timed_out = true;
+                break;
             };
+            let id = options
+                .follow_symlinks
+                .then(|| file_id(&metadata, Path::new(&listed.path)))
+                .flatten();
+            let ancestors: Vec<FileId> = stack
+                .iter()
+                .filter_map(|(_, id)| id.as_ref())
+                .chain(id.as_ref())
+                .cloned()
+                .collect();
+            let contents = open(contents, &ancestors, &mut visited, options, cancel);
+            stack.push((contents.into_iter(), id));
+        }
 
-            let entry_path = entry.path();
-            let file_name = entry.file_name();
-            let name = file_name.to_string_lossy().to_string();
+        // The directories the cursor is in were listed on earlier pages
+        if options.after.as_ref().is_some_and(|after| key <= *after) {
+            continue;
+        }
+        let is_dir = listed.is_dir;
+        if !is_dir && include.is_some_and(|set| !matches(set, root, Path::new(&listed.path))) {
+            continue;
+        }
+        if include.is_none() {
+            entries.push((key, listed, metadata));
+            continue;
+        }
+        // Entries come depth first, so the pending directories above this
+        // one are its ancestors
+        pending.retain(|(_, dir, _)| dir.depth < listed.depth);
+        if is_dir {
+            pending.push((key, listed, metadata));
+        } else {
+            entries.append(&mut pending);
+            entries.push((key, listed, metadata));
+        }
+    }
 
-            // Skip hidden files (start with .)
-            if name.starts_with('.') {
-                continue;
+    // Details can be costly, so only the entries on the page get them
+    let next_depth = entries
+        .get(options.page_size)
+        .map(|(_, entry, _)| entry.depth);
+    entries.truncate(options.page_size);
+    let mut missing_details = false;
+    let git = if wants(Detail::GitStatus) && !entries.is_empty() {
+        let git = git_statuses(root, options.deadline, cancel);
+        if git.is_none() && Instant::now() > options.deadline {
+            timed_out = true;
+            missing_details = true;
+        }
+        git
+    } else {
+        None
+    };
+    let entries = entries
+        .into_iter()
+        .map(|(key, mut entry, metadata)| {
+            add_details(&mut entry, &metadata, &options.details, git.as_ref());
+            (key, entry)
+        })
+        .collect();
+    Walked {
+        entries,
+        next_depth,
+        missing_details,
+        dir_size: wants(Detail::DirSize),
+        timed_out,
+        cancelled: cancel.is_cancelled(),
+    }
+}
+
+/// Fill in the `details` of `entry`, except for directory sizes.
+fn add_details(
+    entry: &mut LocalMapEntry,
+    metadata: &fs::Metadata,
+    details: &[Detail],
+    git: Option<&HashMap<PathBuf, GitStatus>>,
+) {
+    let path = Path::new(&entry.path);
+    for detail in details {
+        match detail {
+            Detail::Modified => {
+                entry.modified = metadata.modified().ok().map(DateTime::<Utc>::from)
+            }
+            Detail::Permissions => entry.permissions = Some(permissions(metadata)),
+            Detail::Language if !entry.is_dir => {
+                entry.language = language(path).map(str::to_string)
             }
+            Detail::Lines if !entry.is_dir => entry.lines = count_lines(path, entry.size_bytes),
+            Detail::GitStatus if !entry.is_dir => {
+                entry.git_status = git.and_then(|git| git.get(path).copied())
+            }
+            _ => {}
+        }
+    }
+}
 
-            // Skip node_modules and .git directories
-            if name == "node_modules" || name == ".git" {
-                continue;
+/// Set the size of each directory to the total size of the files listed
+/// below it. `entries` must be in listing order, each directory before its
+/// contents.
+fn add_dir_sizes(entries: &mut [(SortKey, LocalMapEntry)]) {
+    let mut totals: HashMap<PathBuf, u64> = HashMap::new();
+    for (_, entry) in entries.iter().filter(|(_, entry)| !entry.is_dir) {
+        for dir in Path::new(&entry.path).ancestors().skip(1) {
+            *totals.entry(dir.to_path_buf()).or_default() += entry.size_bytes;
+        }
+    }
+    for (_, entry) in entries.iter_mut().filter(|(_, entry)| entry.is_dir) {
+        entry.size_bytes = totals.get(Path::new(&entry.path)).copied().unwrap_or(0);
+    }
+}
+
+#[cfg(unix)]
+fn permissions(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = metadata.permissions().mode();
+    (0..9)
+        .map(|bit| {
+            if mode & (0o400 >> bit) == 0 {
+                '-'
+            } else {
+                ['r', 'w', 'x'][bit % 3]
             }
+        })
+        .collect()
+}
 
-            let metadata = match entry.metadata() {
-                Ok(m) => m,
-                Err(e) => {
-                    tracing::warn!("Failed to read metadata for {}: {}", entry_path.display(), e);
-                    continue;
-                }
-            };
+#[cfg(not(unix))]
+fn permissions(metadata: &fs::Metadata) -> String {
+    if metadata.permissions().readonly() {
+        "r--".to_string()
+    } else {
+        "rw-".to_string()
+    }
+}
 
-            let is_symlink = metadata.file_type().is_symlink();
-            let is_dir = metadata.is_dir();
-            let size_bytes = if is_dir { 0 } else { metadata.len() };
+/// Language of a source file, from its name or extension.
+fn language(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?;
+    let by_name = match name {
+        "Dockerfile" | "Containerfile" => Some("Dockerfile"),
+        "Makefile" | "GNUmakefile" => Some("Makefile"),
+        "CMakeLists.txt" => Some("CMake"),
+        _ => None,
+    };
+    if by_name.is_some() {
+        return by_name;
+    }
+    let language = match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "rs" => "Rust",
+        "py" | "pyi" => "Python",
+        "js" | "mjs" | "cjs" | "jsx" => "JavaScript",
+        "ts" | "mts" | "cts" | "tsx" => "TypeScript",
+        "go" => "Go",
+        "java" => "Java",
+        "kt" | "kts" => "Kotlin",
+        "swift" => "Swift",
+        "c" | "h" => "C",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "C++",
+        "cs" => "C#",
+        "rb" => "Ruby",
+        "php" => "PHP",
+        "scala" => "Scala",
+        "sh" | "bash" | "zsh" => "Shell",
+        "sql" => "SQL",
+        "html" | "htm" => "HTML",
+        "css" | "scss" | "sass" | "less" => "CSS",
+        "vue" => "Vue",
+        "svelte" => "Svelte",
+        "md" | "markdown" => "Markdown",
+        "json" => "JSON",
+        "yaml" | "yml" => "YAML",
+        "toml" => "TOML",
+        "xml" => "XML",
+        "proto" => "Protocol Buffers",
+        "lua" => "Lua",
+        "dart" => "Dart",
+        "ex" | "exs" => "Elixir",
+        "hs" => "Haskell",
+        "zig" => "Zig",
+        _ => return None,
+    };
+    Some(language)
+}
 
-            entries.push(LocalMapEntry {
-                name: name.clone(),
-                path: entry_path.to_string_lossy().to_string(),
-                is_dir,
-                is_symlink,
-                size_bytes,
-                depth: current_depth + 1,
-            });
-
-            // Queue directories for traversal
-            if is_dir && current_depth + 1 <= args.depth {
-                // Don't traverse symlinked directories unless follow_symlinks is true
-                if !is_symlink || args.follow_symlinks {
-                    queue.push_back((entry_path, current_depth + 1));
-                }
+/// Number of lines in a text file; `None` for binary and very large files.
+fn count_lines(path: &Path, size_bytes: u64) -> Option<u64> {
+    if size_bytes > MAX_LINE_COUNT_BYTES {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    fs::File::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+    if files::looks_binary(&bytes) {
+        return None;
+    }
+    let newlines = bytes.iter().filter(|&&byte| byte == b'\n').count() as u64;
+    Some(newlines + u64::from(bytes.last().is_some_and(|&byte| byte != b'\n')))
+}
+
+/// Run git in `root` and return its output, or `None` when it fails or
+/// has to be killed because the deadline passed or `cancel` fired.
+fn run_git(
+    root: &Path,
+    args: &[&str],
+    deadline: Instant,
+    cancel: &CancellationToken,
+) -> Option<Vec<u8>> {
+    if Instant::now() > deadline || cancel.is_cancelled() {
+        return None;
+    }
+    let mut child = Command::new("git")
+        // An fsmonitor hook would run a command from the checkout's config
+        .args(["-c", "core.fsmonitor=false", "-C"])
+        .arg(root)
+        .args(args)
+        // Reading status must not take the index lock from the user's git
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| tracing::debug!("Failed to run git: {}", e))
+        .ok()?;
+    // Read on another thread so that git never blocks on a full pipe
+    let mut stdout = child.stdout.take()?;
+    let output = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                let output = output.join().ok()?.ok()?;
+                return status.success().then_some(output);
+            }
+            Ok(None) if Instant::now() > deadline || cancel.is_cancelled() => {
+                tracing::debug!("Stopping git {}", args.join(" "));
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(5)),
+            Err(e) => {
+                tracing::debug!("Failed to wait for git: {}", e);
+                return None;
             }
         }
+    }
+}
 
-        if truncated || timed_out {
-            break;
+/// Status of the changed and untracked files of the git checkout around
+/// `root`, keyed by absolute path; `None` outside a checkout, without git,
+/// or when git does not finish before the deadline.
+fn git_statuses(
+    root: &Path,
+    deadline: Instant,
+    cancel: &CancellationToken,
+) -> Option<HashMap<PathBuf, GitStatus>> {
+    let git = |args: &[&str]| run_git(root, args, deadline, cancel);
+    let toplevel = git(&["rev-parse", "--show-toplevel"])?;
+    let toplevel = fs::canonicalize(String::from_utf8_lossy(&toplevel).trim()).ok()?;
+    let status = git(&[
+        "status",
+        "--porcelain=v1",
+        "-z",
+        "--untracked-files=all",
+        "--",
+        ".",
+    ])?;
+
+    let mut statuses = HashMap::new();
+    let mut records = status.split(|&byte| byte == 0);
+    while let Some(record) = records.next() {
+        if record.len() < 4 {
+            continue;
+        }
+        let (code, path) = (&record[..2], String::from_utf8_lossy(&record[3..]));
+        let status = match code {
+            b"??" => GitStatus::Untracked,
+            [b'A', _] => GitStatus::Added,
+            _ => GitStatus::Modified,
+        };
+        // Renames and copies are followed by the original path
+        if matches!(code[0], b'R' | b'C') {
+            records.next();
         }
+        statuses.insert(toplevel.join(path.as_ref()), status);
     }
+    Some(statuses)
+}
 
-    let result = LocalMapResult {
-        root: root_canonical.to_string_lossy().to_string(),
-        entries,
-        truncated: if truncated { Some(true) } else { None },
-        timed_out: if timed_out { Some(true) } else { None },
-    };
+/// Path of `entry` relative to `root`, with a trailing `/` for directories.
+fn relative_path(root: &Path, entry: &LocalMapEntry) -> String {
+    let path = Path::new(&entry.path);
+    let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy();
+    if entry.is_dir {
+        format!("{}/", relative)
+    } else {
+        relative.into_owned()
+    }
+}
 
-    Ok(ToolResponse {
-        success: true,
-        result: serde_json::to_value(result)?,
-        error: None,
-    })
+/// Draw `entries`, one page in listing order, as the `tree` command does.
+/// A page that starts below the root first repeats the directories above
+/// its first entry; `next_depth` is the depth of the entry after the page.
+fn render_tree(root: &Path, entries: &[LocalMapEntry], next_depth: Option<u32>) -> String {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    if let Some(first) = entries.first() {
+        let path = Path::new(&first.path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let above = relative.parent().unwrap_or(Path::new(""));
+        lines.extend(above.components().enumerate().map(|(index, component)| {
+            let name = component.as_os_str().to_string_lossy();
+            (index + 1, format!("{}/", name))
+        }));
+    }
+    lines.extend(entries.iter().map(|entry| {
+        let name = if entry.is_dir {
+            format!("{}/", entry.name)
+        } else {
+            entry.name.clone()
+        };
+        (entry.depth as usize, name)
+    }));
+
+    // An entry is the last of its siblings when no entry at its depth
+    // follows before the listing climbs back above it. The next page may
+    // continue any branch down to the depth of its first entry.
+    let mut is_last = vec![false; lines.len()];
+    let mut seen_below = vec![true; next_depth.map_or(0, |depth| depth as usize + 1)];
+    for (index, (depth, _)) in lines.iter().enumerate().rev() {
+        seen_below.resize(depth + 1, false);
+        is_last[index] = !seen_below[*depth];
+        seen_below[*depth] = true;
+        seen_below.truncate(depth + 1);
+    }
+
+    let mut tree = format!("{}\n", root.display());
+    let mut open: Vec<bool> = Vec::new();
+    for ((depth, name), last) in lines.iter().zip(is_last) {
+        open.truncate(depth.saturating_sub(1));
+        for &more in &open {
+            tree.push_str(if more { "│   " } else { "    " });
+        }
+        tree.push_str(if last { "└── " } else { "├── " });
+        tree.push_str(name);
+        tree.push('\n');
+        open.push(!last);
+    }
+    tree
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs::{create_dir, File};
+    use std::fs::{self, create_dir, File};
     use tempfile::TempDir;
 
     #[tokio::test]
@@ -239,14 +1099,15 @@ mod tests {
             "depth": 2
         });
 
-        let response = execute(args).await.unwrap();
+        let response = execute(args, &LocalMapConfig::default()).await.unwrap();
         let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
 
         // Should have: visible_file.txt, subdir, nested_file.txt (3 entries)
         // Should NOT have: .hidden_file.txt, node_modules, should_skip.txt
-        assert_eq!(result.entries.len(), 3);
+        let entries = result.entries.unwrap();
+        assert_eq!(entries.len(), 3);
 
-        let names: Vec<&str> = result.entries.iter().map(|e| e.name.as_str()).collect();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
         assert!(names.contains(&"visible_file.txt"));
         assert!(names.contains(&"subdir"));
         assert!(names.contains(&"nested_file.txt"));
@@ -256,6 +1117,340 @@ mod tests {
         assert!(!names.contains(&"should_skip.txt"));
     }
 
+    #[tokio::test]
+    async fn test_local_map_filters() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        // temp/
+        //   .gitignore      (ignores target/)
+        //   .env
+        //   README.md
+        //   src/main.rs
+        //   target/out.rs
+        //   node_modules/pkg.js
+        fs::write(temp_path.join(".gitignore"), "target/\n").unwrap();
+        File::create(temp_path.join(".env")).unwrap();
+        File::create(temp_path.join("README.md")).unwrap();
+        create_dir(temp_path.join("src")).unwrap();
+        File::create(temp_path.join("src/main.rs")).unwrap();
+        create_dir(temp_path.join("target")).unwrap();
+        File::create(temp_path.join("target/out.rs")).unwrap();
+        create_dir(temp_path.join("node_modules")).unwrap();
+        File::create(temp_path.join("node_modules/pkg.js")).unwrap();
+
+        let names = |args: serde_json::Value| async move {
+            let response = execute(args, &LocalMapConfig::default()).await.unwrap();
+            let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+            let entries = result.entries.unwrap();
+            let mut names: Vec<String> = entries.into_iter().map(|e| e.name).collect();
+            names.sort();
+            names
+        };
+        let root = temp_path.to_str().unwrap();
+
+        assert_eq!(
+            names(serde_json::json!({ "path": root })).await,
+            ["README.md", "main.rs", "src"]
+        );
+        assert_eq!(
+            names(serde_json::json!({ "path": root, "include": ["*.rs"] })).await,
+            ["main.rs", "src"]
+        );
+        assert_eq!(
+            names(serde_json::json!({ "path": root, "exclude": ["src"] })).await,
+            ["README.md"]
+        );
+        assert_eq!(
+            names(serde_json::json!({
+                "path": root,
+                "include": ["*.rs", "*.js"],
+                "gitignore": false,
+                "skip": []
+            }))
+            .await,
+            [
+                "main.rs",
+                "node_modules",
+                "out.rs",
+                "pkg.js",
+                "src",
+                "target"
+            ]
+        );
+        assert!(
+            names(serde_json::json!({ "path": root, "show_hidden": true }))
+                .await
+                .contains(&".env".to_string())
+        );
+
+        let invalid = execute(
+            serde_json::json!({ "path": root, "include": ["a{"] }),
+            &LocalMapConfig::default(),
+        )
+        .await;
+        assert!(invalid
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid include glob"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_local_map_limits_and_symlink_cycles() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        // temp/a/loop -> temp would be walked forever, and temp/b -> temp/a
+        // would list a's contents twice
+        create_dir(temp_path.join("a")).unwrap();
+        File::create(temp_path.join("a/file.txt")).unwrap();
+        std::os::unix::fs::symlink(temp_path, temp_path.join("a/loop")).unwrap();
+        std::os::unix::fs::symlink(temp_path.join("a"), temp_path.join("b")).unwrap();
+
+        let limits = LocalMapConfig::default();
+        let args = serde_json::json!({
+            "path": temp_path.to_str().unwrap(),
+            "depth": 6,
+            "follow_symlinks": true
+        });
+        let response = execute(args, &limits).await.unwrap();
+        let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+        let entries = result.entries.unwrap();
+        let mut names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["a", "b", "file.txt"]);
+
+        let args = serde_json::json!({
+            "path": temp_path.to_str().unwrap(),
+            "max_entries": 1
+        });
+        let response = execute(args, &limits).await.unwrap();
+        let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+        assert_eq!(result.entries.unwrap().len(), 1);
+        assert_eq!(result.truncated, Some(true));
+        assert!(result.next_cursor.is_some());
+
+        let args = serde_json::json!({ "path": temp_path.to_str().unwrap(), "timeout_ms": 60_000 });
+        let error = execute(args, &limits).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("timeout_ms must be between 1 and 30000"));
+    }
+
+    #[tokio::test]
+    async fn test_local_map_pages() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+
+        create_dir(temp_path.join("b")).unwrap();
+        File::create(temp_path.join("b/z.txt")).unwrap();
+        create_dir(temp_path.join("b/a")).unwrap();
+        File::create(temp_path.join("b/a/inner.txt")).unwrap();
+        File::create(temp_path.join("a.txt")).unwrap();
+        create_dir(temp_path.join("c")).unwrap();
+
+        let limits = LocalMapConfig::default();
+        let root = temp_path.to_str().unwrap();
+        let page = |cursor: Option<String>| {
+            let args = serde_json::json!({ "path": root, "page_size": 2, "cursor": cursor });
+            let limits = &limits;
+            async move {
+                let response = execute(args, limits).await.unwrap();
+                serde_json::from_value::<LocalMapResult>(response.result).unwrap()
+            }
+        };
+        let all_pages = |mut args: serde_json::Value| {
+            let limits = &limits;
+            async move {
+                let mut names = Vec::new();
+                loop {
+                    let response = execute(args.clone(), limits).await.unwrap();
+                    let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+                    let entries = result.entries.unwrap();
+                    assert!(entries.len() <= args["page_size"].as_u64().unwrap() as usize);
+                    names.extend(entries.into_iter().map(|e| e.name));
+                    let Some(cursor) = result.next_cursor else {
+                        assert_eq!(result.truncated, None);
+                        return names;
+                    };
+                    assert_eq!(result.truncated, Some(true));
+                    args["cursor"] = cursor.into();
+                }
+            }
+        };
+
+        // Directories first, then files, each by name; a directory is
+        // followed by its contents
+        assert_eq!(
+            all_pages(serde_json::json!({ "path": root, "page_size": 2 })).await,
+            ["b", "a", "inner.txt", "z.txt", "c", "a.txt"]
+        );
+        // Directories without matches are left out, and those with one are
+        // listed once, right before it
+        assert_eq!(
+            all_pages(serde_json::json!({ "path": root, "page_size": 1, "include": ["*.txt"] }))
+                .await,
+            ["b", "a", "inner.txt", "z.txt", "a.txt"]
+        );
+
+        // Each page of a tree shows the directories above it, and leaves
+        // open the branches the next page continues
+        let mut trees = Vec::new();
+        let mut args = serde_json::json!({ "path": root, "page_size": 2, "format": "tree" });
+        loop {
+            let response = execute(args.clone(), &limits).await.unwrap();
+            let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+            trees.push(result.tree.unwrap().split_once('\n').unwrap().1.to_string());
+            let Some(cursor) = result.next_cursor else {
+                break;
+            };
+            args["cursor"] = cursor.into();
+        }
+        assert_eq!(
+            trees,
+            [
+                "├── b/\n│   ├── a/\n",
+                "├── b/\n│   ├── a/\n│   │   └── inner.txt\n│   └── z.txt\n",
+                "├── c/\n└── a.txt\n",
+            ]
+        );
+
+        let cursor = page(None).await.next_cursor;
+        let args = serde_json::json!({ "path": root, "depth": 0, "cursor": cursor });
+        let error = execute(args, &limits).await.unwrap_err();
+        assert!(error.to_string().contains("different arguments"));
+        let args = serde_json::json!({ "path": root, "cursor": "not a cursor" });
+        assert!(execute(args, &limits).await.is_err());
+    }
+
+    fn walk_options(root: &Path, deadline: Instant) -> WalkOptions {
+        WalkOptions {
+            root: root.to_path_buf(),
+            max_depth: 3,
+            follow_symlinks: false,
+            show_hidden: false,
+            gitignore: true,
+            filter: Arc::new(EntryFilter {
+                root: root.to_path_buf(),
+                skip: HashSet::new(),
+                exclude: None,
+            }),
+            include: None,
+            after: None,
+            page_size: 1_000,
+            details: Vec::new(),
+            deadline,
+        }
+    }
+
+    #[test]
+    fn test_walk_reads_in_parallel_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut expected = Vec::new();
+        for dir in (0..20).rev() {
+            let dir = format!("d{:02}", dir);
+            create_dir(temp_dir.path().join(&dir)).unwrap();
+            for file in ["b.txt", "a.txt"] {
+                File::create(temp_dir.path().join(&dir).join(file)).unwrap();
+            }
+        }
+        for dir in 0..20 {
+            expected.extend([format!("d{:02}", dir), "a.txt".into(), "b.txt".into()]);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let options = walk_options(temp_dir.path(), deadline);
+        let walked = walk(&options, &CancellationToken::new());
+        let names: Vec<String> = walked.entries.into_iter().map(|(_, e)| e.name).collect();
+        assert_eq!(names, expected);
+
+        let options = walk_options(temp_dir.path(), Instant::now() - Duration::from_millis(1));
+        let walked = walk(&options, &CancellationToken::new());
+        assert!(walked.timed_out);
+        assert!(walked.entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_local_map_details_and_formats() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        create_dir(temp_path.join("src")).unwrap();
+        fs::write(temp_path.join("src/main.rs"), "fn main() {\n}\n").unwrap();
+        fs::write(temp_path.join("src/lib.rs"), "pub mod a;").unwrap();
+        fs::write(temp_path.join("data.bin"), [0u8, 1, 2, b'\n']).unwrap();
+        let root = temp_path.to_str().unwrap();
+
+        let args = serde_json::json!({
+            "path": root,
+            "details": ["modified", "permissions", "language", "lines", "git_status", "dir_size"]
+        });
+        let response = execute(args, &LocalMapConfig::default()).await.unwrap();
+        let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+        let entries = result.entries.unwrap();
+        let entry = |name: &str| entries.iter().find(|e| e.name == name).unwrap();
+        assert_eq!(entry("main.rs").language.as_deref(), Some("Rust"));
+        assert_eq!(entry("main.rs").lines, Some(2));
+        assert_eq!(entry("lib.rs").lines, Some(1));
+        assert_eq!(entry("data.bin").lines, None);
+        assert_eq!(entry("data.bin").language, None);
+        assert_eq!(entry("src").size_bytes, 24);
+        assert!(entry("main.rs").modified.is_some());
+        let permissions = entry("main.rs").permissions.clone().unwrap();
+        assert!(permissions.starts_with("rw-"));
+
+        let args = serde_json::json!({ "path": root, "format": "tree" });
+        let response = execute(args, &LocalMapConfig::default()).await.unwrap();
+        let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+        assert!(result.entries.is_none());
+        let tree = result.tree.unwrap();
+        let expected = "├── src/\n│   ├── lib.rs\n│   └── main.rs\n└── data.bin\n";
+        assert_eq!(tree.split_once('\n').unwrap().1, expected);
+
+        let args = serde_json::json!({ "path": root, "format": "paths" });
+        let response = execute(args, &LocalMapConfig::default()).await.unwrap();
+        let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
+        assert_eq!(
+            result.paths.unwrap(),
+            ["src/", "src/lib.rs", "src/main.rs", "data.bin"]
+        );
+    }
+
+    #[test]
+    fn test_git_statuses() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .arg("-C")
+                .arg(&root)
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        assert!(git(&["init", "-q"]), "git is needed for this test");
+        fs::write(root.join("tracked.txt"), "one").unwrap();
+        fs::write(root.join("added.txt"), "one").unwrap();
+        assert!(git(&["add", "tracked.txt"]));
+        assert!(git(&["commit", "-q", "-m", "initial"]));
+        fs::write(root.join("tracked.txt"), "two").unwrap();
+        assert!(git(&["add", "added.txt"]));
+        fs::write(root.join("new.txt"), "").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(30);
+        let cancel = CancellationToken::new();
+        let statuses = git_statuses(&root, deadline, &cancel).unwrap();
+        assert_eq!(statuses[&root.join("tracked.txt")], GitStatus::Modified);
+        assert_eq!(statuses[&root.join("added.txt")], GitStatus::Added);
+        assert_eq!(statuses[&root.join("new.txt")], GitStatus::Untracked);
+        assert_eq!(statuses.len(), 3);
+
+        assert!(git_statuses(&root, Instant::now() - Duration::from_millis(1), &cancel).is_none());
+        cancel.cancel();
+        assert!(git_statuses(&root, deadline, &cancel).is_none());
+    }
+
     #[tokio::test]
     async fn test_local_map_depth_limiting() {
         let temp_dir = TempDir::new().unwrap();
@@ -272,12 +1467,13 @@ mod tests {
             "depth": 1
         });
 
-        let response = execute(args).await.unwrap();
+        let response = execute(args, &LocalMapConfig::default()).await.unwrap();
         let result: LocalMapResult = serde_json::from_value(response.result).unwrap();
 
         // Should have: level1, level2 (depth 1 and 2)
         // Should NOT have: deep_file.txt (depth 3, beyond limit)
-        let names: Vec<&str> = result.entries.iter().map(|e| e.name.as_str()).collect();
+        let entries = result.entries.unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
         assert!(names.contains(&"level1"));
         assert!(names.contains(&"level2"));
         assert!(!names.contains(&"deep_file.txt"));
@@ -290,7 +1486,7 @@ mod tests {
             "depth": 10
         });
 
-        let response = execute(args).await;
+        let response = execute(args, &LocalMapConfig::default()).await;
         assert!(response.is_err());
         assert!(response.unwrap_err().to_string().contains("Depth must be between 0 and 6"));
     }
@@ -302,7 +1498,7 @@ mod tests {
             "depth": 1
         });
 
-        let response = execute(args).await;
+        let response = execute(args, &LocalMapConfig::default()).await;
         assert!(response.is_err());
         assert!(response.unwrap_err().to_string().contains("does not exist"));
     }
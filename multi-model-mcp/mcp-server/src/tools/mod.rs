@@ -1,19 +1,39 @@
-use crate::providers::ProviderType;
+use crate::budget::{BudgetTracker, QuotaExceeded, Reservation};
+use crate::config::LocalMapConfig;
+use crate::providers::tokens::estimate_request_tokens;
+use crate::providers::{CompletionRequest, CompletionResponse, ModelRegistry, ProviderType};
+use crate::usage::{UsageLedger, UsageRecord};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::sync::RwLock;
 
+pub mod chat;
 pub mod code_generation;
 pub mod code_review;
+pub mod compaction;
 pub mod context;
+pub mod continuation;
+pub mod files;
 pub mod local_map;
 pub mod model_switching;
+pub mod prompt;
+pub mod resources;
+pub mod session;
+pub mod structured;
+pub mod tool_loop;
+pub mod usage_report;
+pub mod workspace;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolRequest {
     pub tool: String,
     pub arguments: serde_json::Value,
+    /// Session named in the MCP request's `_meta`; a `session_id` argument
+    /// takes precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,29 +44,233 @@ pub struct ToolResponse {
     pub error: Option<String>,
 }
 
+/// Handle through which tools talk to the current provider. Every completion
+/// is fitted to the model's metadata, checked against the budgets and
+/// recorded in the usage ledger.
+#[derive(Clone)]
+pub struct ModelClient {
+    pub provider: Arc<RwLock<ProviderType>>,
+    pub registry: Arc<ModelRegistry>,
+    pub ledger: Arc<UsageLedger>,
+    pub budget: Arc<BudgetTracker>,
+}
+
+impl ModelClient {
+    /// Model to use when the caller did not specify one.
+    pub async fn default_model(&self) -> String {
+        self.provider.read().await.default_model().to_string()
+    }
+
+    /// Cheap model used to summarize context that does not fit a window.
+    pub async fn summary_model(&self) -> String {
+        self.provider.read().await.summary_model().to_string()
+    }
+
+    pub async fn complete(
+        &self,
+        tool: &str,
+        mut request: CompletionRequest,
+    ) -> Result<CompletionResponse> {
+        let provider = self.provider.read().await.clone();
+        self.registry.adapt(provider.name(), &mut request)?;
+
+        let reservation = match self.reserve_budget(provider.name(), &request) {
+            Ok(reservation) => reservation,
+            Err(exceeded) => {
+                let fallback = self
+                    .budget
+                    .limits(provider.name())
+                    .and_then(|limits| limits.fallback_model.clone());
+                match fallback {
+                    Some(fallback) if fallback != request.model => {
+                        tracing::warn!("{}; routing request to {}", exceeded, fallback);
+                        request.model = fallback;
+                        self.registry.adapt(provider.name(), &mut request)?;
+                        self.reserve_budget(provider.name(), &request)?
+                    }
+                    _ => return Err(exceeded.into()),
+                }
+            }
+        };
+
+        // Run in its own task so that a cancelled tool call does not drop the
+        // request halfway: once sent it is billed, and its usage still has to
+        // reach the budget and the ledger.
+        let client = self.clone();
+        let tool = tool.to_string();
+        tokio::spawn(async move { client.send(&provider, &tool, request, reservation).await })
+            .await?
+    }
+
+    /// Send `request` and record its usage against `reservation`.
+    async fn send(
+        &self,
+        provider: &ProviderType,
+        tool: &str,
+        request: CompletionRequest,
+        reservation: Reservation,
+    ) -> Result<CompletionResponse> {
+        let estimated_input_tokens = estimate_request_tokens(&request);
+        let started = Instant::now();
+        let mut response = provider.complete(request).await?;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let Some(usage) = &mut response.usage else {
+            // Without usage the estimate is all there is to account for
+            reservation.keep();
+            return Ok(response);
+        };
+        usage.estimated_input_tokens = Some(estimated_input_tokens);
+        let meta = self
+            .registry
+            .lookup(&response.model)
+            .filter(|_| self.registry.is_priced(&response.model));
+        let cost_usd = meta.as_ref().map(|meta| meta.usage_cost(usage));
+        let cache_savings_usd = meta.as_ref().map(|meta| meta.cache_savings(usage));
+        reservation.record(
+            usage.input_tokens as u64 + usage.output_tokens as u64,
+            cost_usd.unwrap_or(0.0),
+        );
+        self.ledger.record(&UsageRecord {
+            timestamp: chrono::Utc::now(),
+            provider: provider.name().to_string(),
+            model: response.model.clone(),
+            tool: tool.to_string(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_read_tokens: usage.cache_read_tokens,
+            cache_write_tokens: usage.cache_write_tokens,
+            reasoning_tokens: usage.reasoning_tokens,
+            cost_usd,
+            cache_savings_usd,
+            latency_ms,
+        });
+
+        Ok(response)
+    }
+
+    /// Reserve the worst case: estimated prompt plus the full `max_tokens`
+    /// output. Models without a known price reserve no cost, and so fail
+    /// any cost limit.
+    fn reserve_budget(
+        &self,
+        provider: &str,
+        request: &CompletionRequest,
+    ) -> Result<Reservation, QuotaExceeded> {
+        let input_tokens = estimate_request_tokens(request);
+        let output_tokens = request.max_tokens.unwrap_or(0);
+        let cost_usd = self
+            .registry
+            .lookup(&request.model)
+            .filter(|_| self.registry.is_priced(&request.model))
+            .map(|meta| meta.cost(input_tokens, output_tokens));
+        self.budget.reserve(
+            provider,
+            input_tokens as u64 + output_tokens as u64,
+            cost_usd,
+        )
+    }
+}
+
+#[cfg(test)]
+impl ModelClient {
+    /// A client for `provider` without budgets. Scripted replies carry no
+    /// usage, so nothing is written to the ledger.
+    pub fn scripted(provider: crate::providers::scripted::ScriptedProvider) -> Self {
+        let ledger = UsageLedger::open(Some(&std::env::temp_dir().join("scripted-usage.jsonl")));
+        Self {
+            budget: Arc::new(BudgetTracker::new(Default::default(), &ledger)),
+            provider: Arc::new(RwLock::new(ProviderType::Scripted(provider))),
+            registry: Arc::new(ModelRegistry::default()),
+            ledger: Arc::new(ledger),
+        }
+    }
+}
+
 pub struct ToolExecutor {
     pub current_provider: Arc<RwLock<ProviderType>>,
     pub available_providers: Vec<ProviderType>,
-    pub context: Arc<RwLock<context::ConversationContext>>,
+    pub sessions: session::SessionStore,
+    pub subscriptions: resources::Subscriptions,
+    pub client: ModelClient,
+    /// Server maximums for `local_map` limits
+    pub local_map: LocalMapConfig,
 }
 
 impl ToolExecutor {
-    pub fn new(providers: Vec<ProviderType>) -> Self {
+    pub fn new(
+        providers: Vec<ProviderType>,
+        registry: ModelRegistry,
+        ledger: UsageLedger,
+        budget: BudgetTracker,
+        sessions: session::SessionStore,
+        local_map: LocalMapConfig,
+    ) -> Self {
         let default_provider = providers.first().cloned().unwrap();
+        let current_provider = Arc::new(RwLock::new(default_provider));
         Self {
-            current_provider: Arc::new(RwLock::new(default_provider)),
+            client: ModelClient {
+                provider: current_provider.clone(),
+                registry: Arc::new(registry),
+                ledger: Arc::new(ledger),
+                budget: Arc::new(budget),
+            },
+            current_provider,
             available_providers: providers,
-            context: Arc::new(RwLock::new(context::ConversationContext::new())),
+            sessions,
+            subscriptions: resources::Subscriptions::default(),
+            local_map,
         }
     }
 
     pub async fn execute(&self, request: ToolRequest) -> Result<ToolResponse> {
         match request.tool.as_str() {
             "generate_code" => {
-                code_generation::execute(request.arguments, self.current_provider.clone()).await
+                let (id, session) = self.session(&request).await?;
+                self.refresh(&id, &session, &[]).await?;
+                // Persisted for the summaries cached and how the context was sent
+                let response = code_generation::execute(
+                    request.arguments,
+                    self.client.clone(),
+                    session.context.clone(),
+                    &self.local_map,
+                )
+                .await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
             }
             "review_code" => {
-                code_review::execute(request.arguments, self.current_provider.clone()).await
+                let (id, session) = self.session(&request).await?;
+                self.refresh(&id, &session, &[]).await?;
+                let response = code_review::execute(
+                    request.arguments,
+                    self.client.clone(),
+                    session.context.clone(),
+                    &self.local_map,
+                )
+                .await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "chat" => {
+                let (id, session) = self.session(&request).await?;
+                self.refresh(&id, &session, &[]).await?;
+                let response = chat::execute(
+                    request.arguments,
+                    self.client.clone(),
+                    session.context.clone(),
+                    session.chat.clone(),
+                )
+                .await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "get_chat_history" => chat::get_history(self.session(&request).await?.1.chat).await,
+            "clear_chat_history" => {
+                let (id, session) = self.session(&request).await?;
+                let response = chat::clear_history(session.chat.clone()).await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
             }
             "switch_model" => {
                 model_switching::execute(
@@ -58,11 +282,52 @@ impl ToolExecutor {
             }
             "list_models" => self.list_all_models().await,
             "add_context" => {
-                context::add_context(request.arguments, self.context.clone()).await
+                let (id, session) = self.session(&request).await?;
+                let response =
+                    context::add_context(request.arguments, session.context.clone()).await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "get_context" => context::get_context(self.session(&request).await?.1.context).await,
+            "clear_context" => {
+                let (id, session) = self.session(&request).await?;
+                let response = context::clear_context(session.context.clone()).await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "remove_context" => {
+                let (id, session) = self.session(&request).await?;
+                let response =
+                    context::remove_context(request.arguments, session.context.clone()).await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "list_context" => {
+                let (_, session) = self.session(&request).await?;
+                let model = self.client.default_model().await;
+                context::list_context(request.arguments, session.context, model).await
             }
-            "get_context" => context::get_context(self.context.clone()).await,
-            "clear_context" => context::clear_context(self.context.clone()).await,
-            "local_map" => local_map::execute(request.arguments).await,
+            "set_context_priority" => {
+                let (id, session) = self.session(&request).await?;
+                let response =
+                    context::set_priority(request.arguments, session.context.clone()).await?;
+                self.sessions.persist(&id, &session).await;
+                Ok(response)
+            }
+            "refresh_context" => self.refresh_context(&request).await,
+            "create_session" => session::create_session(request.arguments, &self.sessions).await,
+            "list_sessions" => session::list_sessions(&self.sessions).await,
+            "switch_session" => session::switch_session(request.arguments, &self.sessions).await,
+            "rename_session" => session::rename_session(request.arguments, &self.sessions).await,
+            "fork_session" => session::fork_session(request.arguments, &self.sessions).await,
+            "delete_session" => session::delete_session(request.arguments, &self.sessions).await,
+            "export_session" => session::export_session(request.arguments, &self.sessions).await,
+            "import_session" => session::import_session(request.arguments, &self.sessions).await,
+            "local_map" => local_map::execute(request.arguments, &self.local_map).await,
+            "usage_report" => {
+                usage_report::execute(request.arguments, self.client.ledger.clone()).await
+            }
+            "budget_status" => self.budget_status(),
             _ => Ok(ToolResponse {
                 success: false,
                 result: serde_json::Value::Null,
@@ -71,6 +336,72 @@ impl ToolExecutor {
         }
     }
 
+    /// Session a call works in: its `session_id` argument, else the one in
+    /// the request's `_meta`, else the current session.
+    async fn session(&self, request: &ToolRequest) -> Result<(String, session::Session)> {
+        let id = request
+            .arguments
+            .get("session_id")
+            .and_then(|id| id.as_str())
+            .or(request.session.as_deref());
+        self.sessions.resolve(id).await
+    }
+
+    /// Re-read context files of a session that changed on disk, then save
+    /// the session and notify subscribers of the files that were updated.
+    async fn refresh(
+        &self,
+        id: &str,
+        session: &session::Session,
+        names: &[String],
+    ) -> Result<context::RefreshReport> {
+        let root = workspace::root()?;
+        let sources = session.context.read().await.sources(names)?;
+        // Reading and hashing files blocks, so it runs on its own thread and
+        // the context is only locked again to swap in the results
+        let rechecked =
+            tokio::task::spawn_blocking(move || context::recheck(&root, sources)).await?;
+        let report = session.context.write().await.apply_refresh(rechecked);
+        if !report.refreshed.is_empty() {
+            tracing::info!(
+                "Refreshed changed context files in session {}: {}",
+                id,
+                report.refreshed.join(", ")
+            );
+            self.sessions.persist(id, session).await;
+            for name in &report.refreshed {
+                self.subscriptions.updated(&resources::uri(id, name));
+            }
+        }
+        Ok(report)
+    }
+
+    async fn refresh_context(&self, request: &ToolRequest) -> Result<ToolResponse> {
+        #[derive(Deserialize)]
+        struct RefreshArgs {
+            #[serde(default)]
+            paths: Vec<String>,
+        }
+        let args: RefreshArgs = serde_json::from_value(request.arguments.clone())?;
+        let (id, session) = self.session(request).await?;
+        let report = self.refresh(&id, &session, &args.paths).await?;
+        Ok(ToolResponse {
+            success: true,
+            result: serde_json::to_value(report)?,
+            error: None,
+        })
+    }
+
+    /// Context files of a session as MCP resources.
+    pub async fn list_resources(&self, session: Option<&str>) -> Result<serde_json::Value> {
+        let (id, session) = self.sessions.resolve(session).await?;
+        Ok(resources::list(&id, &session).await)
+    }
+
+    pub async fn read_resource(&self, uri: &str) -> Result<serde_json::Value> {
+        resources::read(uri, &self.sessions).await
+    }
+
     async fn list_all_models(&self) -> Result<ToolResponse> {
         let mut all_models = Vec::new();
 
@@ -79,7 +410,11 @@ impl ToolExecutor {
             for model in models {
                 all_models.push(serde_json::json!({
                     "provider": provider.name(),
-                    "model": model,
+                    "model": model.id,
+                    "display_name": model.display_name,
+                    "created_at": model.created_at,
+                    "default": model.id == provider.default_model(),
+                    "metadata": self.client.registry.lookup(&model.id),
                 }));
             }
         }
@@ -90,4 +425,13 @@ impl ToolExecutor {
             error: None,
         })
     }
+
+    fn budget_status(&self) -> Result<ToolResponse> {
+        let providers: Vec<&str> = self.available_providers.iter().map(|p| p.name()).collect();
+        Ok(ToolResponse {
+            success: true,
+            result: self.client.budget.status(&providers),
+            error: None,
+        })
+    }
 }
@@ -0,0 +1,379 @@
+use super::compaction;
+use super::context::ConversationContext;
+use crate::providers::tokens::{estimate_request_tokens, Tokenizer};
+use crate::providers::{media, CompletionRequest, Message, ModelMetadata};
+use serde::Serialize;
+
+/// Headroom kept free of the context window to absorb tokenizer estimation
+/// error (the Claude count in particular is approximate).
+const SAFETY_MARGIN_RATIO: f64 = 0.05;
+
+/// Files are only truncated if at least this many tokens of them fit;
+/// otherwise they are dropped entirely.
+const MIN_TRUNCATED_FILE_TOKENS: u32 = 256;
+
+/// What part of the conversation context made it into a prompt.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContextReport {
+    pub tokens: u32,
+    pub included: Vec<String>,
+    /// Files sent as an outline of their declarations
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outlined: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub truncated: Vec<String>,
+    /// Notes sent as a cached summary of the older ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub summarized: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub omitted: Vec<String>,
+}
+
+/// Tokens left for additional prompt content once the request as built so
+/// far and its `max_tokens` output are accounted for.
+pub fn remaining_tokens(request: &CompletionRequest, meta: &ModelMetadata) -> u32 {
+    let max_tokens = request
+        .max_tokens
+        .unwrap_or(meta.default_max_tokens())
+        .min(meta.max_output_tokens);
+    let margin = (meta.context_window as f64 * SAFETY_MARGIN_RATIO) as u32;
+    meta.context_window
+        .saturating_sub(estimate_request_tokens(request))
+        .saturating_sub(max_tokens)
+        .saturating_sub(margin)
+}
+
+/// Append the conversation context to `request` as system messages placed
+/// right after its leading system prompt, using at most `budget` tokens.
+///
+/// Files go first in their own cacheable message, so the prompt prefix stays
+/// stable while notes and metadata change. Budget goes to pinned files
+/// first, then metadata and notes, then the other files by descending
+/// priority and path. Files that do not fit are sent as an outline of their
+/// declarations, truncated at a line boundary or omitted; notes that do not
+/// fit fall back to the cached summary of the older ones (see
+/// [`compaction`]). Image and PDF attachments come
+/// first in priority and follow as a user message, since system prompts
+/// only take text; they are omitted unless the model has `vision` support.
+pub fn attach_context(
+    request: &mut CompletionRequest,
+    ctx: &ConversationContext,
+    vision: bool,
+    budget: u32,
+) -> ContextReport {
+    let mut budget = budget;
+    let mut attached = Vec::new();
+    let mut skipped = Vec::new();
+    for name in ctx.ranked(ctx.attachments().keys()) {
+        let part = &ctx.attachments()[name];
+        let tokens = media::estimate_tokens(part);
+        if vision && tokens <= budget {
+            budget -= tokens;
+            attached.push((name.clone(), part.clone()));
+        } else {
+            skipped.push(name.clone());
+        }
+    }
+
+    let (files, notes, mut report) = render_context(ctx, &request.model, budget);
+    let at = request
+        .messages
+        .iter()
+        .take_while(|m| m.role == "system")
+        .count();
+    let mut blocks: Vec<Message> = [(files, true), (notes, false)]
+        .into_iter()
+        .filter(|(text, _)| !text.is_empty())
+        .map(|(text, cache)| Message {
+            cache,
+            ..Message::new("system", text)
+        })
+        .collect();
+
+    if !attached.is_empty() {
+        let names: Vec<&str> = attached.iter().map(|(name, _)| name.as_str()).collect();
+        let mut message = Message::new(
+            "user",
+            format!(
+                "Attached from the conversation context: {}",
+                names.join(", ")
+            ),
+        );
+        message.cache = true;
+        for (name, part) in attached {
+            report.tokens += media::estimate_tokens(&part);
+            report.included.push(name);
+            message.content.push(part);
+        }
+        blocks.push(message);
+    }
+    report.omitted.extend(skipped);
+
+    request.messages.splice(at..at, blocks);
+    report
+}
+
+/// Render context files and notes/metadata as two separate texts.
+fn render_context(
+    ctx: &ConversationContext,
+    model: &str,
+    budget: u32,
+) -> (String, String, ContextReport) {
+    let mut report = ContextReport::default();
+    let mut files = String::new();
+    let mut notes = String::new();
+    if ctx.is_empty() {
+        return (files, notes, report);
+    }
+    let tokenizer = Tokenizer::for_model(model);
+    let mut used = 0u32;
+
+    let push = |out: &mut String, used: &mut u32, text: &str| -> bool {
+        let tokens = tokenizer.count(text);
+        if *used + tokens > budget {
+            return false;
+        }
+        out.push_str(text);
+        *used += tokens;
+        true
+    };
+
+    let add_file =
+        |files: &mut String, used: &mut u32, report: &mut ContextReport, path: &String| {
+            let content = &ctx.files()[path];
+            let header = format!("## File: {}\n```\n", path);
+            let footer = "\n```\n\n";
+            let full = format!("{}{}{}", header, content, footer);
+            if push(files, used, &full) {
+                report.included.push(path.clone());
+                return;
+            }
+
+            if let Some(outline) = compaction::outline(path, content) {
+                let text = format!("## File: {} (outline)\n```\n{}{}", path, outline, footer);
+                if push(files, used, &text) {
+                    report.outlined.push(path.clone());
+                    return;
+                }
+            }
+
+            let frame = tokenizer.count(&header) + tokenizer.count(footer) + 16;
+            let room = budget.saturating_sub(*used).saturating_sub(frame);
+            if room >= MIN_TRUNCATED_FILE_TOKENS {
+                let kept = tokenizer.truncate(content, room);
+                let partial = format!("{}{}\n... [truncated]{}", header, kept, footer);
+                if push(files, used, &partial) {
+                    report.truncated.push(path.clone());
+                    return;
+                }
+            }
+            report.omitted.push(path.clone());
+        };
+
+    let (pinned, others): (Vec<&String>, Vec<&String>) = ctx
+        .ranked(ctx.files().keys())
+        .into_iter()
+        .partition(|path| ctx.priority(path).pinned);
+    for path in pinned {
+        add_file(&mut files, &mut used, &mut report, path);
+    }
+
+    if !ctx.metadata().is_empty() {
+        let mut keys: Vec<_> = ctx.metadata().iter().collect();
+        keys.sort();
+        let mut section = String::from("## Project metadata\n");
+        for (key, value) in keys {
+            section.push_str(&format!("- {}: {}\n", key, value));
+        }
+        if push(&mut notes, &mut used, &section) {
+            report.included.push("metadata".to_string());
+        } else {
+            report.omitted.push("metadata".to_string());
+        }
+    }
+
+    if !ctx.notes().is_empty() {
+        let mut section = String::from("\n## Notes\n");
+        for note in ctx.notes() {
+            section.push_str(&format!("- {}\n", note));
+        }
+        if push(&mut notes, &mut used, &section) {
+            report.included.push("notes".to_string());
+        } else if summarized_notes(ctx).is_some_and(|section| push(&mut notes, &mut used, &section))
+        {
+            report.summarized.push("notes".to_string());
+        } else {
+            report.omitted.push("notes".to_string());
+        }
+    }
+
+    for path in others {
+        add_file(&mut files, &mut used, &mut report, path);
+    }
+
+    if !files.is_empty() {
+        files.insert_str(0, "# Context files\n\n");
+    }
+    if !notes.is_empty() {
+        notes.insert_str(0, "# Conversation context\n\n");
+    }
+    report.tokens = used;
+    (files, notes, report)
+}
+
+/// The notes section with the older notes replaced by their cached summary,
+/// if one was made.
+fn summarized_notes(ctx: &ConversationContext) -> Option<String> {
+    let older = compaction::older_notes(ctx)?;
+    let summary = ctx.summary(&compaction::summary_key("notes", &older))?;
+    let recent = &ctx.notes()[ctx.notes().len() - compaction::RECENT_NOTES..];
+    let mut section = format!(
+        "\n## Notes\nSummary of {} earlier notes:\n{}\n\nRecent notes:\n",
+        ctx.notes().len() - recent.len(),
+        summary
+    );
+    for note in recent {
+        section.push_str(&format!("- {}\n", note));
+    }
+    Some(section)
+}
+
+/// Split `text` into line-aligned chunks of at most `max_tokens` each.
+/// Returns `(first_line, chunk)` pairs with 1-based line numbers.
+pub fn chunk_lines(text: &str, model: &str, max_tokens: u32) -> Vec<(usize, String)> {
+    let tokenizer = Tokenizer::for_model(model);
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_tokens = 0u32;
+    let mut start_line = 1usize;
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let mut line = line;
+        let mut tokens = tokenizer.count(line);
+        if current_tokens + tokens > max_tokens && !current.is_empty() {
+            chunks.push((start_line, std::mem::take(&mut current)));
+            current_tokens = 0;
+            start_line = index + 1;
+        }
+        // A single line longer than a whole chunk is cut rather than sent whole
+        while tokens > max_tokens {
+            let head = tokenizer.truncate(line, max_tokens);
+            let head = if head.is_empty() {
+                &line[..line.chars().next().map_or(line.len(), char::len_utf8)]
+            } else {
+                head
+            };
+            chunks.push((index + 1, head.to_string()));
+            line = &line[head.len()..];
+            tokens = tokenizer.count(line);
+            start_line = index + 1;
+        }
+        current.push_str(line);
+        current_tokens += tokens;
+    }
+    if !current.is_empty() {
+        chunks.push((start_line, current));
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::ContentPart;
+    use crate::tools::context::Priority;
+
+    #[test]
+    fn test_attach_context_trims_to_budget() {
+        let mut ctx = ConversationContext::new();
+        ctx.add_note("Prefer async APIs".to_string());
+        ctx.add_file("a.rs".to_string(), "fn a() {}\n".to_string());
+        ctx.add_file("b.rs".to_string(), "let x = 1;\n".repeat(2_000));
+        ctx.add_file("c.rs".to_string(), "let y = 2;\n".repeat(2_000));
+
+        let message = |role: &str, content: &str| Message {
+            cache: role == "system",
+            ..Message::new(role, content)
+        };
+        let mut request = CompletionRequest {
+            messages: vec![message("system", "base"), message("user", "question")],
+            model: "gpt-4o".to_string(),
+            max_tokens: None,
+            temperature: None,
+            tools: Vec::new(),
+            response_format: None,
+            reasoning: None,
+            sampling: Default::default(),
+        };
+
+        let report = attach_context(&mut request, &ctx, true, 3_000);
+        assert!(report.tokens <= 3_000);
+        assert_eq!(report.included, vec!["notes", "a.rs"]);
+        assert_eq!(report.truncated, vec!["b.rs"]);
+        assert_eq!(report.omitted, vec!["c.rs"]);
+
+        let roles: Vec<_> = request.messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "system", "system", "user"]);
+        let files = &request.messages[1];
+        assert!(files.cache && files.text().contains("... [truncated]"));
+        let notes = &request.messages[2];
+        assert!(!notes.cache && notes.text().contains("Prefer async APIs"));
+
+        let report = attach_context(&mut request, &ConversationContext::new(), true, 3_000);
+        assert_eq!(report.tokens, 0);
+        assert_eq!(request.messages.len(), 4);
+
+        // A pinned file takes the budget ahead of notes and other files
+        ctx.set_priority(
+            "c.rs".to_string(),
+            Priority {
+                pinned: true,
+                priority: 0,
+            },
+        );
+        let mut pinned_request = CompletionRequest {
+            messages: vec![message("system", "base"), message("user", "question")],
+            ..request.clone()
+        };
+        let report = attach_context(&mut pinned_request, &ctx, true, 3_000);
+        assert_eq!(report.truncated, vec!["c.rs"]);
+        assert_eq!(report.included[0], "notes");
+        assert!(report.omitted.contains(&"b.rs".to_string()));
+
+        let mut ctx = ConversationContext::new();
+        let image = ContentPart::Image {
+            media_type: "image/png".to_string(),
+            data: String::new(),
+        };
+        ctx.add_attachment("ui.png".to_string(), image.clone());
+        let mut request = CompletionRequest {
+            messages: vec![message("system", "base"), message("user", "question")],
+            ..request
+        };
+        let report = attach_context(&mut request, &ctx, false, 3_000);
+        assert_eq!(report.omitted, vec!["ui.png"]);
+        let report = attach_context(&mut request, &ctx, true, 3_000);
+        assert_eq!(report.included, vec!["ui.png"]);
+        assert_eq!(request.messages[1].role, "user");
+        assert_eq!(request.messages[1].content[1], image);
+    }
+
+    #[test]
+    fn test_chunk_lines() {
+        let text = "let value = compute(1, 2, 3);\n".repeat(100);
+        let chunks = chunk_lines(&text, "gpt-4o", 200);
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks[0].0, 1);
+        assert_eq!(
+            chunks.iter().map(|(_, c)| c.as_str()).collect::<String>(),
+            text
+        );
+        for (_, chunk) in &chunks {
+            assert!(count(chunk) <= 200);
+        }
+    }
+
+    fn count(text: &str) -> u32 {
+        Tokenizer::for_model("gpt-4o").count(text)
+    }
+}
@@ -0,0 +1,127 @@
+use super::session::{Session, SessionStore};
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+const SCHEME: &str = "context://";
+
+/// URI under which the context file `name` of `session` is exposed as an
+/// MCP resource.
+pub fn uri(session: &str, name: &str) -> String {
+    format!("{}{}/{}", SCHEME, session, name)
+}
+
+fn parse_uri(uri: &str) -> Result<(&str, &str)> {
+    match uri
+        .strip_prefix(SCHEME)
+        .and_then(|rest| rest.split_once('/'))
+    {
+        Some((session, name)) if !session.is_empty() && !name.is_empty() => Ok((session, name)),
+        _ => bail!("Not a context resource: {}", uri),
+    }
+}
+
+/// Resources the client subscribed to, and update notifications waiting to
+/// be sent after the current response.
+#[derive(Default)]
+pub struct Subscriptions {
+    uris: Mutex<HashSet<String>>,
+    pending: Mutex<Vec<serde_json::Value>>,
+}
+
+impl Subscriptions {
+    pub fn subscribe(&self, uri: &str) -> Result<()> {
+        parse_uri(uri)?;
+        self.lock_uris().insert(uri.to_string());
+        Ok(())
+    }
+
+    pub fn unsubscribe(&self, uri: &str) {
+        self.lock_uris().remove(uri);
+    }
+
+    /// Queue a `notifications/resources/updated` for `uri` if the client
+    /// subscribed to it.
+    pub fn updated(&self, uri: &str) {
+        if self.lock_uris().contains(uri) {
+            self.pending
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": "notifications/resources/updated",
+                    "params": { "uri": uri },
+                }));
+        }
+    }
+
+    pub fn take(&self) -> Vec<serde_json::Value> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn lock_uris(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.uris.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The context files of a session, as a `resources/list` result.
+pub async fn list(id: &str, session: &Session) -> serde_json::Value {
+    let context = session.context.read().await;
+    let mut names: Vec<&String> = context.files().keys().collect();
+    names.sort();
+    let resources: Vec<_> = names
+        .into_iter()
+        .map(|name| {
+            serde_json::json!({
+                "uri": uri(id, name),
+                "name": name,
+                "mimeType": "text/plain",
+            })
+        })
+        .collect();
+    serde_json::json!({ "resources": resources })
+}
+
+/// A context file, as a `resources/read` result.
+pub async fn read(uri: &str, sessions: &SessionStore) -> Result<serde_json::Value> {
+    let (id, name) = parse_uri(uri)?;
+    let Some(session) = sessions.get(id).await? else {
+        bail!("Unknown session: {}", id);
+    };
+    let context = session.context.read().await;
+    let Some(content) = context.files().get(name) else {
+        bail!("No context file {} in session {}", name, id);
+    };
+    Ok(serde_json::json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": "text/plain",
+            "text": content,
+        }]
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_only_subscribed_resources() {
+        let subscriptions = Subscriptions::default();
+        let watched = uri("default", "src/main.rs");
+        assert_eq!(parse_uri(&watched).unwrap(), ("default", "src/main.rs"));
+        assert!(subscriptions.subscribe("file:///src/main.rs").is_err());
+
+        subscriptions.subscribe(&watched).unwrap();
+        subscriptions.updated(&watched);
+        subscriptions.updated(&uri("default", "src/lib.rs"));
+        let sent = subscriptions.take();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["params"]["uri"], "context://default/src/main.rs");
+        assert!(subscriptions.take().is_empty());
+
+        subscriptions.unsubscribe(&watched);
+        subscriptions.updated(&watched);
+        assert!(subscriptions.take().is_empty());
+    }
+}
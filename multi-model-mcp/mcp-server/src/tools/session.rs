@@ -0,0 +1,850 @@
+use super::chat::ChatHistory;
+use super::context::ConversationContext;
+use super::workspace;
+use super::ToolResponse;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Session used when a call names none and no other session was switched
+/// to. It always exists, so clients unaware of sessions keep working.
+pub const DEFAULT_SESSION: &str = "default";
+
+const MAX_ID_LEN: usize = 64;
+/// Version of `SessionFile` written by this server
+const FORMAT_VERSION: u32 = 1;
+/// File in the store directory naming the current session
+const CURRENT_FILE: &str = "current";
+
+/// Conversation context and chat history of one session.
+#[derive(Clone)]
+pub struct Session {
+    pub context: Arc<RwLock<ConversationContext>>,
+    pub chat: Arc<RwLock<ChatHistory>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            context: Arc::new(RwLock::new(ConversationContext::new())),
+            chat: Arc::new(RwLock::new(ChatHistory::new())),
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Independent copy of the session's context and history.
+    async fn fork(&self) -> Self {
+        Self {
+            context: Arc::new(RwLock::new(self.context.read().await.clone())),
+            chat: Arc::new(RwLock::new(self.chat.read().await.clone())),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// On-disk and export format of a session.
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "id": "review",
+///   "created_at": "2026-10-18T09:30:00Z",
+///   "context": { "files": {}, "notes": [], "metadata": {}, "attachments": {} },
+///   "chat": { "turns": [] }
+/// }
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub version: u32,
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub context: ConversationContext,
+    pub chat: ChatHistory,
+}
+
+impl SessionFile {
+    async fn new(id: &str, session: &Session) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            id: id.to_string(),
+            created_at: session.created_at,
+            context: session.context.read().await.clone(),
+            chat: session.chat.read().await.clone(),
+        }
+    }
+
+    fn into_session(self) -> Session {
+        Session {
+            context: Arc::new(RwLock::new(self.context)),
+            chat: Arc::new(RwLock::new(self.chat)),
+            created_at: self.created_at,
+        }
+    }
+}
+
+struct Sessions {
+    /// `None` for sessions stored on disk that have not been loaded yet
+    by_id: BTreeMap<String, Option<Session>>,
+    current: String,
+}
+
+/// Named sessions, so that separate chat panels or projects do not share
+/// files, notes and history. Sessions are stored as one JSON file each in a
+/// directory per workspace, read on first use and written after every
+/// change.
+pub struct SessionStore {
+    inner: RwLock<Sessions>,
+    dir: Option<PathBuf>,
+}
+
+impl SessionStore {
+    /// Open the sessions of the current workspace under `root` (default:
+    /// `<data dir>/multi-model-mcp/sessions`).
+    pub fn open(root: Option<&Path>) -> Self {
+        let root = root.map(Path::to_path_buf).or_else(Self::default_root);
+        let dir = match (root, workspace_key()) {
+            (Some(root), Some(key)) => Some(root.join(key)),
+            _ => {
+                tracing::warn!("No data directory available; sessions will not be saved");
+                None
+            }
+        };
+        Self::with_dir(dir)
+    }
+
+    pub fn default_root() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("multi-model-mcp").join("sessions"))
+    }
+
+    fn with_dir(dir: Option<PathBuf>) -> Self {
+        let mut by_id = BTreeMap::new();
+        let mut current = DEFAULT_SESSION.to_string();
+        if let Some(dir) = &dir {
+            if let Ok(entries) = fs::read_dir(dir) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                        continue;
+                    };
+                    if path.extension().is_some_and(|ext| ext == "json") && validate_id(id).is_ok()
+                    {
+                        by_id.insert(id.to_string(), None);
+                    }
+                }
+            }
+            if let Ok(saved) = fs::read_to_string(dir.join(CURRENT_FILE)) {
+                let saved = saved.trim();
+                if by_id.contains_key(saved) {
+                    current = saved.to_string();
+                }
+            }
+        }
+        by_id
+            .entry(DEFAULT_SESSION.to_string())
+            .or_insert_with(|| Some(Session::new()));
+        Self {
+            inner: RwLock::new(Sessions { by_id, current }),
+            dir,
+        }
+    }
+
+    /// Session `id`, created on first use, or the current session when no
+    /// id is given. Returns the id along with the session.
+    pub async fn resolve(&self, id: Option<&str>) -> Result<(String, Session)> {
+        let (id, loaded) = match id {
+            Some(id) => (id.to_string(), self.get(id).await?),
+            None => self.current_session().await?,
+        };
+        if let Some(session) = loaded {
+            return Ok((id, session));
+        }
+        validate_id(&id)?;
+        let mut inner = self.inner.write().await;
+        let session = inner
+            .by_id
+            .entry(id.clone())
+            .or_insert_with(|| Some(Session::new()))
+            .clone()
+            .expect("sessions created here are loaded");
+        Ok((id, session))
+    }
+
+    /// The current session, falling back as [`Self::recover`] describes
+    /// when it cannot be read, so one bad file does not break every tool.
+    async fn current_session(&self) -> Result<(String, Option<Session>)> {
+        let mut id = self.current().await;
+        loop {
+            match self.get(&id).await {
+                Ok(session) => return Ok((id, session)),
+                Err(e) => id = self.recover(&id, e).await?,
+            }
+        }
+    }
+
+    /// Handle the current session `id` failing to load with `error`: the
+    /// default session becomes current, and an unreadable default session
+    /// is moved aside to `default.json.unreadable` and started afresh.
+    /// Returns the id to use instead.
+    async fn recover(&self, id: &str, error: anyhow::Error) -> Result<String> {
+        tracing::warn!("{:#}", error);
+        let mut inner = self.inner.write().await;
+        if id != DEFAULT_SESSION {
+            if inner.current == id {
+                inner.current = DEFAULT_SESSION.to_string();
+                self.save_current(DEFAULT_SESSION).await;
+            }
+            return Ok(DEFAULT_SESSION.to_string());
+        }
+        if let Some(path) = self.path(id) {
+            let aside = path.with_extension("json.unreadable");
+            match tokio::fs::rename(&path, &aside).await {
+                Ok(()) => tracing::warn!(
+                    "Moved the unreadable default session to {}",
+                    aside.display()
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to move {} aside", path.display()))
+                }
+            }
+        }
+        inner.by_id.insert(id.to_string(), Some(Session::new()));
+        Ok(id.to_string())
+    }
+
+    /// Session `id`, loading it from disk on first use. The file is read
+    /// without holding the store.
+    pub async fn get(&self, id: &str) -> Result<Option<Session>> {
+        match self.inner.read().await.by_id.get(id) {
+            Some(Some(session)) => return Ok(Some(session.clone())),
+            Some(None) => {}
+            None => return Ok(None),
+        }
+        let Some(path) = self.path(id) else {
+            bail!("Unknown session: {}", id);
+        };
+        let loaded = tokio::task::spawn_blocking(move || load(&path)).await??;
+        let mut inner = self.inner.write().await;
+        // Another call may have loaded, replaced or deleted it meanwhile
+        let Some(slot) = inner.by_id.get_mut(id) else {
+            return Ok(None);
+        };
+        Ok(Some(slot.get_or_insert(loaded).clone()))
+    }
+
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.json", id)))
+    }
+
+    /// Write `session` to disk, unless it has since been replaced, renamed
+    /// or deleted. Failures are logged rather than failing the tool call
+    /// that changed the session.
+    pub async fn persist(&self, id: &str, session: &Session) {
+        let is_stored = matches!(
+            self.inner.read().await.by_id.get(id),
+            Some(Some(stored)) if Arc::ptr_eq(&stored.context, &session.context)
+        );
+        if is_stored {
+            self.save(id, session).await;
+        }
+    }
+
+    async fn save(&self, id: &str, session: &Session) {
+        let Some(path) = self.path(id) else {
+            return;
+        };
+        let file = SessionFile::new(id, session).await;
+        let result = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Written aside and renamed, so a crash never leaves half a file
+            let contents = serde_json::to_vec(&file)?;
+            let target = path.clone();
+            tokio::task::spawn_blocking(move || write_new(&target, &contents, true)).await??;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to save session {}: {}", path.display(), e);
+        }
+    }
+
+    async fn save_current(&self, current: &str) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let result = match tokio::fs::create_dir_all(dir).await {
+            Ok(()) => tokio::fs::write(dir.join(CURRENT_FILE), current).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to save the current session: {}", e);
+        }
+    }
+
+    async fn remove_file(&self, id: &str) {
+        let Some(path) = self.path(id) else {
+            return;
+        };
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove session {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    pub async fn current(&self) -> String {
+        self.inner.read().await.current.clone()
+    }
+
+    pub async fn create(&self, id: &str) -> Result<()> {
+        validate_id(id)?;
+        let session = Session::new();
+        {
+            let mut inner = self.inner.write().await;
+            if inner.by_id.contains_key(id) {
+                bail!("Session '{}' already exists", id);
+            }
+            inner.by_id.insert(id.to_string(), Some(session.clone()));
+        }
+        self.save(id, &session).await;
+        Ok(())
+    }
+
+    pub async fn switch(&self, id: &str) -> Result<()> {
+        let mut inner = self.inner.write().await;
+        if !inner.by_id.contains_key(id) {
+            bail!("Unknown session: {}", id);
+        }
+        inner.current = id.to_string();
+        self.save_current(id).await;
+        Ok(())
+    }
+
+    pub async fn rename(&self, id: &str, new_id: &str) -> Result<()> {
+        if id == DEFAULT_SESSION {
+            bail!("The default session cannot be renamed; fork it instead");
+        }
+        validate_id(new_id)?;
+        let Some(session) = self.get(id).await? else {
+            bail!("Unknown session: {}", id);
+        };
+        {
+            let mut inner = self.inner.write().await;
+            if inner.by_id.contains_key(new_id) {
+                bail!("Session '{}' already exists", new_id);
+            }
+            if inner.by_id.remove(id).is_none() {
+                bail!("Unknown session: {}", id);
+            }
+            inner
+                .by_id
+                .insert(new_id.to_string(), Some(session.clone()));
+            if inner.current == id {
+                inner.current = new_id.to_string();
+                self.save_current(new_id).await;
+            }
+        }
+        self.save(new_id, &session).await;
+        self.remove_file(id).await;
+        Ok(())
+    }
+
+    pub async fn fork(&self, id: &str, new_id: &str) -> Result<()> {
+        validate_id(new_id)?;
+        let Some(source) = self.get(id).await? else {
+            bail!("Unknown session: {}", id);
+        };
+        // Copied without holding the store, which a running chat in the
+        // source session would otherwise block
+        let forked = source.fork().await;
+        {
+            let mut inner = self.inner.write().await;
+            if inner.by_id.contains_key(new_id) {
+                bail!("Session '{}' already exists", new_id);
+            }
+            inner.by_id.insert(new_id.to_string(), Some(forked.clone()));
+        }
+        self.save(new_id, &forked).await;
+        Ok(())
+    }
+
+    /// Delete a session; if it was the current one, the default session
+    /// becomes current.
+    pub async fn delete(&self, id: &str) -> Result<()> {
+        if id == DEFAULT_SESSION {
+            bail!("The default session cannot be deleted; clear its context and history instead");
+        }
+        let mut inner = self.inner.write().await;
+        if inner.by_id.remove(id).is_none() {
+            bail!("Unknown session: {}", id);
+        }
+        self.remove_file(id).await;
+        if inner.current == id {
+            inner.current = DEFAULT_SESSION.to_string();
+            self.save_current(DEFAULT_SESSION).await;
+        }
+        Ok(())
+    }
+
+    /// Store an exported session as `id`, replacing an existing session of
+    /// that id only when `overwrite` is set.
+    pub async fn import(&self, id: &str, file: SessionFile, overwrite: bool) -> Result<()> {
+        validate_id(id)?;
+        if file.version > FORMAT_VERSION {
+            bail!(
+                "Session format version {} is newer than supported ({})",
+                file.version,
+                FORMAT_VERSION
+            );
+        }
+        let mut file = file;
+        let foreign = file.context.drop_foreign_sources();
+        if !foreign.is_empty() {
+            tracing::warn!(
+                "Session {} names files outside the workspace; {} will not be refreshed from disk",
+                id,
+                foreign.join(", ")
+            );
+        }
+        let session = file.into_session();
+        {
+            let mut inner = self.inner.write().await;
+            if inner.by_id.contains_key(id) && !overwrite {
+                bail!(
+                    "Session '{}' already exists; set overwrite to replace it",
+                    id
+                );
+            }
+            inner.by_id.insert(id.to_string(), Some(session.clone()));
+        }
+        self.save(id, &session).await;
+        Ok(())
+    }
+
+    async fn ids(&self) -> (String, Vec<String>) {
+        let inner = self.inner.read().await;
+        (inner.current.clone(), inner.by_id.keys().cloned().collect())
+    }
+}
+
+/// Read the session stored at `path`.
+fn load(path: &Path) -> Result<Session> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read session {}", path.display()))?;
+    let file: SessionFile = serde_json::from_str(&text)
+        .with_context(|| format!("Failed to parse session {}", path.display()))?;
+    Ok(file.into_session())
+}
+
+/// Directory name of the current workspace: its base name plus a hash of
+/// its full path, so equally named projects get separate stores.
+fn workspace_key() -> Option<String> {
+    let root = std::env::current_dir().ok()?;
+    let root = fs::canonicalize(&root).unwrap_or(root);
+    let hash = Sha256::digest(root.to_string_lossy().as_bytes());
+    let name: String = root
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    Some(format!("{}-{:.16x}", name, hash))
+}
+
+/// Session ids double as names shown to the user, so keep them short and
+/// free of path or control characters.
+fn validate_id(id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > MAX_ID_LEN {
+        bail!("Session ids must be 1 to {} characters long", MAX_ID_LEN);
+    }
+    if id.starts_with('.')
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        bail!(
+            "Invalid session id '{}': use letters, digits, '-', '_' and '.'",
+            id
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionArgs {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameArgs {
+    id: String,
+    new_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportArgs {
+    /// Session to export; the current session when omitted
+    id: Option<String>,
+    /// Workspace file to write; the session is returned when omitted
+    path: Option<String>,
+    /// Replace `path` if it exists
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportArgs {
+    /// Workspace file written by `export_session`
+    path: Option<String>,
+    /// The exported session itself, instead of `path`
+    session: Option<SessionFile>,
+    /// Id to import as; the exported id when omitted
+    id: Option<String>,
+    #[serde(default)]
+    overwrite: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForkArgs {
+    /// Session to copy; the current session when omitted
+    id: Option<String>,
+    new_id: String,
+    #[serde(default)]
+    switch: bool,
+}
+
+pub async fn create_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: SessionArgs = serde_json::from_value(args)?;
+    store.create(&args.id).await?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Created session: {}", args.id),
+        }),
+        error: None,
+    })
+}
+
+pub async fn list_sessions(store: &SessionStore) -> Result<ToolResponse> {
+    let (current, ids) = store.ids().await;
+    let mut listed = Vec::new();
+    let mut unreadable = Vec::new();
+    for id in ids {
+        let session = match store.get(&id).await {
+            Ok(Some(session)) => session,
+            Ok(None) => continue,
+            // Reported rather than failing the whole listing
+            Err(e) => {
+                unreadable.push(serde_json::json!({ "id": id, "error": format!("{:#}", e) }));
+                continue;
+            }
+        };
+        let context = session.context.read().await;
+        listed.push(serde_json::json!({
+            "id": id,
+            "current": id == current,
+            "created_at": session.created_at,
+            "files": context.files().len(),
+            "notes": context.notes().len(),
+            "attachments": context.attachments().len(),
+            "turns": session.chat.read().await.turns().len(),
+        }));
+    }
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "current": current,
+            "sessions": listed,
+            "unreadable": unreadable,
+        }),
+        error: None,
+    })
+}
+
+pub async fn switch_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: SessionArgs = serde_json::from_value(args)?;
+    store.switch(&args.id).await?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Switched to session: {}", args.id),
+        }),
+        error: None,
+    })
+}
+
+pub async fn rename_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: RenameArgs = serde_json::from_value(args)?;
+    store.rename(&args.id, &args.new_id).await?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Renamed session {} to {}", args.id, args.new_id),
+        }),
+        error: None,
+    })
+}
+
+pub async fn fork_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: ForkArgs = serde_json::from_value(args)?;
+    let source = match args.id {
+        Some(id) => id,
+        None => store.current().await,
+    };
+    store.fork(&source, &args.new_id).await?;
+    if args.switch {
+        store.switch(&args.new_id).await?;
+    }
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Forked session {} into {}", source, args.new_id),
+            "current": store.current().await,
+        }),
+        error: None,
+    })
+}
+
+pub async fn delete_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: SessionArgs = serde_json::from_value(args)?;
+    store.delete(&args.id).await?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Deleted session: {}", args.id),
+            "current": store.current().await,
+        }),
+        error: None,
+    })
+}
+
+pub async fn export_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: ExportArgs = serde_json::from_value(args)?;
+    let id = match args.id {
+        Some(id) => id,
+        None => store.current().await,
+    };
+    let Some(session) = store.get(&id).await? else {
+        bail!("Unknown session: {}", id);
+    };
+    let file = SessionFile::new(&id, &session).await;
+
+    let Some(path) = args.path else {
+        return Ok(ToolResponse {
+            success: true,
+            result: serde_json::json!({ "session": file }),
+            error: None,
+        });
+    };
+    let target = workspace::resolve_new_within(&workspace::root()?, &path)?;
+    if target.exists() && !args.overwrite {
+        bail!(
+            "{} already exists; pass \"overwrite\": true to replace it",
+            path
+        );
+    }
+    write_new(
+        &target,
+        serde_json::to_string_pretty(&file)?.as_bytes(),
+        args.overwrite,
+    )
+    .with_context(|| format!("Failed to write {}", path))?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Exported session {} to {}", id, path),
+        }),
+        error: None,
+    })
+}
+
+pub async fn import_session(args: serde_json::Value, store: &SessionStore) -> Result<ToolResponse> {
+    let args: ImportArgs = serde_json::from_value(args)?;
+    let file = match (args.path, args.session) {
+        (Some(path), None) => {
+            let resolved = workspace::resolve_within(&workspace::root()?, &path)?;
+            let text = fs::read_to_string(&resolved)
+                .with_context(|| format!("Failed to read {}", path))?;
+            serde_json::from_str(&text)
+                .with_context(|| format!("{} is not an exported session", path))?
+        }
+        (None, Some(session)) => session,
+        _ => bail!("Give either 'path' or 'session'"),
+    };
+    let id = args.id.unwrap_or_else(|| file.id.clone());
+    store.import(&id, file, args.overwrite).await?;
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "message": format!("Imported session: {}", id),
+        }),
+        error: None,
+    })
+}
+
+/// Write `contents` to a fresh, uniquely named file next to `target` and
+/// rename it over `target` (or, without `overwrite`, only where there is no
+/// file yet), so readers never see half a file. Concurrent writers and
+/// files left behind by a crash never share the temporary name.
+fn write_new(target: &Path, contents: &[u8], overwrite: bool) -> std::io::Result<()> {
+    let dir = target.parent().unwrap_or(Path::new("."));
+    let mut prefix = OsString::from(".");
+    prefix.push(target.file_name().unwrap_or_default());
+    prefix.push(".");
+    let mut file = tempfile::Builder::new()
+        .prefix(&prefix)
+        .suffix(".tmp")
+        .tempfile_in(dir)?;
+    file.write_all(contents)?;
+    // On failure the temporary file is dropped, which removes it
+    let persisted = if overwrite {
+        file.persist(target)
+    } else {
+        file.persist_noclobber(target)
+    };
+    persisted.map(drop).map_err(|e| e.error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_lifecycle() {
+        let store = SessionStore::with_dir(None);
+        let (_, panel) = store.resolve(Some("panel-1")).await.unwrap();
+        panel
+            .context
+            .write()
+            .await
+            .add_note("only in panel-1".to_string());
+        assert!(store
+            .resolve(None)
+            .await
+            .unwrap()
+            .1
+            .context
+            .read()
+            .await
+            .is_empty());
+        assert!(store.resolve(Some("../etc")).await.is_err());
+
+        store.fork("panel-1", "panel-2").await.unwrap();
+        panel.context.write().await.clear();
+        let (_, forked) = store.resolve(Some("panel-2")).await.unwrap();
+        assert_eq!(forked.context.read().await.notes().len(), 1);
+
+        store.switch("panel-2").await.unwrap();
+        store.rename("panel-2", "review").await.unwrap();
+        assert_eq!(store.current().await, "review");
+        store.delete("review").await.unwrap();
+        assert_eq!(store.current().await, DEFAULT_SESSION);
+
+        assert!(store.delete(DEFAULT_SESSION).await.is_err());
+        assert!(store.create("panel-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_persist_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::with_dir(Some(dir.path().to_path_buf()));
+        let (id, session) = store.resolve(Some("panel-1")).await.unwrap();
+        session
+            .context
+            .write()
+            .await
+            .add_file("src/lib.rs".to_string(), "pub fn lib() {}".to_string());
+        store.persist(&id, &session).await;
+        store.switch("panel-1").await.unwrap();
+        store.fork("panel-1", "draft").await.unwrap();
+        store.rename("draft", "review").await.unwrap();
+
+        let reopened = SessionStore::with_dir(Some(dir.path().to_path_buf()));
+        assert_eq!(reopened.current().await, "panel-1");
+        assert!(matches!(
+            reopened.inner.read().await.by_id.get("review"),
+            Some(None)
+        ));
+        let (_, review) = reopened.resolve(Some("review")).await.unwrap();
+        assert_eq!(
+            review.context.read().await.files()["src/lib.rs"],
+            "pub fn lib() {}"
+        );
+        assert!(!dir.path().join("draft.json").exists());
+
+        let exported = SessionFile::new("review", &review).await;
+        let json = serde_json::to_value(&exported).unwrap();
+        assert_eq!(json["version"], 1);
+        let imported: SessionFile = serde_json::from_value(json).unwrap();
+        assert!(reopened.import("review", imported, false).await.is_err());
+    }
+
+    #[test]
+    fn test_write_new_replaces_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("review.json");
+        fs::write(&target, "old").unwrap();
+        // A temporary file left behind by a crash is not in the way
+        fs::write(dir.path().join("review.json.tmp"), "partial").unwrap();
+        write_new(&target, b"new", true).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+
+        let error = write_new(&target, b"newer", false).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+        let mut names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["review.json", "review.json.tmp"]);
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("broken.json"), "{ not json").unwrap();
+        fs::write(dir.path().join(CURRENT_FILE), "broken").unwrap();
+        let store = SessionStore::with_dir(Some(dir.path().to_path_buf()));
+        assert_eq!(store.current().await, "broken");
+
+        // The listing reports the bad file instead of failing
+        let listed = list_sessions(&store).await.unwrap().result;
+        assert_eq!(listed["sessions"][0]["id"], DEFAULT_SESSION);
+        assert_eq!(listed["unreadable"][0]["id"], "broken");
+
+        // Tools fall back to the default session
+        let (id, _) = store.resolve(None).await.unwrap();
+        assert_eq!(id, DEFAULT_SESSION);
+        assert_eq!(store.current().await, DEFAULT_SESSION);
+        assert!(store.resolve(Some("broken")).await.is_err());
+
+        // An unreadable default session is moved aside
+        fs::write(dir.path().join("default.json"), "").unwrap();
+        let store = SessionStore::with_dir(Some(dir.path().to_path_buf()));
+        let (id, _) = store.resolve(None).await.unwrap();
+        assert_eq!(id, DEFAULT_SESSION);
+        assert!(dir.path().join("default.json.unreadable").exists());
+    }
+}
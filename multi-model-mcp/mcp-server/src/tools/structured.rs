@@ -0,0 +1,167 @@
+use super::ModelClient;
+use crate::providers::{CompletionRequest, CompletionResponse, Message, ResponseFormat, UsageInfo};
+use anyhow::{bail, Result};
+use jsonschema::Validator;
+use serde_json::Value;
+
+/// Repair prompts sent after the first reply fails validation.
+pub const MAX_REPAIR_ATTEMPTS: usize = 2;
+
+/// Schema-validation errors listed in a repair prompt.
+const MAX_REPORTED_ERRORS: usize = 10;
+
+/// Schema `review_code` uses for `"format": "json"`.
+pub fn review_schema() -> Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "summary": { "type": "string", "description": "Brief overview of code quality" },
+            "issues": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "severity": { "type": "string", "enum": ["critical", "high", "medium", "low"] },
+                        "line": { "type": ["integer", "null"], "description": "1-based line number, if the issue is local" },
+                        "category": { "type": "string", "description": "e.g. bug, security, performance, style" },
+                        "description": { "type": "string" },
+                        "suggestion": { "type": "string" }
+                    },
+                    "required": ["severity", "description"]
+                }
+            },
+            "improvements": { "type": "array", "items": { "type": "string" } },
+            "positives": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": ["summary", "issues", "improvements", "positives"]
+    })
+}
+
+/// Compile a caller-supplied schema, rejecting ones the providers cannot
+/// enforce.
+pub fn compile(schema: &Value) -> Result<Validator> {
+    if schema.get("type").and_then(Value::as_str) != Some("object") {
+        bail!("Response schema must have \"type\": \"object\" at the root");
+    }
+    jsonschema::validator_for(schema).map_err(|e| anyhow::anyhow!("Invalid response schema: {}", e))
+}
+
+/// Parse a reply as JSON, tolerating a surrounding markdown code fence.
+fn parse(content: &str) -> Result<Value, String> {
+    let trimmed = content.trim();
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    serde_json::from_str(unfenced.trim()).map_err(|e| format!("Reply is not valid JSON: {}", e))
+}
+
+/// Validation errors of `value`, as `path: message` lines.
+fn validation_errors(validator: &Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            let path = if path.is_empty() { "/" } else { path.as_str() };
+            format!("{}: {}", path, e)
+        })
+        .collect()
+}
+
+/// Outcome of a structured completion.
+pub struct StructuredResponse {
+    pub value: Value,
+    pub response: CompletionResponse,
+    /// Requests made, including repair attempts
+    pub attempts: usize,
+}
+
+/// Complete `request` with a JSON reply validated against `schema`. Invalid
+/// replies are sent back to the model with the validation errors, up to
+/// `MAX_REPAIR_ATTEMPTS` times. Usage is summed over all attempts.
+pub async fn complete(
+    client: &ModelClient,
+    tool: &str,
+    mut request: CompletionRequest,
+    name: &str,
+    schema: Value,
+) -> Result<StructuredResponse> {
+    let validator = compile(&schema)?;
+    request.response_format = Some(ResponseFormat {
+        name: name.to_string(),
+        schema,
+    });
+
+    let mut usage: Option<UsageInfo> = None;
+    let mut errors = Vec::new();
+    for attempt in 1..=MAX_REPAIR_ATTEMPTS + 1 {
+        let mut response = client.complete(tool, request.clone()).await?;
+        if let Some(step_usage) = &response.usage {
+            usage.get_or_insert_with(UsageInfo::default).add(step_usage);
+        }
+
+        errors = match parse(&response.content) {
+            Ok(value) => {
+                let errors = validation_errors(&validator, &value);
+                if errors.is_empty() {
+                    response.usage = usage;
+                    return Ok(StructuredResponse {
+                        value,
+                        response,
+                        attempts: attempt,
+                    });
+                }
+                errors
+            }
+            Err(e) => vec![e],
+        };
+        tracing::debug!(
+            "{} reply failed schema validation (attempt {}): {:?}",
+            tool,
+            attempt,
+            errors
+        );
+
+        request
+            .messages
+            .push(Message::new("assistant", response.content));
+        request.messages.push(Message::new(
+            "user",
+            format!(
+                "Your reply does not match the required JSON schema:\n- {}\n\n\
+                 Reply again with only the corrected JSON document.",
+                errors.join("\n- ")
+            ),
+        ));
+    }
+
+    bail!(
+        "Model reply did not match the response schema after {} attempts: {}",
+        MAX_REPAIR_ATTEMPTS + 1,
+        errors.join("; ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_validate() {
+        let validator = compile(&review_schema()).unwrap();
+
+        let reply = "```json\n{\"summary\": \"ok\", \"issues\": [], \"improvements\": [], \"positives\": []}\n```";
+        let value = parse(reply).unwrap();
+        assert!(validation_errors(&validator, &value).is_empty());
+
+        let value = parse(r#"{"summary": "ok", "issues": [{"severity": "urgent"}]}"#).unwrap();
+        let errors = validation_errors(&validator, &value);
+        assert!(errors.iter().any(|e| e.starts_with("/issues/0")));
+        assert!(errors.iter().any(|e| e.contains("improvements")));
+
+        assert!(parse("Here is the review").is_err());
+        assert!(compile(&serde_json::json!({ "type": "array" })).is_err());
+    }
+}
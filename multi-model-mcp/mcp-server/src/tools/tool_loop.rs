@@ -0,0 +1,200 @@
+use super::context::{self, ConversationContext};
+use super::{local_map, ModelClient, ToolResponse};
+use crate::config::LocalMapConfig;
+use crate::providers::{
+    CompletionRequest, CompletionResponse, Message, StopReason, ToolCall, ToolDefinition, UsageInfo,
+};
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Upper bound on model round trips in one tool call, so a model that keeps
+/// calling tools cannot run up unbounded cost.
+pub const MAX_TOOL_STEPS: usize = 8;
+
+/// Largest tool result passed back to the model; longer output is cut.
+const MAX_TOOL_RESULT_CHARS: usize = 50_000;
+
+/// One tool invocation made by the model, as reported to the caller.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolTrace {
+    pub step: usize,
+    pub name: String,
+    pub arguments: serde_json::Value,
+    pub success: bool,
+}
+
+/// Read-only server tools offered to the model.
+pub fn definitions() -> Vec<ToolDefinition> {
+    vec![
+        ToolDefinition {
+            name: "local_map".to_string(),
+            description: "List files and directories under a path in the user's workspace"
+                .to_string(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Starting path relative to the workspace root (default: \".\")" },
+                    "depth": { "type": "integer", "minimum": 0, "maximum": 6, "description": "Maximum depth (default: 2)" },
+                    "include": { "type": "array", "items": { "type": "string" }, "description": "Only list files matching these globs" },
+                    "exclude": { "type": "array", "items": { "type": "string" }, "description": "Leave out entries matching these globs" },
+                    "cursor": { "type": "string", "description": "next_cursor of the previous result, for the next page" },
+                    "format": { "type": "string", "enum": ["json", "tree", "paths"], "description": "tree or paths give a shorter listing (default: json)" }
+                }
+            }),
+        },
+        ToolDefinition {
+            name: "get_context".to_string(),
+            description:
+                "Return the files, notes and metadata the user added to the conversation context"
+                    .to_string(),
+            input_schema: serde_json::json!({ "type": "object", "properties": {} }),
+        },
+    ]
+}
+
+/// Complete `request` while letting the model call the server's own tools,
+/// feeding results back until it produces a final answer or
+/// `MAX_TOOL_STEPS` round trips have been made. Usage is summed over all
+/// steps; each step is still recorded in the ledger on its own.
+pub async fn run(
+    client: &ModelClient,
+    tool: &str,
+    mut request: CompletionRequest,
+    context: Arc<RwLock<ConversationContext>>,
+    local_map: &LocalMapConfig,
+) -> Result<(CompletionResponse, Vec<ToolTrace>)> {
+    request.tools = definitions();
+    let mut trace = Vec::new();
+    let mut usage: Option<UsageInfo> = None;
+    let mut thinking = Vec::new();
+
+    for step in 1..=MAX_TOOL_STEPS {
+        let mut response = client.complete(tool, request.clone()).await?;
+        if let Some(step_usage) = &response.usage {
+            usage.get_or_insert_with(UsageInfo::default).add(step_usage);
+        }
+        thinking.extend(response.thinking.iter().cloned());
+        if response.stop_reason != StopReason::ToolUse
+            || response.tool_calls.is_empty()
+            || step == MAX_TOOL_STEPS
+        {
+            if response.stop_reason == StopReason::ToolUse {
+                tracing::warn!("{} stopped after {} tool steps", tool, MAX_TOOL_STEPS);
+            }
+            response.usage = usage;
+            response.thinking = thinking;
+            return Ok((response, trace));
+        }
+
+        request.messages.push(Message {
+            tool_calls: response.tool_calls.clone(),
+            thinking: response.thinking.clone(),
+            ..Message::new("assistant", response.content.clone())
+        });
+        for call in &response.tool_calls {
+            let result = call_tool(call, context.clone(), local_map).await;
+            trace.push(ToolTrace {
+                step,
+                name: call.name.clone(),
+                arguments: call.arguments.clone(),
+                success: result.success,
+            });
+            request.messages.push(Message {
+                tool_call_id: Some(call.id.clone()),
+                ..Message::new("tool", render_result(&result))
+            });
+        }
+    }
+
+    unreachable!("the last step always returns")
+}
+
+async fn call_tool(
+    call: &ToolCall,
+    context: Arc<RwLock<ConversationContext>>,
+    limits: &LocalMapConfig,
+) -> ToolResponse {
+    tracing::debug!("Model called {} with {}", call.name, call.arguments);
+    let result = match call.name.as_str() {
+        "local_map" => local_map::execute_for_model(call.arguments.clone(), limits).await,
+        "get_context" => context::get_context(context).await,
+        other => Err(anyhow::anyhow!("Unknown tool: {}", other)),
+    };
+    result.unwrap_or_else(|e| ToolResponse {
+        success: false,
+        result: serde_json::Value::Null,
+        error: Some(e.to_string()),
+    })
+}
+
+fn render_result(result: &ToolResponse) -> String {
+    let mut text = serde_json::to_string(result).unwrap_or_default();
+    if text.len() > MAX_TOOL_RESULT_CHARS {
+        let mut cut = MAX_TOOL_RESULT_CHARS;
+        while !text.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        text.truncate(cut);
+        text.push_str("... [truncated]");
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_call_tool_dispatch() {
+        let context = Arc::new(RwLock::new(ConversationContext::new()));
+        context.write().await.add_note("use tokio".to_string());
+
+        let call = |name: &str, arguments| ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments,
+        };
+        let limits = LocalMapConfig::default();
+        let result = call_tool(
+            &call("get_context", serde_json::json!({})),
+            context.clone(),
+            &limits,
+        )
+        .await;
+        assert!(result.success);
+        assert!(render_result(&result).contains("use tokio"));
+
+        let result = call_tool(
+            &call("delete_files", serde_json::json!({})),
+            context.clone(),
+            &limits,
+        )
+        .await;
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("Unknown tool: delete_files"));
+
+        // The model only lists the workspace, with the arguments it is offered
+        for arguments in [
+            serde_json::json!({ "path": "/" }),
+            serde_json::json!({ "path": ".." }),
+            serde_json::json!({ "path": ".", "details": ["git_status"] }),
+            serde_json::json!({ "path": ".", "follow_symlinks": true }),
+        ] {
+            let result = call_tool(&call("local_map", arguments), context.clone(), &limits).await;
+            assert!(!result.success);
+        }
+        let arguments = serde_json::json!({ "path": "src", "depth": 0, "format": "paths" });
+        let result = call_tool(&call("local_map", arguments), context.clone(), &limits).await;
+        assert!(result.success);
+
+        assert_eq!(
+            definitions()
+                .iter()
+                .map(|d| d.name.as_str())
+                .collect::<Vec<_>>(),
+            ["local_map", "get_context"]
+        );
+    }
+}
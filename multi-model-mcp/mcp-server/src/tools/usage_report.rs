@@ -0,0 +1,69 @@
+use super::ToolResponse;
+use crate::usage::{summarize, GroupBy, UsageLedger, UsageTotals};
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+struct UsageReportArgs {
+    #[serde(default = "default_group_by")]
+    group_by: Vec<GroupBy>,
+    /// Inclusive start date (YYYY-MM-DD, UTC)
+    from: Option<String>,
+    /// Inclusive end date (YYYY-MM-DD, UTC)
+    to: Option<String>,
+    provider: Option<String>,
+    model: Option<String>,
+    tool: Option<String>,
+}
+
+fn default_group_by() -> Vec<GroupBy> {
+    vec![GroupBy::Day]
+}
+
+fn parse_date(field: &str, value: Option<&str>) -> Result<Option<NaiveDate>> {
+    value
+        .map(|v| {
+            NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                .with_context(|| format!("'{}' must be a date in YYYY-MM-DD format", field))
+        })
+        .transpose()
+}
+
+pub async fn execute(args: serde_json::Value, ledger: Arc<UsageLedger>) -> Result<ToolResponse> {
+    let args: UsageReportArgs = serde_json::from_value(args)?;
+
+    let from = parse_date("from", args.from.as_deref())?;
+    let to = parse_date("to", args.to.as_deref())?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            bail!("'from' ({}) must not be after 'to' ({})", from, to);
+        }
+    }
+
+    let records: Vec<_> = ledger
+        .read(from, to)?
+        .into_iter()
+        .filter(|r| args.provider.as_ref().is_none_or(|p| &r.provider == p))
+        .filter(|r| args.model.as_ref().is_none_or(|m| &r.model == m))
+        .filter(|r| args.tool.as_ref().is_none_or(|t| &r.tool == t))
+        .collect();
+
+    let mut totals = UsageTotals::default();
+    for record in &records {
+        totals.add(record);
+    }
+
+    Ok(ToolResponse {
+        success: true,
+        result: serde_json::json!({
+            "from": from,
+            "to": to,
+            "group_by": args.group_by,
+            "groups": summarize(&records, &args.group_by),
+            "totals": totals,
+        }),
+        error: None,
+    })
+}
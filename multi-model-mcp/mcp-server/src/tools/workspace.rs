@@ -0,0 +1,142 @@
+use anyhow::{bail, Result};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The workspace root: the server's working directory, canonicalized.
+pub fn root() -> Result<PathBuf> {
+    let workspace_root = std::env::current_dir()?;
+    fs::canonicalize(&workspace_root)
+        .map_err(|e| anyhow::anyhow!("Failed to canonicalize workspace: {}", e))
+}
+
+/// Resolve a user-supplied path against the workspace root (the server's
+/// working directory) and canonicalize it. Relative paths must stay inside
+/// the workspace to prevent path traversal; absolute paths are allowed for
+/// testing and explicit use cases.
+pub fn resolve(path: &str) -> Result<PathBuf> {
+    let workspace_root = std::env::current_dir()?;
+    let workspace_canonical = root()?;
+
+    let requested = PathBuf::from(path);
+    let is_absolute = requested.is_absolute();
+    let requested = if is_absolute {
+        requested
+    } else {
+        workspace_root.join(&requested)
+    };
+
+    if !requested.exists() {
+        bail!("Path does not exist: {}", requested.display());
+    }
+
+    let canonical = fs::canonicalize(&requested)
+        .map_err(|e| anyhow::anyhow!("Failed to canonicalize path: {}", e))?;
+
+    if !is_absolute && !canonical.starts_with(&workspace_canonical) {
+        bail!(
+            "Access denied: path '{}' is outside workspace root '{}'",
+            canonical.display(),
+            workspace_canonical.display()
+        );
+    }
+
+    Ok(canonical)
+}
+
+/// Resolve `path` strictly inside the workspace `root`: absolute paths and
+/// `..` components are refused, and so is a path that leads out of the
+/// workspace through a symlink. For paths chosen by the model or read from
+/// shared files, which must never reach the rest of the host.
+pub fn resolve_within(root: &Path, path: &str) -> Result<PathBuf> {
+    let requested = root.join(relative(path)?);
+    if !requested.exists() {
+        bail!("Path does not exist: {}", path);
+    }
+    let canonical = fs::canonicalize(&requested)
+        .map_err(|e| anyhow::anyhow!("Failed to canonicalize path: {}", e))?;
+    if !canonical.starts_with(root) {
+        bail!(
+            "Access denied: path '{}' is outside workspace root '{}'",
+            path,
+            root.display()
+        );
+    }
+    Ok(canonical)
+}
+
+/// Like [`resolve_within`] for a file that may not exist yet: its directory
+/// must exist inside the workspace, and the file itself must not be a
+/// symlink.
+pub fn resolve_new_within(root: &Path, path: &str) -> Result<PathBuf> {
+    let relative = relative(path)?;
+    let Some(name) = relative.file_name() else {
+        bail!("'{}' does not name a file", path);
+    };
+    let parent = match relative.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+    let target = resolve_within(root, &parent)?.join(name);
+    if fs::symlink_metadata(&target).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+        bail!("Access denied: '{}' is a symlink", path);
+    }
+    Ok(target)
+}
+
+/// `path` as given relative to the workspace root, refusing absolute paths
+/// and `..` components.
+pub fn relative(path: &str) -> Result<&Path> {
+    let path = Path::new(path);
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => {
+                bail!("Access denied: '{}' must not contain '..'", path.display())
+            }
+            Component::RootDir | Component::Prefix(_) => bail!(
+                "Access denied: '{}' must be relative to the workspace root",
+                path.display()
+            ),
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_within_stays_in_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+
+        assert_eq!(
+            resolve_within(&root, "src/lib.rs").unwrap(),
+            root.join("src/lib.rs")
+        );
+        assert_eq!(resolve_within(&root, ".").unwrap(), root);
+        assert!(resolve_within(&root, "/etc/passwd").is_err());
+        assert!(resolve_within(&root, "src/../../x").is_err());
+        assert!(resolve_within(&root, "missing.rs").is_err());
+
+        assert_eq!(
+            resolve_new_within(&root, "src/new.json").unwrap(),
+            root.join("src/new.json")
+        );
+        assert!(resolve_new_within(&root, "../new.json").is_err());
+        assert!(resolve_new_within(&root, "/tmp/new.json").is_err());
+        assert!(resolve_new_within(&root, "missing/new.json").is_err());
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink("/etc", root.join("etc")).unwrap();
+            assert!(resolve_within(&root, "etc/passwd").is_err());
+            assert!(resolve_new_within(&root, "etc/new.json").is_err());
+            std::os::unix::fs::symlink("/etc/passwd", root.join("out.json")).unwrap();
+            assert!(resolve_new_within(&root, "out.json").is_err());
+        }
+    }
+}
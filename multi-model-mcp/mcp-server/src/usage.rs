@@ -0,0 +1,284 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// One completion as recorded in the usage ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    pub tool: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Part of `input_tokens` served from the provider's prompt cache.
+    #[serde(default)]
+    pub cache_read_tokens: u32,
+    /// Part of `input_tokens` written to the prompt cache.
+    #[serde(default)]
+    pub cache_write_tokens: u32,
+    /// Part of `output_tokens` spent on reasoning.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+    /// `None` when the model has no pricing in the registry.
+    pub cost_usd: Option<f64>,
+    /// Cost avoided through prompt caching (negative while cache writes
+    /// have not been paid back by reads).
+    #[serde(default)]
+    pub cache_savings_usd: Option<f64>,
+    pub latency_ms: u64,
+}
+
+/// Append-only JSONL ledger of completions, stored by default at
+/// `<data dir>/multi-model-mcp/usage.jsonl`.
+#[derive(Debug)]
+pub struct UsageLedger {
+    path: Option<PathBuf>,
+    write_lock: Mutex<()>,
+}
+
+impl UsageLedger {
+    pub fn open(path: Option<&Path>) -> Self {
+        let path = path.map(Path::to_path_buf).or_else(Self::default_path);
+        if path.is_none() {
+            tracing::warn!("No data directory available; usage will not be recorded");
+        }
+        Self {
+            path,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("multi-model-mcp").join("usage.jsonl"))
+    }
+
+    /// Append a record. Failures are logged rather than failing the tool call
+    /// that produced the completion.
+    pub fn record(&self, record: &UsageRecord) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let result = (|| -> Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            let mut line = serde_json::to_vec(record)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            tracing::warn!("Failed to write usage ledger {}: {}", path.display(), e);
+        }
+    }
+
+    /// Records whose UTC date falls within `[from, to]` (both optional).
+    pub fn read(&self, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Result<Vec<UsageRecord>> {
+        let Some(path) = &self.path else {
+            return Ok(Vec::new());
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open usage ledger {}", path.display()))
+            }
+        };
+
+        let mut records = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: UsageRecord = match serde_json::from_str(&line) {
+                Ok(record) => record,
+                Err(e) => {
+                    tracing::warn!("Skipping malformed usage ledger line {}: {}", index + 1, e);
+                    continue;
+                }
+            };
+            let day = record.timestamp.date_naive();
+            if from.is_some_and(|from| day < from) || to.is_some_and(|to| day > to) {
+                continue;
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+}
+
+/// Dimension a usage report can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    Day,
+    Provider,
+    Model,
+    Tool,
+}
+
+impl GroupBy {
+    fn key(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Provider => "provider",
+            Self::Model => "model",
+            Self::Tool => "tool",
+        }
+    }
+
+    fn value(&self, record: &UsageRecord) -> String {
+        match self {
+            Self::Day => record.timestamp.date_naive().to_string(),
+            Self::Provider => record.provider.clone(),
+            Self::Model => record.model.clone(),
+            Self::Tool => record.tool.clone(),
+        }
+    }
+}
+
+/// Aggregated usage for one group of records.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub total_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cost_usd: f64,
+    pub cache_savings_usd: f64,
+    /// Requests whose model had no pricing, so `cost_usd` undercounts them.
+    pub unpriced_requests: u64,
+    pub avg_latency_ms: u64,
+    #[serde(skip)]
+    latency_ms_sum: u64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.input_tokens += record.input_tokens as u64;
+        self.output_tokens += record.output_tokens as u64;
+        self.total_tokens = self.input_tokens + self.output_tokens;
+        self.cache_read_tokens += record.cache_read_tokens as u64;
+        self.cache_write_tokens += record.cache_write_tokens as u64;
+        self.reasoning_tokens += record.reasoning_tokens as u64;
+        self.cache_savings_usd += record.cache_savings_usd.unwrap_or(0.0);
+        match record.cost_usd {
+            Some(cost) => self.cost_usd += cost,
+            None => self.unpriced_requests += 1,
+        }
+        self.latency_ms_sum += record.latency_ms;
+        self.avg_latency_ms = self.latency_ms_sum / self.requests;
+    }
+}
+
+/// Group records by the given dimensions, ordered by group key.
+pub fn summarize(records: &[UsageRecord], group_by: &[GroupBy]) -> Vec<serde_json::Value> {
+    let mut groups: BTreeMap<Vec<String>, UsageTotals> = BTreeMap::new();
+    for record in records {
+        let key = group_by.iter().map(|g| g.value(record)).collect();
+        groups.entry(key).or_default().add(record);
+    }
+
+    groups
+        .into_iter()
+        .map(|(key, totals)| {
+            let mut value = serde_json::to_value(&totals).unwrap_or_default();
+            for (dimension, key) in group_by.iter().zip(key) {
+                value[dimension.key()] = serde_json::Value::String(key);
+            }
+            value
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(timestamp: &str, provider: &str, tool: &str, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            timestamp: timestamp.parse().unwrap(),
+            provider: provider.to_string(),
+            model: format!("{}-model", provider),
+            tool: tool.to_string(),
+            input_tokens: 100,
+            output_tokens: 50,
+            cache_read_tokens: 40,
+            cache_write_tokens: 0,
+            reasoning_tokens: 20,
+            cost_usd: cost,
+            cache_savings_usd: cost.map(|c| c / 10.0),
+            latency_ms: 200,
+        }
+    }
+
+    #[test]
+    fn test_ledger_roundtrip_with_date_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        let ledger = UsageLedger::open(Some(&temp_dir.path().join("usage.jsonl")));
+
+        ledger.record(&record(
+            "2025-03-01T10:00:00Z",
+            "openai",
+            "review_code",
+            Some(0.01),
+        ));
+        ledger.record(&record(
+            "2025-03-02T10:00:00Z",
+            "anthropic",
+            "generate_code",
+            None,
+        ));
+
+        assert_eq!(ledger.read(None, None).unwrap().len(), 2);
+        let day = NaiveDate::from_ymd_opt(2025, 3, 2);
+        let records = ledger.read(day, day).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].provider, "anthropic");
+    }
+
+    #[test]
+    fn test_summarize_groups() {
+        let records = vec![
+            record("2025-03-01T10:00:00Z", "openai", "review_code", Some(0.01)),
+            record(
+                "2025-03-01T11:00:00Z",
+                "openai",
+                "generate_code",
+                Some(0.02),
+            ),
+            record("2025-03-02T10:00:00Z", "anthropic", "generate_code", None),
+        ];
+
+        let groups = summarize(&records, &[GroupBy::Day, GroupBy::Provider]);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0]["day"], "2025-03-01");
+        assert_eq!(groups[0]["provider"], "openai");
+        assert_eq!(groups[0]["requests"], 2);
+        assert_eq!(groups[0]["total_tokens"], 300);
+        assert_eq!(groups[0]["cache_read_tokens"], 80);
+        assert_eq!(groups[0]["reasoning_tokens"], 40);
+        assert!((groups[0]["cache_savings_usd"].as_f64().unwrap() - 0.003).abs() < 1e-9);
+        assert!((groups[0]["cost_usd"].as_f64().unwrap() - 0.03).abs() < 1e-9);
+        assert_eq!(groups[1]["unpriced_requests"], 1);
+
+        let totals = summarize(&records, &[]);
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0]["requests"], 3);
+    }
+}
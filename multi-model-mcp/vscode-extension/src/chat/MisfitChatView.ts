@@ -88,12 +88,12 @@ export class MisfitChatView implements vscode.WebviewViewProvider {
             return;
         }
 
-        // Minimal plan: use generate_code for free text prompts.
+        // Minimal plan: continue the server-side chat with free text prompts.
         // You can extend this to route to other tools based on intent.
         const plan: ToolCallRequest[] = [
             {
-                name: "generate_code",
-                arguments: { prompt, model }
+                name: "chat",
+                arguments: { message: prompt, model }
             }
         ];
 
@@ -116,7 +116,7 @@ export class MisfitChatView implements vscode.WebviewViewProvider {
             // Build terse summaries
             const fileHints = await this.client.detectFileChanges(); // best effort
             summaryAccomplishments.push("Planned and executed 1 tool call");
-            summaryAccomplishments.push("Generated code from the prompt");
+            summaryAccomplishments.push("Replied in the ongoing chat");
 
             if (fileHints.created.length || fileHints.modified.length || fileHints.deleted.length) {
                 summaryAccomplishments.push(